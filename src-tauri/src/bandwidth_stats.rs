@@ -0,0 +1,114 @@
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri::AppHandle;
+use tokio::time::sleep;
+
+use crate::{extensions::AnyhowErrorToStringChain, utils};
+
+/// 将内存中的计数写入文件的间隔
+const FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// 还没写入文件的字节数
+static PENDING_BYTES: AtomicU64 = AtomicU64::new(0);
+/// 写入文件时需要先读再写，避免定时写入和退出时写入同时进行
+static FILE_LOCK: parking_lot::Mutex<()> = parking_lot::Mutex::new(());
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct DailyBandwidth {
+    /// 格式为`YYYY-MM-DD`的本地日期
+    pub date: String,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct BandwidthStats {
+    /// 最近几天每天的流量，从旧到新排列，没有流量的日期也会列出
+    pub daily: Vec<DailyBandwidth>,
+    /// 有记录以来的总流量
+    pub total_bytes: u64,
+}
+
+/// 记录下载的字节数，包括漫画图片和封面
+pub fn record(bytes: u64) {
+    PENDING_BYTES.fetch_add(bytes, Ordering::Relaxed);
+}
+
+/// 每天的流量保存在数据目录中的`流量统计.json`，键为日期，值为字节数
+fn stats_path(app: &AppHandle) -> anyhow::Result<PathBuf> {
+    Ok(utils::data_dir(app)?.join("流量统计.json"))
+}
+
+fn load(app: &AppHandle) -> anyhow::Result<BTreeMap<String, u64>> {
+    let path = stats_path(app)?;
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let stats_string = std::fs::read_to_string(&path).context(format!("读取文件`{path:?}`失败"))?;
+    let stats =
+        serde_json::from_str(&stats_string).context(format!("`{path:?}`不是合法的流量统计文件"))?;
+    Ok(stats)
+}
+
+/// 将内存中的计数累加到今天的记录中
+pub fn flush(app: &AppHandle) -> anyhow::Result<()> {
+    let _lock = FILE_LOCK.lock();
+    let pending_bytes = PENDING_BYTES.swap(0, Ordering::Relaxed);
+    if pending_bytes == 0 {
+        return Ok(());
+    }
+    let result = add_to_today(app, pending_bytes);
+    // 写入失败时把计数放回去，下次再试
+    if result.is_err() {
+        PENDING_BYTES.fetch_add(pending_bytes, Ordering::Relaxed);
+    }
+    result
+}
+
+fn add_to_today(app: &AppHandle, bytes: u64) -> anyhow::Result<()> {
+    let mut stats = load(app)?;
+    let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+    *stats.entry(today).or_default() += bytes;
+    let path = stats_path(app)?;
+    let stats_string = serde_json::to_string_pretty(&stats)?;
+    std::fs::write(&path, stats_string).context(format!("写入文件`{path:?}`失败"))?;
+    Ok(())
+}
+
+pub async fn flush_loop(app: AppHandle) {
+    loop {
+        sleep(FLUSH_INTERVAL).await;
+        if let Err(err) = flush(&app) {
+            let err_title = "保存流量统计失败";
+            let string_chain = err.to_string_chain();
+            tracing::error!(err_title, message = string_chain);
+        }
+    }
+}
+
+/// 获取最近`days`天(包括今天)的流量和总流量
+pub fn get_stats(app: &AppHandle, days: u32) -> anyhow::Result<BandwidthStats> {
+    flush(app).context("保存流量统计失败")?;
+    let stats = load(app)?;
+    let today = chrono::Local::now().date_naive();
+    let daily = (0..days)
+        .rev()
+        .filter_map(|days_ago| today.checked_sub_days(chrono::Days::new(u64::from(days_ago))))
+        .map(|date| {
+            let date = date.format("%Y-%m-%d").to_string();
+            let bytes = stats.get(&date).copied().unwrap_or_default();
+            DailyBandwidth { date, bytes }
+        })
+        .collect();
+    let total_bytes = stats.values().sum();
+    Ok(BandwidthStats { daily, total_bytes })
+}
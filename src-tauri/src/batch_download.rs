@@ -0,0 +1,205 @@
+use std::{
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri::{AppHandle, Manager};
+use tokio::{sync::Semaphore, task::JoinSet, time::sleep};
+
+use crate::{
+    config::Config, download_manager::DownloadManager, errors::RateLimitedError,
+    extensions::AnyhowErrorToStringChain, search_cache::SearchQuery, types::Comic, utils,
+    wnacg_client::WnacgClient,
+};
+
+/// 同时获取漫画信息的数量
+const RESOLVE_CONCURRENCY: usize = 3;
+/// 每获取一部漫画的信息后等待的时间，以免请求过于频繁
+const RESOLVE_INTERVAL: Duration = Duration::from_secs(1);
+/// `download_search_page`中连续这么多部漫画获取失败时停止，很可能是IP被限制了
+const MAX_CONSECUTIVE_FAILURES: u32 = 5;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchDownloadFailure {
+    pub comic_id: i64,
+    pub err_message: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchDownloadExclusion {
+    pub comic_id: i64,
+    /// 命中的`excluded_tags`中的标签
+    pub tag: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchDownloadResult {
+    /// 已加入下载队列的漫画id
+    pub enqueued_ids: Vec<i64>,
+    /// 已下载或已在下载队列中而跳过的漫画id
+    pub skipped_ids: Vec<i64>,
+    /// 带有`excluded_tags`中的标签而跳过的漫画
+    pub excluded: Vec<BatchDownloadExclusion>,
+    /// 获取漫画信息失败的漫画id和原因
+    pub failures: Vec<BatchDownloadFailure>,
+}
+
+enum ResolveOutcome {
+    Enqueued,
+    Skipped,
+    Excluded(String),
+    Failed(String),
+}
+
+/// `comic`的标签中第一个在`excluded_tags`中的标签，不区分大小写
+pub fn find_excluded_tag(comic: &Comic, excluded_tags: &[String]) -> Option<String> {
+    comic
+        .tags
+        .iter()
+        .find(|tag| {
+            excluded_tags
+                .iter()
+                .any(|excluded_tag| excluded_tag.to_lowercase() == tag.name.to_lowercase())
+        })
+        .map(|tag| tag.name.clone())
+}
+
+/// 从输入的文本中提取漫画id，逐一获取漫画信息并创建下载任务
+///
+/// 某部漫画获取失败不会中断其他漫画，失败的漫画记录在`failures`中
+pub async fn create_download_tasks_from_input(app: &AppHandle, input: &str) -> BatchDownloadResult {
+    let comic_ids = utils::extract_comic_ids(input);
+    resolve_all(app, &comic_ids, None).await
+}
+
+/// 获取搜索结果的第`page_num`页，为其中未下载的漫画获取漫画信息并创建下载任务
+///
+/// 被限流或连续`MAX_CONSECUTIVE_FAILURES`部漫画获取失败时停止，剩下的漫画记录在`failures`中
+pub async fn download_search_page(
+    app: &AppHandle,
+    query: SearchQuery,
+    page_num: i64,
+) -> anyhow::Result<BatchDownloadResult> {
+    let wnacg_client = app.state::<WnacgClient>().inner().clone();
+    let search_result = wnacg_client.fetch_search_result(&query, page_num).await?;
+    let (downloaded_comics, comics): (Vec<_>, Vec<_>) = search_result
+        .comics
+        .into_iter()
+        .partition(|comic| comic.is_downloaded);
+    let comic_ids = comics.iter().map(|comic| comic.id).collect::<Vec<_>>();
+
+    let mut result = resolve_all(app, &comic_ids, Some(MAX_CONSECUTIVE_FAILURES)).await;
+    result
+        .skipped_ids
+        .extend(downloaded_comics.iter().map(|comic| comic.id));
+    Ok(result)
+}
+
+/// 并发获取漫画信息并创建下载任务，结果按`comic_ids`中的顺序返回
+///
+/// `max_consecutive_failures`不为`None`时，连续失败达到这个次数或被限流后，不再获取剩下的漫画
+async fn resolve_all(
+    app: &AppHandle,
+    comic_ids: &[i64],
+    max_consecutive_failures: Option<u32>,
+) -> BatchDownloadResult {
+    let sem = Arc::new(Semaphore::new(RESOLVE_CONCURRENCY));
+    let consecutive_failures = Arc::new(AtomicU32::new(0));
+    let mut join_set = JoinSet::new();
+    for &comic_id in comic_ids {
+        let app = app.clone();
+        let sem = sem.clone();
+        let consecutive_failures = consecutive_failures.clone();
+        join_set.spawn(async move {
+            let outcome = match sem.acquire().await {
+                Ok(_permit) => {
+                    let failure_count = consecutive_failures.load(Ordering::Relaxed);
+                    if max_consecutive_failures.is_some_and(|max| failure_count >= max) {
+                        let err_message =
+                            "连续多部漫画获取失败或被限流，可能IP被限制，已停止获取剩下的漫画";
+                        return (comic_id, ResolveOutcome::Failed(err_message.to_string()));
+                    }
+                    let outcome = resolve_and_enqueue(&app, comic_id, &consecutive_failures).await;
+                    sleep(RESOLVE_INTERVAL).await;
+                    outcome
+                }
+                Err(err) => ResolveOutcome::Failed(err.to_string()),
+            };
+            (comic_id, outcome)
+        });
+    }
+
+    let mut result = BatchDownloadResult::default();
+    // 按输入中的顺序返回
+    let mut outcomes = join_set.join_all().await;
+    outcomes.sort_by_key(|(comic_id, _)| comic_ids.iter().position(|id| id == comic_id));
+    for (comic_id, outcome) in outcomes {
+        match outcome {
+            ResolveOutcome::Enqueued => result.enqueued_ids.push(comic_id),
+            ResolveOutcome::Skipped => result.skipped_ids.push(comic_id),
+            ResolveOutcome::Excluded(tag) => {
+                result
+                    .excluded
+                    .push(BatchDownloadExclusion { comic_id, tag });
+            }
+            ResolveOutcome::Failed(err_message) => result.failures.push(BatchDownloadFailure {
+                comic_id,
+                err_message,
+            }),
+        }
+    }
+    result
+}
+
+/// 获取失败时增加`consecutive_failures`，被限流时直接设为最大值，成功时清零
+async fn resolve_and_enqueue(
+    app: &AppHandle,
+    comic_id: i64,
+    consecutive_failures: &AtomicU32,
+) -> ResolveOutcome {
+    let download_manager = app.state::<DownloadManager>();
+    if download_manager.is_task_active(comic_id) {
+        return ResolveOutcome::Skipped;
+    }
+    let wnacg_client = app.state::<WnacgClient>().inner().clone();
+    let comic = match wnacg_client.get_comic(comic_id).await {
+        Ok(comic) => {
+            consecutive_failures.store(0, Ordering::Relaxed);
+            comic
+        }
+        Err(err) => {
+            if err.is::<RateLimitedError>() {
+                consecutive_failures.store(u32::MAX, Ordering::Relaxed);
+            } else {
+                let _ =
+                    consecutive_failures.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| {
+                        Some(n.saturating_add(1))
+                    });
+            }
+            let err_title = format!("获取漫画ID为`{comic_id}`的漫画失败");
+            let string_chain = err.to_string_chain();
+            tracing::error!(err_title, message = string_chain);
+            return ResolveOutcome::Failed(string_chain);
+        }
+    };
+    if comic.is_downloaded == Some(true) {
+        return ResolveOutcome::Skipped;
+    }
+    let excluded_tags = app.state::<RwLock<Config>>().read().excluded_tags.clone();
+    if let Some(tag) = find_excluded_tag(&comic, &excluded_tags) {
+        let comic_title = &comic.title;
+        tracing::info!(comic_id, comic_title, "漫画带有排除的标签`{tag}`，已跳过");
+        return ResolveOutcome::Excluded(tag);
+    }
+    download_manager.create_download_task(comic, None);
+    ResolveOutcome::Enqueued
+}
@@ -0,0 +1,56 @@
+use std::time::Duration;
+
+use chrono::{Local, NaiveDate};
+use parking_lot::RwLock;
+use tauri::{AppHandle, Manager};
+use tauri_specta::Event;
+use tokio::time::sleep;
+
+use crate::{
+    config::Config, cookie_store::CookieStore, errors::SessionExpiredError, events::CheckInEvent,
+    extensions::AnyhowErrorToStringChain, wnacg_client::WnacgClient,
+};
+
+/// 检查是否需要签到的间隔，跨过零点后最多晚这么久签到
+const CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// 开启`config.auto_check_in`且已登录时，启动后签到一次，之后每天签到一次
+///
+/// 失败时只记录日志，下次检查时再试
+pub async fn auto_check_in_loop(app: AppHandle) {
+    let mut last_check_in_date: Option<NaiveDate> = None;
+    loop {
+        let today = Local::now().date_naive();
+        let auto_check_in = app.state::<RwLock<Config>>().read().auto_check_in;
+        let should_check_in = auto_check_in
+            && last_check_in_date != Some(today)
+            && !CookieStore::get(&app).is_empty();
+        if should_check_in {
+            let wnacg_client = app.state::<WnacgClient>().inner().clone();
+            match wnacg_client.check_in().await {
+                Ok(check_in_result) => {
+                    last_check_in_date = Some(today);
+                    if check_in_result.already_checked_in {
+                        tracing::info!("今天已经签到过了");
+                    } else {
+                        tracing::info!("自动签到成功: {}", check_in_result.message);
+                    }
+                    let _ = CheckInEvent {
+                        already_checked_in: check_in_result.already_checked_in,
+                        points: check_in_result.points,
+                    }
+                    .emit(&app);
+                }
+                Err(err) if err.is::<SessionExpiredError>() => {
+                    tracing::warn!("cookie已过期，自动签到失败，重新登录后会再次尝试");
+                }
+                Err(err) => {
+                    let err_title = "自动签到失败";
+                    let string_chain = err.to_string_chain();
+                    tracing::warn!(err_title, message = string_chain);
+                }
+            }
+        }
+        sleep(CHECK_INTERVAL).await;
+    }
+}
@@ -1,23 +1,48 @@
-use anyhow::Context;
+use std::{collections::HashSet, path::PathBuf};
+
+use anyhow::{anyhow, Context};
 use parking_lot::RwLock;
-use tauri::{AppHandle, State};
+use tauri::{AppHandle, Manager, State};
 use tauri_plugin_opener::OpenerExt;
+use tauri_specta::Event;
 
 use crate::{
-    config::Config,
-    download_manager::DownloadManager,
-    errors::{CommandError, CommandResult},
-    export,
-    extensions::AnyhowErrorToStringChain,
-    logger,
-    types::{Comic, GetFavoriteResult, SearchResult, UserProfile},
+    bandwidth_stats::{self, BandwidthStats},
+    batch_download::{self, BatchDownloadResult},
+    config::{self, Config, ConfigApplyReport, InvalidConfigField},
+    cookie_store::{CookieStore, Credentials},
+    diagnostics,
+    download_manager::{DownloadManager, DownloadTaskInfo},
+    errors::{ComicNotFoundError, CommandError, CommandResult, ExportCancelledError},
+    events::{CheckInEvent, ComicDeletedEvent, DownloadDirChangedEvent, LogEvent},
+    export::{self, Archive},
+    library::{
+        self, ComicPage, DuplicateGroup, FavoritesDiff, LibraryDiskUsage, LibraryIndex,
+        LibraryIssue, LibraryMigrationState, LibrarySortBy, OrphanTempDir,
+    },
+    logger::{self, LogFile},
+    search_cache::SearchQuery,
+    subscription::{Subscription, Subscriptions},
+    types::{
+        AppVersion, CheckInResult, Comic, DownloadFormat, GetAllFavoritesResult, GetFavoriteResult,
+        ImgList, LogLevel, SearchResult, UserProfile,
+    },
+    utils,
     wnacg_client::WnacgClient,
 };
 
+#[allow(clippy::needless_pass_by_value)]
 #[tauri::command]
 #[specta::specta]
-pub fn greet(name: &str) -> String {
-    format!("Hello, {}! You've been greeted from Rust!", name)
+pub fn get_app_version(app: AppHandle) -> AppVersion {
+    let git_hash = Some(env!("GIT_HASH"))
+        .filter(|git_hash| !git_hash.is_empty())
+        .map(ToString::to_string);
+    AppVersion {
+        version: app.package_info().version.to_string(),
+        git_hash,
+        is_debug: cfg!(debug_assertions),
+    }
 }
 
 #[tauri::command(async)]
@@ -32,28 +57,158 @@ pub fn get_config(config: tauri::State<RwLock<Config>>) -> Config {
 #[tauri::command(async)]
 #[specta::specta]
 #[allow(clippy::needless_pass_by_value)]
-pub fn save_config(
+pub fn save_config(app: AppHandle, config: Config) -> CommandResult<ConfigApplyReport> {
+    apply_config(&app, config)
+}
+
+/// 修改日志级别并保存到配置中，立即生效
+#[tauri::command(async)]
+#[specta::specta]
+#[allow(clippy::needless_pass_by_value)]
+pub fn set_log_level(
+    app: AppHandle,
+    config: State<RwLock<Config>>,
+    level: LogLevel,
+) -> CommandResult<()> {
+    let mut new_config = config.read().clone();
+    new_config.log_level = level;
+    apply_config(&app, new_config)?;
+    tracing::debug!("修改日志级别为`{level:?}`成功");
+    Ok(())
+}
+
+/// 检查配置是否合法、网站是否可以访问，不会保存配置，也不会创建配置中的目录
+///
+/// 网站不可访问时返回的`InvalidConfigField`的`field`为`network`
+#[tauri::command(async)]
+#[specta::specta]
+pub async fn validate_config(config: Config) -> Vec<InvalidConfigField> {
+    let mut invalid_fields = config.check_without_side_effects();
+    if let Err(err) = WnacgClient::check_reachable(&config).await {
+        // 网站不可访问不一定是代理的问题，所以不归到`proxyUrl`下
+        invalid_fields.push(InvalidConfigField::new("network", format!("{err:#}")));
+    }
+    tracing::debug!("检查配置完成，共有`{}`个问题", invalid_fields.len());
+    invalid_fields
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+#[allow(clippy::needless_pass_by_value)]
+pub fn reset_config(
     app: AppHandle,
     config_state: State<RwLock<Config>>,
-    config: Config,
+    preserve_cookie: bool,
 ) -> CommandResult<()> {
-    let enable_file_logger = config.enable_file_logger;
-    let enable_file_logger_changed = config_state
+    let data_dir = utils::data_dir(&app).map_err(|err| CommandError::from("重置配置失败", err))?;
+    let mut config = Config::default(&data_dir);
+    {
+        // 保留下载目录，以免已下载的漫画全部变成未下载
+        let config_state = config_state.read();
+        config.download_dir = config_state.download_dir.clone();
+        if preserve_cookie {
+            config.cookie = config_state.cookie.clone();
+            config.secure_cookie_storage = config_state.secure_cookie_storage;
+        }
+    }
+    apply_config(&app, config)?;
+    tracing::debug!("重置配置成功");
+    Ok(())
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+#[allow(clippy::needless_pass_by_value)]
+pub fn backup_config(app: AppHandle, config: State<RwLock<Config>>) -> CommandResult<PathBuf> {
+    let backup_path = config
         .read()
-        .enable_file_logger
-        .ne(&enable_file_logger);
+        .backup(&app)
+        .map_err(|err| CommandError::from("备份配置失败", err))?;
+    tracing::debug!("备份配置到`{backup_path:?}`成功");
+    Ok(backup_path)
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+#[allow(clippy::needless_pass_by_value)]
+pub fn restore_config(app: AppHandle, path: PathBuf) -> CommandResult<()> {
+    let config = Config::from_backup(&path)
+        .map_err(|err| CommandError::from(&format!("从`{path:?}`恢复配置失败"), err))?;
+    apply_config(&app, config)?;
+    tracing::debug!("从`{path:?}`恢复配置成功");
+    Ok(())
+}
+
+/// 检查并应用新的配置，保存到文件后通知各个组件与前端，返回各个修改过的配置项的生效情况
+fn apply_config(app: &AppHandle, mut config: Config) -> CommandResult<ConfigApplyReport> {
+    let config_state = app.state::<RwLock<Config>>();
+    // `is_portable`是只读的，不能由前端修改
+    config.is_portable = utils::is_portable();
+    config.clamp();
+    if let Err(err) = config.validate() {
+        let err_title = match err.downcast_ref::<InvalidConfigField>() {
+            Some(invalid_field) => format!("保存配置失败，配置项`{}`不合法", invalid_field.field),
+            None => "保存配置失败".to_string(),
+        };
+        return Err(CommandError::from(&err_title, err));
+    }
+
+    let enable_file_logger = config.enable_file_logger;
+    let (enable_file_logger_changed, file_logger_settings_changed) = {
+        let old_config = config_state.read();
+        (
+            old_config.enable_file_logger != enable_file_logger,
+            old_config.log_max_size_mb != config.log_max_size_mb
+                || old_config.log_retention_count != config.log_retention_count
+                || old_config.log_format != config.log_format,
+        )
+    };
+
+    let log_level = config.log_level;
+    let log_level_changed = config_state.read().log_level != log_level;
+
+    let old_download_dir = config_state.read().download_dir.clone();
+    let new_download_dir = config.download_dir.clone();
+
+    if config_state.read().secure_cookie_storage && !config.secure_cookie_storage {
+        // 关闭了安全存储，把钥匙串中的cookie取回内存中的配置，否则删除钥匙串中的cookie后就相当于退出登录了
+        config.take_cookie_from_keyring(app);
+    }
+
+    let changed_fields = config::changed_fields(&config_state.read(), &config);
 
     {
         // 包裹在大括号中，以便自动释放写锁
         let mut config_state = config_state.write();
         *config_state = config;
         config_state
-            .save(&app)
+            .save(app)
             .map_err(|err| CommandError::from("保存配置失败", err))?;
         tracing::debug!("保存配置成功");
     }
 
-    if enable_file_logger_changed {
+    let download_dir_changed = old_download_dir != new_download_dir;
+    if download_dir_changed {
+        // 记录最初的下载目录，等待用户决定是否迁移已下载的漫画
+        let migration_state = app.state::<RwLock<LibraryMigrationState>>();
+        let mut migration_state = migration_state.write();
+        let pending_dir = migration_state
+            .old_download_dir
+            .get_or_insert(old_download_dir.clone());
+        if *pending_dir == new_download_dir {
+            // 改回了最初的下载目录，不需要迁移
+            migration_state.old_download_dir = None;
+        }
+        drop(migration_state);
+
+        let _ = DownloadDirChangedEvent {
+            old_download_dir,
+            new_download_dir,
+        }
+        .emit(app);
+    }
+
+    if enable_file_logger_changed || (enable_file_logger && file_logger_settings_changed) {
         if enable_file_logger {
             logger::reload_file_logger()
                 .map_err(|err| CommandError::from("重新加载文件日志失败", err))?;
@@ -63,7 +218,16 @@ pub fn save_config(
         }
     }
 
-    Ok(())
+    if log_level_changed {
+        logger::set_level(log_level).map_err(|err| CommandError::from("修改日志级别失败", err))?;
+    }
+
+    let report = config::on_config_fields_changed(app, changed_fields);
+    if download_dir_changed {
+        library::rebuild_index(app);
+    }
+
+    Ok(report)
 }
 
 #[tauri::command(async)]
@@ -81,6 +245,28 @@ pub async fn login(
     Ok(cookie)
 }
 
+/// 将账号密码保存到钥匙串中，cookie过期时会用它自动重新登录
+#[tauri::command(async)]
+#[specta::specta]
+#[allow(clippy::needless_pass_by_value)]
+pub fn save_credentials(app: AppHandle, username: String, password: String) -> CommandResult<()> {
+    let credentials = Credentials { username, password };
+    CookieStore::save_credentials(&app, &credentials)
+        .map_err(|err| CommandError::from("保存账号密码失败", err))?;
+    tracing::debug!("保存账号密码成功");
+    Ok(())
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+#[allow(clippy::needless_pass_by_value)]
+pub fn delete_credentials(app: AppHandle) -> CommandResult<()> {
+    CookieStore::delete_credentials(&app)
+        .map_err(|err| CommandError::from("删除账号密码失败", err))?;
+    tracing::debug!("删除账号密码成功");
+    Ok(())
+}
+
 #[tauri::command(async)]
 #[specta::specta]
 pub async fn get_user_profile(wnacg_client: State<'_, WnacgClient>) -> CommandResult<UserProfile> {
@@ -92,6 +278,25 @@ pub async fn get_user_profile(wnacg_client: State<'_, WnacgClient>) -> CommandRe
     Ok(user_profile)
 }
 
+#[tauri::command(async)]
+#[specta::specta]
+pub async fn check_in(
+    app: AppHandle,
+    wnacg_client: State<'_, WnacgClient>,
+) -> CommandResult<CheckInResult> {
+    let check_in_result = wnacg_client
+        .check_in()
+        .await
+        .map_err(|err| CommandError::from("签到失败", err))?;
+    let _ = CheckInEvent {
+        already_checked_in: check_in_result.already_checked_in,
+        points: check_in_result.points,
+    }
+    .emit(&app);
+    tracing::debug!("签到成功");
+    Ok(check_in_result)
+}
+
 #[tauri::command(async)]
 #[specta::specta]
 pub async fn search_by_keyword(
@@ -122,17 +327,46 @@ pub async fn search_by_tag(
     Ok(search_result)
 }
 
+/// 从粘贴的任意文本中提取所有漫画id，已去重
+#[tauri::command]
+#[specta::specta]
+#[allow(clippy::needless_pass_by_value)]
+pub fn extract_comic_ids(text: String) -> Vec<i64> {
+    utils::extract_comic_ids(&text)
+}
+
 #[tauri::command(async)]
 #[specta::specta]
 pub async fn get_comic(wnacg_client: State<'_, WnacgClient>, id: i64) -> CommandResult<Comic> {
-    let comic = wnacg_client
-        .get_comic(id)
-        .await
-        .map_err(|err| CommandError::from("获取漫画失败", err))?;
+    let comic = wnacg_client.get_comic(id).await.map_err(|err| {
+        if err.is::<ComicNotFoundError>() {
+            CommandError::from("漫画不存在或已被删除", err)
+        } else {
+            CommandError::from("获取漫画失败", err)
+        }
+    })?;
     tracing::debug!("获取漫画成功");
     Ok(comic)
 }
 
+/// 只获取漫画的图片列表，不获取详情页，用于已有元数据时刷新可能失效的图片链接
+#[tauri::command(async)]
+#[specta::specta]
+pub async fn get_comic_images(
+    wnacg_client: State<'_, WnacgClient>,
+    id: i64,
+) -> CommandResult<ImgList> {
+    let img_list = wnacg_client.get_img_list(id).await.map_err(|err| {
+        if err.is::<ComicNotFoundError>() {
+            CommandError::from("漫画不存在或已被删除", err)
+        } else {
+            CommandError::from("获取漫画的图片列表失败", err)
+        }
+    })?;
+    tracing::debug!("获取漫画的图片列表成功");
+    Ok(img_list)
+}
+
 #[tauri::command(async)]
 #[specta::specta]
 pub async fn get_favorite(
@@ -148,14 +382,111 @@ pub async fn get_favorite(
     Ok(get_favorite_result)
 }
 
+/// 获取书架的所有页，已按漫画id去重，`warnings`中是结果可能与网站不一致的说明
+#[tauri::command(async)]
+#[specta::specta]
+pub async fn get_all_favorites(
+    wnacg_client: State<'_, WnacgClient>,
+    shelf_id: i64,
+) -> CommandResult<GetAllFavoritesResult> {
+    let get_all_favorites_result = wnacg_client
+        .get_all_favorites(shelf_id)
+        .await
+        .map_err(|err| CommandError::from("获取书架的所有漫画失败", err))?;
+    tracing::debug!("获取书架的所有漫画成功");
+    Ok(get_all_favorites_result)
+}
+
+/// 获取书架的所有页，与已下载的漫画对比，找出还没下载的和已不在书架中的漫画
+#[tauri::command(async)]
+#[specta::specta]
+pub async fn diff_favorites_against_library(
+    app: AppHandle,
+    wnacg_client: State<'_, WnacgClient>,
+    shelf_id: i64,
+) -> CommandResult<FavoritesDiff> {
+    let favorites = wnacg_client
+        .get_all_favorites(shelf_id)
+        .await
+        .map_err(|err| CommandError::from("获取书架的所有漫画失败", err))?;
+    let library_index = app.state::<RwLock<LibraryIndex>>();
+    // 启动时的后台重建还没完成，直接在这里重建
+    if !library_index.read().is_ready() {
+        library::rebuild_index(&app);
+    }
+    let downloaded_comics = library_index.read().comics();
+    let favorites_diff = library::diff_favorites(favorites, downloaded_comics);
+    tracing::debug!(
+        "对比书架与已下载的漫画完成，未下载{}部，已下载{}部，不在书架中{}部",
+        favorites_diff.missing.len(),
+        favorites_diff.downloaded.len(),
+        favorites_diff.orphaned_local.len(),
+    );
+    Ok(favorites_diff)
+}
+
+/// 获取搜索结果的某一页，为其中所有未下载的漫画创建下载任务
+#[tauri::command(async)]
+#[specta::specta]
+pub async fn download_search_page(
+    app: AppHandle,
+    query: SearchQuery,
+    page_num: i64,
+) -> CommandResult<BatchDownloadResult> {
+    let result = batch_download::download_search_page(&app, query, page_num)
+        .await
+        .map_err(|err| CommandError::from(&format!("获取搜索结果第{page_num}页失败"), err))?;
+    tracing::debug!(
+        "下载整页搜索结果完成，加入队列{}部，跳过{}部，失败{}部",
+        result.enqueued_ids.len(),
+        result.skipped_ids.len(),
+        result.failures.len()
+    );
+    Ok(result)
+}
+
+/// `format_override`不为`None`时，这部漫画使用它而不是`config.download_format`
 #[allow(clippy::needless_pass_by_value)]
 #[tauri::command(async)]
 #[specta::specta]
-pub fn create_download_task(download_manager: State<DownloadManager>, comic: Comic) {
-    download_manager.create_download_task(comic);
+pub fn create_download_task(
+    download_manager: State<DownloadManager>,
+    comic: Comic,
+    format_override: Option<DownloadFormat>,
+) {
+    download_manager.create_download_task(comic, format_override);
     tracing::debug!("下载任务创建成功");
 }
 
+/// 从粘贴的文本中提取漫画id，获取漫画信息后批量创建下载任务，已下载的漫画会被跳过
+#[tauri::command(async)]
+#[specta::specta]
+pub async fn create_download_tasks_from_input(
+    app: AppHandle,
+    input: String,
+) -> BatchDownloadResult {
+    let result = batch_download::create_download_tasks_from_input(&app, &input).await;
+    tracing::debug!(
+        "批量创建下载任务完成，加入队列{}部，跳过{}部，失败{}部",
+        result.enqueued_ids.len(),
+        result.skipped_ids.len(),
+        result.failures.len()
+    );
+    result
+}
+
+#[allow(clippy::needless_pass_by_value)]
+#[tauri::command(async)]
+#[specta::specta]
+pub fn get_download_task(
+    download_manager: State<DownloadManager>,
+    comic_id: i64,
+) -> Option<DownloadTaskInfo> {
+    let download_task = download_manager.get_download_task(comic_id);
+    tracing::debug!("获取漫画ID为`{comic_id}`的下载任务成功");
+    download_task
+}
+
 #[allow(clippy::needless_pass_by_value)]
 #[tauri::command(async)]
 #[specta::specta]
@@ -184,6 +515,49 @@ pub fn resume_download_task(
     Ok(())
 }
 
+#[allow(clippy::needless_pass_by_value)]
+#[tauri::command(async)]
+#[specta::specta]
+pub fn pause_all_download_tasks(download_manager: State<DownloadManager>) -> Vec<i64> {
+    let paused_comic_ids = download_manager.pause_all_download_tasks();
+    tracing::debug!("暂停所有下载任务成功，共{}个", paused_comic_ids.len());
+    paused_comic_ids
+}
+
+#[allow(clippy::needless_pass_by_value)]
+#[tauri::command(async)]
+#[specta::specta]
+pub fn resume_all_download_tasks(download_manager: State<DownloadManager>) -> Vec<i64> {
+    let resumed_comic_ids = download_manager.resume_all_download_tasks();
+    tracing::debug!("恢复所有下载任务成功，共{}个", resumed_comic_ids.len());
+    resumed_comic_ids
+}
+
+/// 前端在供电状态变化时调用，是否暂停由后端根据`pause_on_battery`决定
+#[allow(clippy::needless_pass_by_value)]
+#[tauri::command(async)]
+#[specta::specta]
+pub fn set_power_state(download_manager: State<DownloadManager>, on_battery: bool) {
+    download_manager.on_power_state_changed(on_battery);
+}
+
+/// 让下载任务立即开始，不受`download_schedule`限制
+#[allow(clippy::needless_pass_by_value)]
+#[tauri::command(async)]
+#[specta::specta]
+pub fn force_start_download_task(
+    download_manager: State<DownloadManager>,
+    comic_id: i64,
+) -> CommandResult<()> {
+    download_manager
+        .force_start_download_task(comic_id)
+        .map_err(|err| {
+            CommandError::from(&format!("立即开始漫画ID为`{comic_id}`的下载任务"), err)
+        })?;
+    tracing::debug!("立即开始漫画ID为`{comic_id}`的下载任务成功");
+    Ok(())
+}
+
 #[allow(clippy::needless_pass_by_value)]
 #[tauri::command(async)]
 #[specta::specta]
@@ -198,77 +572,241 @@ pub fn cancel_download_task(
     Ok(())
 }
 
+#[allow(clippy::needless_pass_by_value)]
 #[tauri::command(async)]
 #[specta::specta]
+pub fn clear_finished_download_tasks(download_manager: State<DownloadManager>) {
+    download_manager.clear_finished_download_tasks();
+    tracing::debug!("清除已结束的下载任务成功");
+}
+
+/// 只清除已完成的下载任务，保留失败和已取消的任务，返回被清除的任务数量
 #[allow(clippy::needless_pass_by_value)]
-pub fn get_downloaded_comics(
+#[tauri::command(async)]
+#[specta::specta]
+pub fn clear_completed_download_tasks(download_manager: State<DownloadManager>) -> usize {
+    let cleared_count = download_manager.clear_completed_download_tasks();
+    tracing::debug!("清除`{cleared_count}`个已完成的下载任务成功");
+    cleared_count
+}
+
+#[allow(clippy::needless_pass_by_value)]
+#[tauri::command(async)]
+#[specta::specta]
+pub fn remove_download_task(
+    download_manager: State<DownloadManager>,
+    comic_id: i64,
+) -> CommandResult<()> {
+    download_manager
+        .remove_download_task(comic_id)
+        .map_err(|err| CommandError::from(&format!("移除漫画ID为`{comic_id}`的下载任务"), err))?;
+    tracing::debug!("移除漫画ID为`{comic_id}`的下载任务成功");
+    Ok(())
+}
+
+/// `sort_by`为`None`时按下载时间排序，最新下载的排在最前面
+#[tauri::command(async)]
+#[specta::specta]
+pub async fn get_downloaded_comics(
     app: AppHandle,
-    config: State<RwLock<Config>>,
+    library_index: State<'_, RwLock<LibraryIndex>>,
+    sort_by: Option<LibrarySortBy>,
 ) -> CommandResult<Vec<Comic>> {
-    let download_dir = config.read().download_dir.clone();
-    // 遍历下载目录，获取所有元数据文件的路径和修改时间
-    let mut metadata_path_with_modify_time = std::fs::read_dir(&download_dir)
-        .map_err(|err| {
-            let err_title = format!("获取已下载的漫画失败，读取下载目录 {download_dir:?} 失败");
-            CommandError::from(&err_title, err)
-        })?
-        .filter_map(Result::ok)
-        .filter_map(|entry| {
-            if entry.file_name().to_string_lossy().starts_with(".下载中-") {
-                return None;
-            }
-            let metadata_path = entry.path().join("元数据.json");
-            if !metadata_path.exists() {
-                return None;
-            }
-            let modify_time = metadata_path.metadata().ok()?.modified().ok()?;
-            Some((metadata_path, modify_time))
-        })
-        .collect::<Vec<_>>();
-    // 按照文件修改时间排序，最新的排在最前面
-    metadata_path_with_modify_time.sort_by(|(_, a), (_, b)| b.cmp(a));
-    // 从元数据文件中读取Comic
-    let downloaded_comics = metadata_path_with_modify_time
-        .iter()
-        .filter_map(|(metadata_path, _)| {
-            match Comic::from_metadata(&app, metadata_path).map_err(anyhow::Error::from) {
-                Ok(comic) => Some(comic),
-                Err(err) => {
-                    let err_title = format!("读取元数据文件`{metadata_path:?}`失败");
-                    let string_chain = err.to_string_chain();
-                    tracing::error!(err_title, message = string_chain);
-                    None
-                }
-            }
-        })
-        .collect::<Vec<_>>();
-
+    let sort_by = sort_by.unwrap_or(LibrarySortBy::DownloadTime);
+    // 启动时的后台重建还没完成，直接在这里重建
+    if !library_index.read().is_ready() {
+        // 重建时需要遍历漫画目录，放到专门的线程中执行
+        let app = app.clone();
+        tauri::async_runtime::spawn_blocking(move || library::rebuild_index(&app))
+            .await
+            .map_err(|err| CommandError::from("获取已下载的漫画失败", err))?;
+    }
+    let downloaded_comics = library_index.read().sorted_comics(sort_by);
     tracing::debug!("获取已下载的漫画成功");
     Ok(downloaded_comics)
 }
 
+/// 重新扫描下载目录，用于发现在应用外添加或修改的漫画
 #[tauri::command(async)]
 #[specta::specta]
 #[allow(clippy::needless_pass_by_value)]
-pub fn export_pdf(app: AppHandle, comic: Comic) -> CommandResult<()> {
+pub fn refresh_library(app: AppHandle, library_index: State<RwLock<LibraryIndex>>) -> Vec<Comic> {
+    library::rebuild_index(&app);
+    let downloaded_comics = library_index.read().comics();
+    tracing::debug!("刷新已下载的漫画成功");
+    downloaded_comics
+}
+
+/// 进度通过`ExportAllEvent`发送
+#[tauri::command(async)]
+#[specta::specta]
+pub async fn export_all_downloaded(app: AppHandle, format: Archive) -> CommandResult<()> {
+    let extension = format.extension();
+    export::export_all(&app, format)
+        .await
+        .map_err(|err| CommandError::from(&format!("批量导出{extension}失败"), err))?;
+    tracing::debug!("批量导出{extension}完成");
+    Ok(())
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn cancel_export_all_downloaded() {
+    export::cancel_export_all();
+    tracing::debug!("取消批量导出");
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+pub async fn get_library_disk_usage(app: AppHandle) -> CommandResult<LibraryDiskUsage> {
+    let (download_dir, export_dir) = {
+        let config = app.state::<RwLock<Config>>();
+        let config = config.read();
+        (config.download_dir.clone(), config.export_dir.clone())
+    };
+    let comic_ids_by_title = app
+        .state::<RwLock<LibraryIndex>>()
+        .read()
+        .comic_ids_by_title();
+    // 遍历所有文件是耗时的同步操作，放到专门的线程中执行
+    let disk_usage = tauri::async_runtime::spawn_blocking(move || {
+        library::disk_usage(&download_dir, &export_dir, &comic_ids_by_title)
+    })
+    .await
+    .map_err(anyhow::Error::from)
+    .and_then(|result| result)
+    .map_err(|err| CommandError::from("获取漫画库占用的磁盘空间失败", err))?;
+    tracing::debug!("获取漫画库占用的磁盘空间成功");
+    Ok(disk_usage)
+}
+
+/// 将本地已有的图片目录导入漫画库，提供`source_id`时从网站获取完整的元数据
+#[tauri::command(async)]
+#[specta::specta]
+pub async fn import_local_comic(
+    app: AppHandle,
+    wnacg_client: State<'_, WnacgClient>,
+    path: PathBuf,
+    title: Option<String>,
+    source_id: Option<i64>,
+) -> CommandResult<Comic> {
+    let err_title = format!("导入`{path:?}`失败");
+    let comic = match source_id {
+        Some(source_id) => Some(
+            wnacg_client
+                .get_comic(source_id)
+                .await
+                .map_err(|err| CommandError::from(&err_title, err))?,
+        ),
+        None => None,
+    };
+    let download_dir = app.state::<RwLock<Config>>().read().download_dir.clone();
+    let local_comic_id = app
+        .state::<RwLock<LibraryIndex>>()
+        .read()
+        .next_local_comic_id();
+    // 复制图片是耗时的同步操作，放到专门的线程中执行
+    let comic = tauri::async_runtime::spawn_blocking({
+        let download_dir = download_dir.clone();
+        move || {
+            library::import_local_comic(
+                &download_dir,
+                &path,
+                comic,
+                title.as_deref(),
+                local_comic_id,
+            )
+        }
+    })
+    .await
+    .map_err(anyhow::Error::from)
+    .and_then(|result| result)
+    .map_err(|err| CommandError::from(&err_title, err))?;
+    library::upsert_index(&app, &download_dir.join(&comic.title));
+    tracing::debug!("导入漫画`{}`成功", comic.title);
+    Ok(comic)
+}
+
+/// 找出可能重复的漫画，用户确认后可以用`delete_downloaded_comic`删除
+#[tauri::command(async)]
+#[specta::specta]
+pub async fn find_duplicate_comics(app: AppHandle) -> CommandResult<Vec<DuplicateGroup>> {
+    let download_dir = app.state::<RwLock<Config>>().read().download_dir.clone();
+    let comics = app.state::<RwLock<LibraryIndex>>().read().comics();
+    // 读取图片和遍历文件是耗时的同步操作，放到专门的线程中执行
+    let duplicate_groups = tauri::async_runtime::spawn_blocking(move || {
+        library::find_duplicates(&download_dir, &comics)
+    })
+    .await
+    .map_err(|err| CommandError::from("查找重复的漫画失败", err))?;
+    tracing::debug!("查找重复的漫画成功");
+    Ok(duplicate_groups)
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+pub async fn export_pdf(app: AppHandle, comic: Comic) -> CommandResult<()> {
     let title = comic.title.clone();
-    export::pdf(&app, &comic)
-        .map_err(|err| CommandError::from(&format!("漫画`{title}`导出pdf失败"), err))?;
-    tracing::debug!("漫画`{title}`导出pdf成功");
+    let cover_data = export::get_cover_page(&app, &comic).await;
+    // 导出是耗时的同步操作，放到专门的线程中执行
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        export::pdf(&app, &comic, cover_data.as_deref())
+    })
+    .await
+    .map_err(anyhow::Error::from)
+    .and_then(|result| result);
+    match result {
+        Ok(()) => tracing::debug!("漫画`{title}`导出pdf成功"),
+        // 取消不是错误，前端通过`ExportPdfEvent::Cancelled`得知
+        Err(err) if err.is::<ExportCancelledError>() => {
+            tracing::debug!("漫画`{title}`导出pdf已取消");
+        }
+        Err(err) => {
+            return Err(CommandError::from(
+                &format!("漫画`{title}`导出pdf失败"),
+                err,
+            ))
+        }
+    }
     Ok(())
 }
 
+/// 取消正在进行的pdf导出，`uuid`为`ExportPdfEvent::Start`中的uuid，返回是否找到了该导出
 #[tauri::command(async)]
 #[specta::specta]
 #[allow(clippy::needless_pass_by_value)]
-pub fn export_cbz(app: AppHandle, comic: Comic) -> CommandResult<()> {
+pub fn cancel_export(uuid: String) -> bool {
+    let found = export::cancel_export(&uuid);
+    tracing::debug!("取消导出`{uuid}`，是否找到: {found}");
+    found
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+pub async fn export_cbz(app: AppHandle, comic: Comic) -> CommandResult<()> {
     let title = comic.title.clone();
-    export::cbz(&app, comic)
+    let cover_data = export::get_cover_page(&app, &comic).await;
+    // 导出是耗时的同步操作，放到专门的线程中执行
+    tauri::async_runtime::spawn_blocking(move || export::cbz(&app, comic, cover_data.as_deref()))
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(|result| result)
         .map_err(|err| CommandError::from(&format!("漫画`{title}`导出cbz失败"), err))?;
     tracing::debug!("漫画`{title}`导出cbz成功");
     Ok(())
 }
 
+/// 获取最近`days`天(包括今天)每天的流量和总流量
+#[allow(clippy::needless_pass_by_value)]
+#[tauri::command(async)]
+#[specta::specta]
+pub fn get_bandwidth_stats(app: AppHandle, days: u32) -> CommandResult<BandwidthStats> {
+    let bandwidth_stats = bandwidth_stats::get_stats(&app, days)
+        .map_err(|err| CommandError::from("获取流量统计失败", err))?;
+    tracing::debug!("获取流量统计成功");
+    Ok(bandwidth_stats)
+}
+
 #[allow(clippy::needless_pass_by_value)]
 #[tauri::command(async)]
 #[specta::specta]
@@ -287,6 +825,77 @@ pub fn get_logs_dir_size(app: AppHandle) -> CommandResult<u64> {
     Ok(logs_dir_size)
 }
 
+/// 从日志文件中查询最近的`limit`条级别不低于`level`的日志，从新到旧排列
+#[allow(clippy::needless_pass_by_value)]
+#[tauri::command(async)]
+#[specta::specta]
+pub fn query_logs(
+    app: AppHandle,
+    level: Option<LogLevel>,
+    limit: usize,
+) -> CommandResult<Vec<LogEvent>> {
+    let log_events = logger::query_logs(&app, level, limit)
+        .map_err(|err| CommandError::from("查询日志失败", err))?;
+    tracing::debug!("查询日志成功");
+    Ok(log_events)
+}
+
+/// 前端挂载日志面板时调用一次，补齐挂载监听之前的日志，之后依靠`LogEvent`
+#[tauri::command(async)]
+#[specta::specta]
+pub fn get_buffered_logs() -> Vec<LogEvent> {
+    logger::get_buffered_logs()
+}
+
+#[allow(clippy::needless_pass_by_value)]
+#[tauri::command(async)]
+#[specta::specta]
+pub fn open_log_dir(app: AppHandle) -> CommandResult<()> {
+    let logs_dir = logger::logs_dir(&app)
+        .context("获取日志目录失败")
+        .map_err(|err| CommandError::from("打开日志目录失败", err))?;
+    // 没有启用文件日志时日志目录可能不存在
+    std::fs::create_dir_all(&logs_dir)
+        .context(format!("创建日志目录`{logs_dir:?}`失败"))
+        .map_err(|err| CommandError::from("打开日志目录失败", err))?;
+    app.opener()
+        .open_path(logs_dir.to_string_lossy(), None::<&str>)
+        .context(format!("在文件管理器中打开`{logs_dir:?}`失败"))
+        .map_err(|err| CommandError::from("打开日志目录失败", err))?;
+    tracing::debug!("打开日志目录成功");
+    Ok(())
+}
+
+#[allow(clippy::needless_pass_by_value)]
+#[tauri::command(async)]
+#[specta::specta]
+pub fn export_diagnostic_bundle(app: AppHandle) -> CommandResult<PathBuf> {
+    let bundle_path = diagnostics::export_bundle(&app)
+        .map_err(|err| CommandError::from("导出诊断信息失败", err))?;
+    tracing::debug!("导出诊断信息到`{bundle_path:?}`成功");
+    Ok(bundle_path)
+}
+
+/// 删除所有旧日志文件，并让正在写入的日志切换到新文件
+#[allow(clippy::needless_pass_by_value)]
+#[tauri::command(async)]
+#[specta::specta]
+pub fn clear_logs(app: AppHandle) -> CommandResult<()> {
+    logger::clear_logs(&app).map_err(|err| CommandError::from("清空日志失败", err))?;
+    tracing::debug!("清空日志成功");
+    Ok(())
+}
+
+#[allow(clippy::needless_pass_by_value)]
+#[tauri::command(async)]
+#[specta::specta]
+pub fn get_log_files(app: AppHandle) -> CommandResult<Vec<LogFile>> {
+    let log_files = logger::get_log_files(&app)
+        .map_err(|err| CommandError::from("获取日志文件列表失败", err))?;
+    tracing::debug!("获取日志文件列表成功");
+    Ok(log_files)
+}
+
 #[allow(clippy::needless_pass_by_value)]
 #[tauri::command(async)]
 #[specta::specta]
@@ -299,6 +908,25 @@ pub fn show_path_in_file_manager(app: AppHandle, path: &str) -> CommandResult<()
     Ok(())
 }
 
+/// 获取漫画前`count`页的缩略图(jpeg)，用于下载前预览，已有图片列表时传入`img_list`可以少发一次请求
+///
+/// 返回缩略图文件的路径，前端用`convertFileSrc`转换后通过asset协议显示
+#[tauri::command(async)]
+#[specta::specta]
+pub async fn get_comic_preview(
+    wnacg_client: State<'_, WnacgClient>,
+    comic_id: i64,
+    img_list: Option<ImgList>,
+    count: u8,
+) -> CommandResult<Vec<PathBuf>> {
+    let preview_paths = wnacg_client
+        .get_comic_preview(comic_id, img_list, count)
+        .await
+        .map_err(|err| CommandError::from("获取漫画的预览失败", err))?;
+    tracing::debug!("获取漫画的预览成功");
+    Ok(preview_paths)
+}
+
 #[allow(clippy::needless_pass_by_value)]
 #[tauri::command(async)]
 #[specta::specta]
@@ -312,3 +940,343 @@ pub async fn get_cover_data(
         .map_err(|err| CommandError::from("获取封面失败", err))?;
     Ok(cover_data.to_vec())
 }
+
+#[allow(clippy::needless_pass_by_value)]
+#[tauri::command(async)]
+#[specta::specta]
+pub fn migrate_library(
+    app: AppHandle,
+    config: State<RwLock<Config>>,
+    migration_state: State<RwLock<LibraryMigrationState>>,
+    move_files: bool,
+) -> CommandResult<()> {
+    let old_download_dir = migration_state.write().old_download_dir.take();
+    let Some(old_download_dir) = old_download_dir else {
+        tracing::debug!("没有需要迁移的漫画");
+        return Ok(());
+    };
+    // 不移动文件，只需要前端重新获取已下载的漫画
+    if !move_files {
+        tracing::debug!("跳过迁移`{old_download_dir:?}`中的漫画");
+        return Ok(());
+    }
+
+    let new_download_dir = config.read().download_dir.clone();
+    if let Err(err) = library::migrate(&app, &old_download_dir, &new_download_dir) {
+        // 部分漫画可能已经移动过了，需要重建索引，同时保留待迁移的目录，以便用户重试
+        library::rebuild_index(&app);
+        migration_state.write().old_download_dir = Some(old_download_dir);
+        return Err(CommandError::from("迁移已下载的漫画失败", err));
+    }
+    library::rebuild_index(&app);
+    tracing::debug!("将`{old_download_dir:?}`中的漫画迁移到`{new_download_dir:?}`成功");
+    Ok(())
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+pub async fn refresh_comic_metadata(
+    app: AppHandle,
+    config: State<'_, RwLock<Config>>,
+    wnacg_client: State<'_, WnacgClient>,
+    comic_id: i64,
+) -> CommandResult<Comic> {
+    let comic = wnacg_client
+        .get_comic(comic_id)
+        .await
+        .map_err(|err| CommandError::from("更新漫画元数据失败，获取漫画失败", err))?;
+    let download_dir = config.read().download_dir.clone();
+    let comic = library::refresh_metadata(&download_dir, comic)
+        .map_err(|err| CommandError::from("更新漫画元数据失败", err))?;
+    library::upsert_index(&app, &download_dir.join(&comic.title));
+    tracing::debug!("更新漫画ID为`{comic_id}`的元数据成功");
+    Ok(comic)
+}
+
+#[allow(clippy::needless_pass_by_value)]
+#[tauri::command(async)]
+#[specta::specta]
+pub fn delete_downloaded_comic(
+    app: AppHandle,
+    config: State<RwLock<Config>>,
+    download_manager: State<DownloadManager>,
+    comic_id: i64,
+) -> CommandResult<()> {
+    let err_title = format!("删除漫画ID为`{comic_id}`的已下载漫画失败");
+    if download_manager.is_task_active(comic_id) {
+        let err = anyhow!("该漫画正在下载，请先取消下载任务");
+        return Err(CommandError::from(&err_title, err));
+    }
+    if export::is_exporting(comic_id) {
+        let err = anyhow!("该漫画正在导出，请等待导出完成");
+        return Err(CommandError::from(&err_title, err));
+    }
+
+    let (download_dir, delete_to_trash) = {
+        let config = config.read();
+        (config.download_dir.clone(), config.delete_to_trash)
+    };
+    let comic_dir = library::find_comic_dir(&download_dir, comic_id)
+        .and_then(|comic_dir| comic_dir.context("没有找到该漫画的下载目录"))
+        .map_err(|err| CommandError::from(&err_title, err))?;
+    library::delete_comic_dir(&comic_dir, delete_to_trash)
+        .map_err(|err| CommandError::from(&err_title, err))?;
+    // 已结束的下载任务也一并移除，没有下载任务时会返回错误，直接忽略
+    let _ = download_manager.remove_download_task(comic_id);
+    app.state::<RwLock<LibraryIndex>>().write().remove(comic_id);
+
+    let _ = ComicDeletedEvent { comic_id }.emit(&app);
+    tracing::debug!("删除漫画ID为`{comic_id}`的已下载漫画成功");
+    Ok(())
+}
+
+#[allow(clippy::needless_pass_by_value)]
+#[tauri::command(async)]
+#[specta::specta]
+pub fn show_comic_in_file_manager(
+    app: AppHandle,
+    config: State<RwLock<Config>>,
+    comic_id: i64,
+) -> CommandResult<()> {
+    let download_dir = config.read().download_dir.clone();
+    let comic_dir = library::find_comic_dir(&download_dir, comic_id)
+        .and_then(|comic_dir| comic_dir.context("尚未下载该漫画"))
+        .map_err(|err| CommandError::from("在文件管理器中打开漫画目录失败", err))?;
+    app.opener()
+        .reveal_item_in_dir(&comic_dir)
+        .context(format!("在文件管理器中打开`{comic_dir:?}`失败"))
+        .map_err(|err| CommandError::from("在文件管理器中打开漫画目录失败", err))?;
+    tracing::debug!("在文件管理器中打开漫画目录成功");
+    Ok(())
+}
+
+#[allow(clippy::needless_pass_by_value)]
+#[tauri::command(async)]
+#[specta::specta]
+pub fn show_export_in_file_manager(
+    app: AppHandle,
+    config: State<RwLock<Config>>,
+    comic_id: i64,
+    format: Archive,
+) -> CommandResult<()> {
+    let download_dir = config.read().download_dir.clone();
+    let (_, comic) = library::find_comic(&download_dir, comic_id)
+        .and_then(|comic| comic.context("尚未下载该漫画"))
+        .map_err(|err| CommandError::from("在文件管理器中打开导出文件失败", err))?;
+    let export_path = export::get_export_path(&app, &comic, format);
+    if !export_path.exists() {
+        let extension = format.extension();
+        let err = anyhow!("尚未导出为{extension}: `{export_path:?}`不存在");
+        return Err(CommandError::from("在文件管理器中打开导出文件失败", err));
+    }
+    app.opener()
+        .reveal_item_in_dir(&export_path)
+        .context(format!("在文件管理器中打开`{export_path:?}`失败"))
+        .map_err(|err| CommandError::from("在文件管理器中打开导出文件失败", err))?;
+    tracing::debug!("在文件管理器中打开导出文件成功");
+    Ok(())
+}
+
+#[allow(clippy::needless_pass_by_value)]
+#[tauri::command(async)]
+#[specta::specta]
+pub fn get_comic_pages(
+    config: State<RwLock<Config>>,
+    comic_id: i64,
+) -> CommandResult<Vec<ComicPage>> {
+    let download_dir = config.read().download_dir.clone();
+    let comic_dir = library::find_comic_dir(&download_dir, comic_id)
+        .and_then(|comic_dir| comic_dir.context("尚未下载该漫画"))
+        .map_err(|err| CommandError::from("获取漫画页面失败", err))?;
+    let pages = library::get_pages(&comic_dir)
+        .map_err(|err| CommandError::from("获取漫画页面失败", err))?;
+    tracing::debug!("获取漫画ID为`{comic_id}`的页面成功");
+    Ok(pages)
+}
+
+#[allow(clippy::needless_pass_by_value)]
+#[tauri::command(async)]
+#[specta::specta]
+pub fn search_library(
+    library_index: State<RwLock<LibraryIndex>>,
+    query: String,
+    tag: Option<String>,
+    category: Option<String>,
+) -> Vec<Comic> {
+    let comics = library_index
+        .read()
+        .search(&query, tag.as_deref(), category.as_deref());
+    tracing::debug!(
+        "在已下载的漫画中搜索`{query}`成功，共`{}`个结果",
+        comics.len()
+    );
+    comics
+}
+
+#[allow(clippy::needless_pass_by_value)]
+#[tauri::command(async)]
+#[specta::specta]
+pub fn list_orphan_temp_dirs(
+    config: State<RwLock<Config>>,
+    download_manager: State<DownloadManager>,
+) -> CommandResult<Vec<OrphanTempDir>> {
+    let download_dir = config.read().download_dir.clone();
+    let active_titles = download_manager.active_comic_titles();
+    let orphan_temp_dirs = library::list_orphan_temp_dirs(
+        &download_dir,
+        |comic_id| download_manager.is_task_active(comic_id),
+        &active_titles,
+    )
+    .map_err(|err| CommandError::from("获取残留的临时下载目录失败", err))?;
+    tracing::debug!("获取残留的临时下载目录成功");
+    Ok(orphan_temp_dirs)
+}
+
+#[allow(clippy::needless_pass_by_value)]
+#[tauri::command(async)]
+#[specta::specta]
+pub fn clean_orphan_temp_dirs(
+    config: State<RwLock<Config>>,
+    download_manager: State<DownloadManager>,
+    names: Vec<String>,
+) -> CommandResult<()> {
+    let err_title = "清理残留的临时下载目录失败";
+    let (download_dir, delete_to_trash) = {
+        let config = config.read();
+        (config.download_dir.clone(), config.delete_to_trash)
+    };
+    // 重新获取一遍，确保只删除此刻仍然没有对应下载任务的临时下载目录
+    let active_titles = download_manager.active_comic_titles();
+    let orphan_names = library::list_orphan_temp_dirs(
+        &download_dir,
+        |comic_id| download_manager.is_task_active(comic_id),
+        &active_titles,
+    )
+    .map_err(|err| CommandError::from(err_title, err))?
+    .into_iter()
+    .map(|orphan_temp_dir| orphan_temp_dir.name)
+    .collect::<HashSet<_>>();
+
+    for name in names {
+        if !orphan_names.contains(&name) {
+            let err = anyhow!("`{name}`不是残留的临时下载目录，或者它的下载任务正在进行");
+            return Err(CommandError::from(err_title, err));
+        }
+        let temp_dir = download_dir.join(&name);
+        library::delete_comic_dir(&temp_dir, delete_to_trash)
+            .map_err(|err| CommandError::from(err_title, err))?;
+        tracing::debug!("清理残留的临时下载目录`{temp_dir:?}`成功");
+    }
+    Ok(())
+}
+
+#[allow(clippy::needless_pass_by_value)]
+#[tauri::command(async)]
+#[specta::specta]
+pub fn verify_library(
+    app: AppHandle,
+    config: State<RwLock<Config>>,
+) -> CommandResult<Vec<LibraryIssue>> {
+    let download_dir = config.read().download_dir.clone();
+    let issues = library::verify(&app, &download_dir)
+        .map_err(|err| CommandError::from("检查已下载的漫画失败", err))?;
+    tracing::debug!("检查已下载的漫画完成，共发现`{}`个问题", issues.len());
+    Ok(issues)
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn cancel_verify_library() {
+    library::cancel_verify();
+    tracing::debug!("取消检查已下载的漫画");
+}
+
+#[allow(clippy::needless_pass_by_value)]
+#[tauri::command(async)]
+#[specta::specta]
+pub fn rename_downloaded_comic(
+    app: AppHandle,
+    config: State<RwLock<Config>>,
+    download_manager: State<DownloadManager>,
+    comic_id: i64,
+    new_title: String,
+) -> CommandResult<Comic> {
+    let err_title = format!("重命名漫画ID为`{comic_id}`的已下载漫画失败");
+    if download_manager.is_task_active(comic_id) {
+        let err = anyhow!("该漫画正在下载，请先取消下载任务");
+        return Err(CommandError::from(&err_title, err));
+    }
+    if export::is_exporting(comic_id) {
+        let err = anyhow!("该漫画正在导出，请等待导出完成");
+        return Err(CommandError::from(&err_title, err));
+    }
+
+    let download_dir = config.read().download_dir.clone();
+    let comic = library::rename_comic(&download_dir, comic_id, &new_title)
+        .map_err(|err| CommandError::from(&err_title, err))?;
+    library::upsert_index(&app, &download_dir.join(&comic.title));
+    tracing::debug!(
+        "将漫画ID为`{comic_id}`的已下载漫画重命名为`{}`成功",
+        comic.title
+    );
+    Ok(comic)
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+#[allow(clippy::needless_pass_by_value)]
+pub fn list_subscriptions(subscriptions: State<RwLock<Subscriptions>>) -> Vec<Subscription> {
+    subscriptions.read().list()
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+#[allow(clippy::needless_pass_by_value)]
+pub fn add_subscription(
+    app: AppHandle,
+    subscriptions: State<RwLock<Subscriptions>>,
+    tag: String,
+    auto_download: bool,
+) -> CommandResult<()> {
+    let err_title = format!("订阅标签`{tag}`失败");
+    subscriptions
+        .write()
+        .add(&app, tag.clone(), auto_download)
+        .map_err(|err| CommandError::from(&err_title, err))?;
+    tracing::debug!("订阅标签`{tag}`成功");
+    Ok(())
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+#[allow(clippy::needless_pass_by_value)]
+pub fn remove_subscription(
+    app: AppHandle,
+    subscriptions: State<RwLock<Subscriptions>>,
+    tag: String,
+) -> CommandResult<()> {
+    subscriptions
+        .write()
+        .remove(&app, &tag)
+        .map_err(|err| CommandError::from(&format!("取消订阅标签`{tag}`失败"), err))?;
+    tracing::debug!("取消订阅标签`{tag}`成功");
+    Ok(())
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+#[allow(clippy::needless_pass_by_value)]
+pub fn get_comic_page_dimensions(
+    config: State<RwLock<Config>>,
+    comic: Comic,
+) -> CommandResult<Vec<Option<(u32, u32)>>> {
+    let err_title = format!("获取漫画`{}`的图片尺寸失败", comic.title);
+    let download_dir = config.read().download_dir.clone();
+    let comic_dir = library::find_comic_dir(&download_dir, comic.id)
+        .and_then(|comic_dir| comic_dir.context("尚未下载该漫画"))
+        .map_err(|err| CommandError::from(&err_title, err))?;
+    let dimensions = library::get_page_dimensions(&comic_dir, comic.img_list.len())
+        .map_err(|err| CommandError::from(&err_title, err))?;
+    tracing::debug!("获取漫画`{}`的图片尺寸成功", comic.title);
+    Ok(dimensions)
+}
@@ -0,0 +1,123 @@
+use anyhow::Context;
+use keyring::Entry;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use crate::{
+    config::{self, Config},
+    extensions::AnyhowErrorToStringChain,
+};
+
+/// 开启`secure_cookie_storage`后，配置文件中的cookie会被替换为这个占位符
+pub const COOKIE_PLACEHOLDER: &str = "<stored-in-keyring>";
+
+const KEYRING_USER: &str = "cookie";
+const CREDENTIALS_KEYRING_USER: &str = "credentials";
+
+/// 用于cookie过期后自动重新登录的账号密码
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+/// 负责cookie的读取与保存
+///
+/// 开启`secure_cookie_storage`后，cookie保存在系统的钥匙串中，配置文件中只保留占位符
+pub struct CookieStore;
+
+impl CookieStore {
+    /// 获取当前的cookie，如果配置中是占位符，则从钥匙串中读取
+    pub fn get(app: &AppHandle) -> String {
+        let cookie = app.state::<RwLock<Config>>().read().cookie.clone();
+        if cookie != COOKIE_PLACEHOLDER {
+            return cookie;
+        }
+        match Self::load_from_keyring(app) {
+            Ok(cookie) => cookie,
+            Err(err) => {
+                let err_title = "从钥匙串读取cookie失败，将视为未登录";
+                let string_chain = err.to_string_chain();
+                tracing::warn!(err_title, message = string_chain);
+                String::new()
+            }
+        }
+    }
+
+    /// 将cookie保存到钥匙串中
+    pub fn save_to_keyring(app: &AppHandle, cookie: &str) -> anyhow::Result<()> {
+        Self::entry(app)?
+            .set_password(cookie)
+            .context("将cookie保存到钥匙串失败")?;
+        Ok(())
+    }
+
+    /// 删除钥匙串中的cookie
+    pub fn delete_from_keyring(app: &AppHandle) -> anyhow::Result<()> {
+        match Self::entry(app)?.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(err) => Err(err).context("删除钥匙串中的cookie失败"),
+        }
+    }
+
+    /// 从钥匙串读取cookie
+    pub fn load_from_keyring(app: &AppHandle) -> anyhow::Result<String> {
+        let cookie = Self::entry(app)?
+            .get_password()
+            .context("从钥匙串读取cookie失败")?;
+        Ok(cookie)
+    }
+
+    /// 将账号密码保存到钥匙串中，密码不会以明文写入配置文件
+    pub fn save_credentials(app: &AppHandle, credentials: &Credentials) -> anyhow::Result<()> {
+        let credentials_json = serde_json::to_string(credentials)?;
+        Self::credentials_entry(app)?
+            .set_password(&credentials_json)
+            .context("将账号密码保存到钥匙串失败")?;
+        Ok(())
+    }
+
+    /// 从钥匙串读取账号密码，没有保存过时返回`None`
+    pub fn load_credentials(app: &AppHandle) -> anyhow::Result<Option<Credentials>> {
+        let credentials_json = match Self::credentials_entry(app)?.get_password() {
+            Ok(credentials_json) => credentials_json,
+            Err(keyring::Error::NoEntry) => return Ok(None),
+            Err(err) => return Err(err).context("从钥匙串读取账号密码失败"),
+        };
+        let credentials =
+            serde_json::from_str(&credentials_json).context("钥匙串中保存的账号密码格式不正确")?;
+        Ok(Some(credentials))
+    }
+
+    /// 删除钥匙串中的账号密码
+    pub fn delete_credentials(app: &AppHandle) -> anyhow::Result<()> {
+        match Self::credentials_entry(app)?.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(err) => Err(err).context("删除钥匙串中的账号密码失败"),
+        }
+    }
+
+    /// 更新cookie并保存配置，用于自动重新登录后
+    pub fn save(app: &AppHandle, cookie: String) -> anyhow::Result<()> {
+        {
+            let config = app.state::<RwLock<Config>>();
+            let mut config = config.write();
+            config.cookie = cookie;
+            config.save(app)?;
+        }
+        config::on_config_changed(app);
+        Ok(())
+    }
+
+    fn entry(app: &AppHandle) -> anyhow::Result<Entry> {
+        // 用app的identifier作为service，避免与其他app冲突
+        let service = &app.config().identifier;
+        Entry::new(service, KEYRING_USER).context("创建钥匙串条目失败")
+    }
+
+    fn credentials_entry(app: &AppHandle) -> anyhow::Result<Entry> {
+        let service = &app.config().identifier;
+        Entry::new(service, CREDENTIALS_KEYRING_USER).context("创建钥匙串条目失败")
+    }
+}
@@ -0,0 +1,105 @@
+use std::{path::PathBuf, time::SystemTime};
+
+use anyhow::Context;
+use bytes::Bytes;
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Manager};
+
+use crate::{extensions::AnyhowErrorToStringChain, utils};
+
+/// 封面缓存的总大小上限，超过后从最久没有使用的封面开始删除
+const MAX_CACHE_SIZE: u64 = 200 * 1024 * 1024;
+
+/// 封面缓存目录，位于数据目录中的`封面缓存`
+fn cache_dir(app: &AppHandle) -> anyhow::Result<PathBuf> {
+    let cache_dir = utils::data_dir(app)?.join("封面缓存");
+    std::fs::create_dir_all(&cache_dir).context(format!("创建目录`{cache_dir:?}`失败"))?;
+    Ok(cache_dir)
+}
+
+/// 缓存文件名为封面url的SHA-256
+fn cache_path(app: &AppHandle, cover_url: &str) -> anyhow::Result<PathBuf> {
+    let hash = Sha256::digest(cover_url.as_bytes());
+    Ok(cache_dir(app)?.join(format!("{hash:x}")))
+}
+
+/// 从缓存中读取封面，读取成功时更新文件的修改时间，作为最近使用的时间
+pub fn get(app: &AppHandle, cover_url: &str) -> Option<Bytes> {
+    let cache_path = cache_path(app, cover_url).ok()?;
+    let cover_data = std::fs::read(&cache_path).ok()?;
+    if let Ok(file) = std::fs::File::options().write(true).open(&cache_path) {
+        let _ = file.set_modified(SystemTime::now());
+    }
+    Some(Bytes::from(cover_data))
+}
+
+/// 缓存中有`cover_url`时返回缓存文件的路径，并更新文件的修改时间，作为最近使用的时间
+pub fn get_path(app: &AppHandle, cover_url: &str) -> Option<PathBuf> {
+    let cache_path = cache_path(app, cover_url).ok()?;
+    let file = std::fs::File::options()
+        .write(true)
+        .open(&cache_path)
+        .ok()?;
+    let _ = file.set_modified(SystemTime::now());
+    Some(cache_path)
+}
+
+/// 将封面写入缓存，写入后如果缓存总大小超过上限，则删除最久没有使用的封面
+pub fn put(app: &AppHandle, cover_url: &str, cover_data: &[u8]) -> anyhow::Result<()> {
+    put_and_get_path(app, cover_url, cover_data)?;
+    Ok(())
+}
+
+/// 与`put`相同，但返回缓存文件的路径
+pub fn put_and_get_path(
+    app: &AppHandle,
+    cover_url: &str,
+    cover_data: &[u8],
+) -> anyhow::Result<PathBuf> {
+    let cache_path = cache_path(app, cover_url)?;
+    std::fs::write(&cache_path, cover_data).context(format!("写入文件`{cache_path:?}`失败"))?;
+    evict(app)?;
+    Ok(cache_path)
+}
+
+/// 允许前端通过asset协议访问缓存目录中的文件，用于显示缓存的缩略图
+pub fn allow_asset_access(app: &AppHandle) {
+    let result = cache_dir(app).and_then(|cache_dir| {
+        app.asset_protocol_scope()
+            .allow_directory(&cache_dir, false)
+            .map_err(anyhow::Error::from)
+    });
+    if let Err(err) = result {
+        let err_title = "允许访问封面缓存目录失败";
+        let string_chain = err.to_string_chain();
+        tracing::error!(err_title, message = string_chain);
+    }
+}
+
+fn evict(app: &AppHandle) -> anyhow::Result<()> {
+    let cache_dir = cache_dir(app)?;
+    let mut entries = std::fs::read_dir(&cache_dir)
+        .context(format!("读取目录`{cache_dir:?}`失败"))?
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            let modify_time = metadata.modified().ok()?;
+            Some((entry.path(), metadata.len(), modify_time))
+        })
+        .collect::<Vec<_>>();
+
+    let mut total_size = entries.iter().map(|(_, size, _)| size).sum::<u64>();
+    if total_size <= MAX_CACHE_SIZE {
+        return Ok(());
+    }
+    // 最久没有使用的排在最前面
+    entries.sort_by_key(|(_, _, modify_time)| *modify_time);
+    for (path, size, _) in entries {
+        if total_size <= MAX_CACHE_SIZE {
+            break;
+        }
+        std::fs::remove_file(&path).context(format!("删除文件`{path:?}`失败"))?;
+        total_size -= size;
+    }
+    Ok(())
+}
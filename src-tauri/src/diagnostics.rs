@@ -0,0 +1,122 @@
+use std::{
+    fs::File,
+    io::{BufReader, Write},
+    path::PathBuf,
+};
+
+use anyhow::Context;
+use parking_lot::RwLock;
+use serde_json::json;
+use tauri::{AppHandle, Manager};
+use zip::{write::SimpleFileOptions, ZipWriter};
+
+use crate::{config::Config, download_manager::DownloadManager, logger};
+
+/// 替换敏感信息时使用的文本
+const REDACTED: &str = "<已隐藏>";
+
+/// 将日志、脱敏后的配置、版本与系统信息、下载任务打包为导出目录中的zip，返回zip的路径
+///
+/// cookie和代理地址中的用户名密码不会被写入
+pub fn export_bundle(app: &AppHandle) -> anyhow::Result<PathBuf> {
+    let config = app.state::<RwLock<Config>>().read().clone();
+    let export_dir = config.export_dir.clone();
+    std::fs::create_dir_all(&export_dir).context(format!("创建目录`{export_dir:?}`失败"))?;
+
+    let timestamp = chrono::Local::now().format("%Y%m%d-%H%M%S");
+    let zip_path = export_dir.join(format!("诊断信息-{timestamp}.zip"));
+    let zip_file = File::create(&zip_path).context(format!("创建文件`{zip_path:?}`失败"))?;
+    let mut zip_writer = ZipWriter::new(zip_file);
+
+    let config_json = serde_json::to_string_pretty(&redact_config(config))?;
+    write_entry(&mut zip_writer, "config.json", config_json.as_bytes())?;
+
+    let package_info = app.package_info();
+    let system_json = serde_json::to_string_pretty(&json!({
+        "appName": package_info.name,
+        "appVersion": package_info.version.to_string(),
+        "tauriVersion": tauri::VERSION,
+        "os": std::env::consts::OS,
+        "osFamily": std::env::consts::FAMILY,
+        "arch": std::env::consts::ARCH,
+    }))?;
+    write_entry(&mut zip_writer, "system.json", system_json.as_bytes())?;
+
+    let download_tasks: Vec<_> = app
+        .state::<DownloadManager>()
+        .download_task_infos()
+        .into_iter()
+        .map(|(comic_id, comic_title, info)| {
+            json!({
+                "comicId": comic_id,
+                "comicTitle": comic_title,
+                "state": info.state,
+                "downloadedImgCount": info.downloaded_img_count,
+                "totalImgCount": info.total_img_count,
+            })
+        })
+        .collect();
+    let download_tasks_json = serde_json::to_string_pretty(&download_tasks)?;
+    write_entry(
+        &mut zip_writer,
+        "download_tasks.json",
+        download_tasks_json.as_bytes(),
+    )?;
+
+    // 日志文件可能很大，边读边写，不一次性读入内存
+    let logs_dir = logger::logs_dir(app).context("获取日志目录失败")?;
+    let log_files = logger::get_log_files(app).context("获取日志文件列表失败")?;
+    for log_file in log_files {
+        let log_path = logs_dir.join(&log_file.filename);
+        let file = match File::open(&log_path) {
+            Ok(file) => file,
+            // 日志文件可能刚好被滚动删除，跳过即可
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(err) => {
+                return Err(err).context(format!("打开日志文件`{log_path:?}`失败"));
+            }
+        };
+        let entry_name = format!("logs/{}", log_file.filename);
+        zip_writer
+            .start_file(&entry_name, SimpleFileOptions::default())
+            .context(format!("在`{zip_path:?}`中创建`{entry_name}`失败"))?;
+        std::io::copy(&mut BufReader::new(file), &mut zip_writer)
+            .context(format!("将日志文件`{log_path:?}`写入`{zip_path:?}`失败"))?;
+    }
+
+    zip_writer
+        .finish()
+        .context(format!("关闭`{zip_path:?}`失败"))?;
+
+    Ok(zip_path)
+}
+
+fn write_entry(
+    zip_writer: &mut ZipWriter<File>,
+    entry_name: &str,
+    data: &[u8],
+) -> anyhow::Result<()> {
+    zip_writer
+        .start_file(entry_name, SimpleFileOptions::default())
+        .context(format!("创建`{entry_name}`失败"))?;
+    zip_writer
+        .write_all(data)
+        .context(format!("写入`{entry_name}`失败"))?;
+    Ok(())
+}
+
+/// 去掉配置中的敏感信息
+fn redact_config(mut config: Config) -> Config {
+    if !config.cookie.is_empty() {
+        config.cookie = REDACTED.to_string();
+    }
+    if let Ok(mut proxy_url) = reqwest::Url::parse(&config.proxy_url) {
+        let has_credentials = !proxy_url.username().is_empty() || proxy_url.password().is_some();
+        if has_credentials {
+            let _ = proxy_url.set_username(REDACTED);
+            let _ = proxy_url.set_password(None);
+            config.proxy_url = proxy_url.to_string();
+        }
+    }
+    config
+}
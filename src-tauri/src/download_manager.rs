@@ -1,20 +1,24 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap, HashSet},
     ops::ControlFlow,
     path::{Path, PathBuf},
     sync::{
-        atomic::{AtomicU32, AtomicU64, Ordering},
+        atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering},
         Arc,
     },
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use anyhow::{anyhow, Context};
 use image::ImageFormat;
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use specta::Type;
-use tauri::{AppHandle, Manager};
+use tauri::{
+    window::{ProgressBarState, ProgressBarStatus},
+    AppHandle, Manager,
+};
 use tauri_specta::Event;
 use tokio::{
     sync::{watch, Semaphore, SemaphorePermit},
@@ -24,9 +28,15 @@ use tokio::{
 
 use crate::{
     config::Config,
-    events::{DownloadSleepingEvent, DownloadSpeedEvent, DownloadTaskEvent},
+    errors::RateLimitedError,
+    events::{
+        DiskSpaceEvent, DownloadSleepingEvent, DownloadSpeedEvent, DownloadTaskCreatedEvent,
+        DownloadTaskEvent, DownloadTaskRemovedEvent, MeteredNetworkEvent, WaitingForScheduleEvent,
+    },
     extensions::AnyhowErrorToStringChain,
-    types::Comic,
+    library, metadata_store,
+    notifier::{self, DownloadOutcome},
+    types::{Comic, ComicBrief, DownloadFormat, ImgInImgList, OnExistingFolder},
     wnacg_client::WnacgClient,
 };
 
@@ -43,20 +53,61 @@ pub struct DownloadManager {
     app: AppHandle,
     comic_sem: Arc<Semaphore>,
     img_sem: Arc<Semaphore>,
+    /// 减少并发数时还没回收的permit数，见`resize_semaphore`
+    comic_sem_debt: Arc<Mutex<usize>>,
+    img_sem_debt: Arc<Mutex<usize>>,
+    comic_concurrency: Arc<AtomicUsize>,
+    img_concurrency: Arc<AtomicUsize>,
     byte_per_sec: Arc<AtomicU64>,
     download_tasks: Arc<RwLock<HashMap<i64, DownloadTask>>>,
+    /// 因电池供电等原因被自动暂停的任务，条件解除时只恢复这些任务，不影响用户手动暂停的任务
+    auto_paused_comic_ids: Arc<Mutex<HashMap<AutoPauseReason, HashSet<i64>>>>,
+    /// 下载目录所在磁盘的剩余空间是否低于`min_free_space_mb`
+    is_disk_space_low: Arc<AtomicBool>,
+    /// 当前网络是否按流量计费，由`metered_network::watch_metered_loop`更新
+    is_metered: Arc<AtomicBool>,
+    /// 任务栏图标上是否正在显示下载进度，空闲时只需要清除一次
+    is_taskbar_progress_shown: Arc<AtomicBool>,
 }
 
+/// 自动暂停下载任务的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum AutoPauseReason {
+    OnBattery,
+    MeteredNetwork,
+    LowDiskSpace,
+    OutsideSchedule,
+}
+
+/// 图片请求连续返回429达到这个次数时，下载任务进入`RateLimited`
+const RATE_LIMITED_THRESHOLD: u32 = 3;
+/// 图片请求返回429后，等待这么久再重试这张图片
+const RATE_LIMITED_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
 pub enum DownloadTaskState {
+    /// 刚创建或刚恢复，还没开始排队(例如在等待`download_schedule`的时间段)
     Pending,
+    /// 正在等待下载漫画的permit，即排在并发数限制之后
+    Queued,
     Downloading,
     Paused,
+    /// IP被限制访问，已暂停，可以像`Paused`一样恢复
+    RateLimited,
     Cancelled,
     Completed,
     Failed,
 }
 
+/// 单个下载任务的当前进度
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct DownloadTaskInfo {
+    pub state: DownloadTaskState,
+    pub downloaded_img_count: u32,
+    pub total_img_count: u32,
+}
+
 impl DownloadManager {
     pub fn new(app: &AppHandle) -> Self {
         let (comic_concurrency, img_concurrency) = {
@@ -69,8 +120,16 @@ impl DownloadManager {
             app: app.clone(),
             comic_sem: Arc::new(Semaphore::new(comic_concurrency)),
             img_sem: Arc::new(Semaphore::new(img_concurrency)),
+            comic_sem_debt: Arc::new(Mutex::new(0)),
+            img_sem_debt: Arc::new(Mutex::new(0)),
+            comic_concurrency: Arc::new(AtomicUsize::new(comic_concurrency)),
+            img_concurrency: Arc::new(AtomicUsize::new(img_concurrency)),
             byte_per_sec: Arc::new(AtomicU64::new(0)),
             download_tasks: Arc::new(RwLock::new(HashMap::new())),
+            auto_paused_comic_ids: Arc::new(Mutex::new(HashMap::new())),
+            is_disk_space_low: Arc::new(AtomicBool::new(false)),
+            is_metered: Arc::new(AtomicBool::new(false)),
+            is_taskbar_progress_shown: Arc::new(AtomicBool::new(false)),
         };
 
         tauri::async_runtime::spawn(manager.clone().emit_download_speed_loop());
@@ -78,32 +137,115 @@ impl DownloadManager {
         manager
     }
 
-    pub fn create_download_task(&self, comic: Comic) {
-        use DownloadTaskState::{Downloading, Paused, Pending};
+    /// 配置变化后，根据新的并发数调整信号量
+    pub fn on_config_changed(&self, config: &Config) {
+        let old_comic_concurrency = self
+            .comic_concurrency
+            .swap(config.comic_concurrency, Ordering::Relaxed);
+        resize_semaphore(
+            &self.comic_sem,
+            &self.comic_sem_debt,
+            old_comic_concurrency,
+            config.comic_concurrency,
+        );
+
+        let old_img_concurrency = self
+            .img_concurrency
+            .swap(config.img_concurrency, Ordering::Relaxed);
+        resize_semaphore(
+            &self.img_sem,
+            &self.img_sem_debt,
+            old_img_concurrency,
+            config.img_concurrency,
+        );
+    }
+
+    /// `format_override`不为`None`时，这个任务使用它而不是`config.download_format`
+    pub fn create_download_task(&self, comic: Comic, format_override: Option<DownloadFormat>) {
+        use DownloadTaskState::{Downloading, Paused, Pending, Queued, RateLimited};
         let comic_id = comic.id;
         let mut tasks = self.download_tasks.write();
         if let Some(task) = tasks.get(&comic_id) {
-            // 如果任务已经存在，且状态是`Pending`、`Downloading`或`Paused`，则不创建新任务
+            // 如果任务已经存在，且状态是`Pending`、`Queued`、`Downloading`、`Paused`或`RateLimited`，则不创建新任务
             let state = *task.state_sender.borrow();
-            if matches!(state, Pending | Downloading | Paused) {
+            if matches!(state, Pending | Queued | Downloading | Paused | RateLimited) {
                 return;
             }
         }
-        let task = DownloadTask::new(self.app.clone(), comic);
+        let _ = DownloadTaskCreatedEvent {
+            comic: comic.clone(),
+        }
+        .emit(&self.app);
+        let task = DownloadTask::new(self.app.clone(), comic, format_override);
         tauri::async_runtime::spawn(task.clone().process());
         tasks.insert(comic_id, task);
     }
 
+    pub fn get_download_task(&self, comic_id: i64) -> Option<DownloadTaskInfo> {
+        let tasks = self.download_tasks.read();
+        let task = tasks.get(&comic_id)?;
+        Some(DownloadTaskInfo {
+            state: *task.state_sender.borrow(),
+            downloaded_img_count: task.downloaded_img_count.load(Ordering::Relaxed),
+            total_img_count: task.total_img_count.load(Ordering::Relaxed),
+        })
+    }
+
+    /// 所有下载任务的漫画id、漫画标题和进度
+    pub fn download_task_infos(&self) -> Vec<(i64, String, DownloadTaskInfo)> {
+        self.download_tasks
+            .read()
+            .iter()
+            .map(|(comic_id, task)| {
+                let info = DownloadTaskInfo {
+                    state: *task.state_sender.borrow(),
+                    downloaded_img_count: task.downloaded_img_count.load(Ordering::Relaxed),
+                    total_img_count: task.total_img_count.load(Ordering::Relaxed),
+                };
+                (*comic_id, task.comic.title.clone(), info)
+            })
+            .collect()
+    }
+
+    /// 漫画的下载任务是否还未结束(`Pending`、`Queued`、`Downloading`、`Paused`或`RateLimited`)
+    pub fn is_task_active(&self, comic_id: i64) -> bool {
+        use DownloadTaskState::{Downloading, Paused, Pending, Queued, RateLimited};
+        self.download_tasks
+            .read()
+            .get(&comic_id)
+            .is_some_and(|task| {
+                let state = *task.state_sender.borrow();
+                matches!(state, Pending | Queued | Downloading | Paused | RateLimited)
+            })
+    }
+
+    /// 所有未结束(`Pending`、`Queued`、`Downloading`、`Paused`或`RateLimited`)的下载任务的漫画标题
+    pub fn active_comic_titles(&self) -> HashSet<String> {
+        use DownloadTaskState::{Downloading, Paused, Pending, Queued, RateLimited};
+        self.download_tasks
+            .read()
+            .values()
+            .filter(|task| {
+                let state = *task.state_sender.borrow();
+                matches!(state, Pending | Queued | Downloading | Paused | RateLimited)
+            })
+            .map(|task| task.comic.title.clone())
+            .collect()
+    }
+
     pub fn pause_download_task(&self, comic_id: i64) -> anyhow::Result<()> {
         let tasks = self.download_tasks.read();
         let Some(task) = tasks.get(&comic_id) else {
             return Err(anyhow!("未找到漫画ID为`{comic_id}`的下载任务"));
         };
         task.set_state(DownloadTaskState::Paused);
+        drop(tasks);
+        self.forget_auto_paused(&[comic_id]);
         Ok(())
     }
 
     pub fn resume_download_task(&self, comic_id: i64) -> anyhow::Result<()> {
+        self.forget_auto_paused(&[comic_id]);
         use DownloadTaskState::{Cancelled, Completed, Failed, Pending};
         let comic = {
             let tasks = self.download_tasks.read();
@@ -114,19 +256,242 @@ impl DownloadManager {
 
             if matches!(task_state, Failed | Cancelled | Completed) {
                 // 如果任务状态是`Failed`、`Cancelled`或`Completed`，则获取 comic 用于重新创建下载任务
-                Some(task.comic.as_ref().clone())
+                Some((task.comic.as_ref().clone(), task.format_override))
             } else {
                 task.set_state(Pending);
                 None
             }
         };
         // 如果 comic 不为 None，则重新创建下载任务
-        if let Some(comic) = comic {
-            self.create_download_task(comic);
+        if let Some((comic, format_override)) = comic {
+            self.create_download_task(comic, format_override);
+        }
+        Ok(())
+    }
+
+    /// 暂停所有`Pending`、`Queued`或`Downloading`的下载任务，返回被暂停的任务的漫画id
+    pub fn pause_all_download_tasks(&self) -> Vec<i64> {
+        use DownloadTaskState::{Downloading, Pending, Queued};
+        let tasks = self.download_tasks.read();
+        let mut paused_comic_ids = vec![];
+        for (comic_id, task) in tasks.iter() {
+            let state = *task.state_sender.borrow();
+            if matches!(state, Pending | Queued | Downloading) {
+                task.set_state(DownloadTaskState::Paused);
+                paused_comic_ids.push(*comic_id);
+            }
+        }
+        paused_comic_ids
+    }
+
+    /// 恢复所有`Paused`或`RateLimited`的下载任务，返回被恢复的任务的漫画id
+    pub fn resume_all_download_tasks(&self) -> Vec<i64> {
+        use DownloadTaskState::{Paused, RateLimited};
+        let tasks = self.download_tasks.read();
+        let mut resumed_comic_ids = vec![];
+        for (comic_id, task) in tasks.iter() {
+            let state = *task.state_sender.borrow();
+            if matches!(state, Paused | RateLimited) {
+                task.set_state(DownloadTaskState::Pending);
+                resumed_comic_ids.push(*comic_id);
+            }
+        }
+        drop(tasks);
+        self.forget_auto_paused(&resumed_comic_ids);
+        resumed_comic_ids
+    }
+
+    /// 网络的计费方式变化时暂停或恢复下载任务
+    ///
+    /// 只在切换到按流量计费的网络时暂停一次，之后用户手动恢复的任务不会再被暂停
+    pub fn on_metered_changed(&self, is_metered: bool) {
+        let was_metered = self.is_metered.swap(is_metered, Ordering::Relaxed);
+        if is_metered && !was_metered {
+            let paused_count = self.auto_pause(AutoPauseReason::MeteredNetwork);
+            tracing::info!("当前网络按流量计费，已暂停{paused_count}个下载任务");
+            let _ = MeteredNetworkEvent::Metered { paused_count }.emit(&self.app);
+        } else if !is_metered && was_metered {
+            let resumed_count = self.auto_resume(AutoPauseReason::MeteredNetwork);
+            tracing::info!("当前网络不再按流量计费，已恢复{resumed_count}个下载任务");
+            let _ = MeteredNetworkEvent::Unmetered { resumed_count }.emit(&self.app);
+        }
+    }
+
+    /// 用户手动暂停或恢复的任务不再由自动暂停的条件解除时恢复
+    fn forget_auto_paused(&self, comic_ids: &[i64]) {
+        for auto_paused_comic_ids in self.auto_paused_comic_ids.lock().values_mut() {
+            for comic_id in comic_ids {
+                auto_paused_comic_ids.remove(comic_id);
+            }
+        }
+    }
+
+    /// 根据供电状态和`pause_on_battery`决定是否暂停或恢复下载任务
+    ///
+    /// 前端可能多次报告同一状态，重复调用不会产生副作用
+    pub fn on_power_state_changed(&self, on_battery: bool) {
+        let pause_on_battery = self.app.state::<RwLock<Config>>().read().pause_on_battery;
+        if on_battery && pause_on_battery {
+            let paused_count = self.auto_pause(AutoPauseReason::OnBattery);
+            if paused_count > 0 {
+                tracing::debug!("使用电池供电，已暂停{paused_count}个下载任务");
+            }
+        } else if !on_battery {
+            let resumed_count = self.auto_resume(AutoPauseReason::OnBattery);
+            if resumed_count > 0 {
+                tracing::debug!("已恢复外接电源，已恢复{resumed_count}个下载任务");
+            }
+        }
+    }
+
+    /// 暂停所有`Pending`、`Queued`或`Downloading`的下载任务，并记录为因`reason`被暂停，返回被暂停的任务数量
+    fn auto_pause(&self, reason: AutoPauseReason) -> usize {
+        let paused_comic_ids = self.pause_all_download_tasks();
+        let paused_count = paused_comic_ids.len();
+        self.auto_paused_comic_ids
+            .lock()
+            .entry(reason)
+            .or_default()
+            .extend(paused_comic_ids);
+        paused_count
+    }
+
+    /// 恢复因`reason`被暂停的下载任务，返回被恢复的任务数量
+    fn auto_resume(&self, reason: AutoPauseReason) -> usize {
+        let comic_ids = self
+            .auto_paused_comic_ids
+            .lock()
+            .remove(&reason)
+            .unwrap_or_default();
+        let tasks = self.download_tasks.read();
+        let mut resumed_count = 0;
+        for comic_id in comic_ids {
+            let Some(task) = tasks.get(&comic_id) else {
+                continue;
+            };
+            // 期间被用户取消或重新开始的任务不再处理
+            if *task.state_sender.borrow() == DownloadTaskState::Paused {
+                task.set_state(DownloadTaskState::Pending);
+                resumed_count += 1;
+            }
+        }
+        resumed_count
+    }
+
+    /// 让下载任务立即开始，不受`download_schedule`限制
+    pub fn force_start_download_task(&self, comic_id: i64) -> anyhow::Result<()> {
+        let tasks = self.download_tasks.read();
+        let Some(task) = tasks.get(&comic_id) else {
+            return Err(anyhow!("未找到漫画ID为`{comic_id}`的下载任务"));
+        };
+        task.force_start.store(true, Ordering::Relaxed);
+        if let Some(comic_ids) = self
+            .auto_paused_comic_ids
+            .lock()
+            .get_mut(&AutoPauseReason::OutsideSchedule)
+        {
+            comic_ids.remove(&comic_id);
+        }
+        if *task.state_sender.borrow() == DownloadTaskState::Paused {
+            task.set_state(DownloadTaskState::Pending);
         }
         Ok(())
     }
 
+    /// 当前不在`download_schedule`的时间段内时，暂停正在下载的任务(用户选择立即开始的除外)，
+    /// 时间段开始后恢复这些任务
+    ///
+    /// 排队中的任务会在`acquire_comic_permit`中等待时间段开始，不需要暂停
+    fn check_download_schedule(&self) {
+        let is_outside = self
+            .app
+            .state::<RwLock<Config>>()
+            .read()
+            .secs_until_download_window()
+            > 0;
+        if !is_outside {
+            let resumed_count = self.auto_resume(AutoPauseReason::OutsideSchedule);
+            if resumed_count > 0 {
+                tracing::debug!("下载时间段已开始，已恢复{resumed_count}个下载任务");
+            }
+            return;
+        }
+
+        let mut paused_comic_ids = vec![];
+        for (comic_id, task) in self.download_tasks.read().iter() {
+            let is_downloading = *task.state_sender.borrow() == DownloadTaskState::Downloading;
+            if is_downloading && !task.force_start.load(Ordering::Relaxed) {
+                task.set_state(DownloadTaskState::Paused);
+                paused_comic_ids.push(*comic_id);
+            }
+        }
+        if paused_comic_ids.is_empty() {
+            return;
+        }
+        tracing::debug!(
+            "已超出下载时间段，已暂停{}个下载任务",
+            paused_comic_ids.len()
+        );
+        self.auto_paused_comic_ids
+            .lock()
+            .entry(AutoPauseReason::OutsideSchedule)
+            .or_default()
+            .extend(paused_comic_ids);
+    }
+
+    /// 检查下载目录所在磁盘的剩余空间，低于`min_free_space_mb`时暂停所有下载任务，以免写入失败
+    ///
+    /// 空间恢复后，开启了`auto_resume_on_space`时自动恢复这些任务，否则需要用户手动恢复
+    fn check_disk_space(&self) {
+        let (download_dir, min_free_space_mb, auto_resume_on_space) = {
+            let config = self.app.state::<RwLock<Config>>();
+            let config = config.read();
+            (
+                config.download_dir.clone(),
+                config.min_free_space_mb,
+                config.auto_resume_on_space,
+            )
+        };
+        // 下载目录不存在(例如外接硬盘被拔出)时无法获取剩余空间，此时下载本身会报错
+        let Ok(available_bytes) = fs2::available_space(&download_dir) else {
+            return;
+        };
+        let available_mb = available_bytes / 1024 / 1024;
+        let is_low = min_free_space_mb > 0 && available_mb < min_free_space_mb;
+        let was_low = self.is_disk_space_low.swap(is_low, Ordering::Relaxed);
+
+        if is_low {
+            // 空间不足期间新加入的任务也要暂停
+            let paused_count = self.auto_pause(AutoPauseReason::LowDiskSpace);
+            if !was_low {
+                tracing::warn!(
+                    "下载目录所在磁盘的剩余空间`{available_mb}MB`低于`{min_free_space_mb}MB`，已暂停{paused_count}个下载任务"
+                );
+                let _ = DiskSpaceEvent::Low {
+                    available_mb,
+                    min_free_space_mb,
+                }
+                .emit(&self.app);
+            }
+        } else if was_low {
+            let resumed_count = if auto_resume_on_space {
+                self.auto_resume(AutoPauseReason::LowDiskSpace)
+            } else {
+                // 不自动恢复时，由用户手动恢复，不再记录这些任务
+                self.auto_paused_comic_ids
+                    .lock()
+                    .remove(&AutoPauseReason::LowDiskSpace);
+                0
+            };
+            tracing::info!("下载目录所在磁盘的剩余空间已恢复，已恢复{resumed_count}个下载任务");
+            let _ = DiskSpaceEvent::Recovered {
+                available_mb,
+                resumed_count,
+            }
+            .emit(&self.app);
+        }
+    }
+
     pub fn cancel_download_task(&self, comic_id: i64) -> anyhow::Result<()> {
         let tasks = self.download_tasks.read();
         let Some(task) = tasks.get(&comic_id) else {
@@ -136,6 +501,189 @@ impl DownloadManager {
         Ok(())
     }
 
+    /// 移除所有状态为`Completed`、`Cancelled`或`Failed`的下载任务
+    pub fn clear_finished_download_tasks(&self) {
+        use DownloadTaskState::{Cancelled, Completed, Failed};
+        self.remove_download_tasks_where(|state| matches!(state, Completed | Cancelled | Failed));
+    }
+
+    /// 移除所有状态为`Completed`的下载任务，返回被移除的任务数量
+    ///
+    /// 已完成的漫画仍然可以在漫画库中看到
+    pub fn clear_completed_download_tasks(&self) -> usize {
+        self.remove_download_tasks_where(|state| state == DownloadTaskState::Completed)
+    }
+
+    /// 移除所有状态满足`predicate`的下载任务，每移除一个任务发送一次`DownloadTaskRemovedEvent`，返回被移除的任务数量
+    fn remove_download_tasks_where(&self, predicate: impl Fn(DownloadTaskState) -> bool) -> usize {
+        let mut removed_comic_ids = vec![];
+        self.download_tasks.write().retain(|comic_id, task| {
+            let state = *task.state_sender.borrow();
+            let should_remove = predicate(state);
+            if should_remove {
+                removed_comic_ids.push(*comic_id);
+            }
+            !should_remove
+        });
+        let removed_count = removed_comic_ids.len();
+        for comic_id in removed_comic_ids {
+            let _ = DownloadTaskRemovedEvent { comic_id }.emit(&self.app);
+        }
+        removed_count
+    }
+
+    /// 已结束的下载任务超过`max_finished_tasks`时，移除最早结束的任务，优先移除`Completed`和`Cancelled`，
+    /// 再移除`Failed`，未结束的任务不会被移除
+    ///
+    /// 每个任务都持有完整的图片列表，长时间下载大量漫画时不清理会占用大量内存
+    fn prune_finished_download_tasks(&self) {
+        use DownloadTaskState::{Cancelled, Completed, Failed};
+        let max_finished_tasks = self.app.state::<RwLock<Config>>().read().max_finished_tasks;
+        let mut tasks = self.download_tasks.write();
+        let mut finished_tasks: Vec<(bool, Option<Instant>, i64)> = tasks
+            .iter()
+            .filter_map(|(comic_id, task)| {
+                let state = *task.state_sender.borrow();
+                if !matches!(state, Completed | Cancelled | Failed) {
+                    return None;
+                }
+                let finished_at = *task.finished_at.lock();
+                Some((state == Failed, finished_at, *comic_id))
+            })
+            .collect();
+        if finished_tasks.len() <= max_finished_tasks {
+            return;
+        }
+        // `Failed`排在最后，同类的任务中最早结束的排在最前面
+        finished_tasks.sort();
+        let prune_count = finished_tasks.len() - max_finished_tasks;
+        let pruned_comic_ids: Vec<i64> = finished_tasks
+            .into_iter()
+            .take(prune_count)
+            .map(|(_, _, comic_id)| comic_id)
+            .collect();
+        for comic_id in &pruned_comic_ids {
+            tasks.remove(comic_id);
+        }
+        drop(tasks);
+        tracing::debug!(
+            "已结束的下载任务超过`{max_finished_tasks}`个，自动移除了`{prune_count}`个"
+        );
+        for comic_id in pruned_comic_ids {
+            let _ = DownloadTaskRemovedEvent { comic_id }.emit(&self.app);
+        }
+    }
+
+    /// 移除已结束的下载任务，未结束的任务需要先取消
+    pub fn remove_download_task(&self, comic_id: i64) -> anyhow::Result<()> {
+        use DownloadTaskState::{Cancelled, Completed, Failed};
+        let mut tasks = self.download_tasks.write();
+        let Some(task) = tasks.get(&comic_id) else {
+            return Err(anyhow!("未找到漫画ID为`{comic_id}`的下载任务"));
+        };
+        let state = *task.state_sender.borrow();
+        if !matches!(state, Completed | Cancelled | Failed) {
+            return Err(anyhow!(
+                "漫画ID为`{comic_id}`的下载任务状态为`{state:?}`，请先取消该任务"
+            ));
+        }
+        tasks.remove(&comic_id);
+        drop(tasks);
+        let _ = DownloadTaskRemovedEvent { comic_id }.emit(&self.app);
+        Ok(())
+    }
+
+    /// 计算每个任务最近一秒的下载速度，并给`Downloading`的任务发送带有速度的`DownloadTaskEvent`
+    fn emit_task_speeds(&self) {
+        let downloading_tasks: Vec<DownloadTask> = self
+            .download_tasks
+            .read()
+            .values()
+            .filter_map(|task| {
+                let byte_per_sec = task.byte_count.swap(0, Ordering::Relaxed);
+                task.byte_per_sec.store(byte_per_sec, Ordering::Relaxed);
+                let state = *task.state_sender.borrow();
+                (state == DownloadTaskState::Downloading).then(|| task.clone())
+            })
+            .collect();
+        for task in downloading_tasks {
+            task.emit_download_task_event();
+        }
+    }
+
+    /// 在主窗口的任务栏图标上显示所有未结束任务的总下载进度
+    ///
+    /// 有任务失败时显示为错误状态，未结束的任务都暂停时显示为暂停状态，没有未结束的任务或关闭了`taskbar_progress`时不显示
+    fn update_taskbar_progress(&self) {
+        use DownloadTaskState::{Downloading, Failed, Paused, Pending, Queued, RateLimited};
+        let taskbar_progress = self.app.state::<RwLock<Config>>().read().taskbar_progress;
+
+        let mut downloaded_img_count: u64 = 0;
+        let mut total_img_count: u64 = 0;
+        let mut has_active_task = false;
+        let mut has_running_task = false;
+        let mut has_failed_task = false;
+        for task in self.download_tasks.read().values() {
+            let state = *task.state_sender.borrow();
+            match state {
+                Pending | Queued | Downloading | Paused | RateLimited => {
+                    has_active_task = true;
+                    has_running_task |= matches!(state, Pending | Queued | Downloading);
+                    downloaded_img_count +=
+                        u64::from(task.downloaded_img_count.load(Ordering::Relaxed));
+                    total_img_count += u64::from(task.total_img_count.load(Ordering::Relaxed));
+                }
+                Failed => has_failed_task = true,
+                _ => {}
+            }
+        }
+
+        let progress_bar_state = if taskbar_progress && has_active_task {
+            self.is_taskbar_progress_shown
+                .store(true, Ordering::Relaxed);
+            let status = if has_failed_task {
+                ProgressBarStatus::Error
+            } else if has_running_task {
+                ProgressBarStatus::Normal
+            } else {
+                ProgressBarStatus::Paused
+            };
+            let progress = if total_img_count == 0 {
+                0
+            } else {
+                (downloaded_img_count * 100 / total_img_count).min(100)
+            };
+            ProgressBarState {
+                status: Some(status),
+                progress: Some(progress),
+            }
+        } else {
+            // 已经清除过了，不需要每秒都清除一次
+            if !self
+                .is_taskbar_progress_shown
+                .swap(false, Ordering::Relaxed)
+            {
+                return;
+            }
+            ProgressBarState {
+                status: Some(ProgressBarStatus::None),
+                progress: None,
+            }
+        };
+
+        let Some(window) = self.app.get_webview_window("main") else {
+            return;
+        };
+        if let Err(err) = window
+            .set_progress_bar(progress_bar_state)
+            .map_err(anyhow::Error::from)
+        {
+            let err_title = "设置任务栏下载进度失败";
+            let string_chain = err.to_string_chain();
+            tracing::error!(err_title, message = string_chain);
+        }
+    }
+
     #[allow(clippy::cast_precision_loss)]
     async fn emit_download_speed_loop(self) {
         let mut interval = tokio::time::interval(Duration::from_secs(1));
@@ -146,7 +694,62 @@ impl DownloadManager {
             let mega_byte_per_sec = byte_per_sec as f64 / 1024.0 / 1024.0;
             let speed = format!("{mega_byte_per_sec:.2} MB/s");
             // 发送总进度条下载速度事件
-            let _ = DownloadSpeedEvent { speed }.emit(&self.app);
+            let _ = DownloadSpeedEvent {
+                speed,
+                bytes_per_sec: byte_per_sec,
+            }
+            .emit(&self.app);
+            self.emit_task_speeds();
+            self.update_taskbar_progress();
+
+            self.check_disk_space();
+            self.check_download_schedule();
+        }
+    }
+}
+
+/// 将信号量的permit总数从`old`调整为`new`
+///
+/// 减少时，正在使用中的permit无法立即回收，记在`debt`中，等它们被释放后再回收  
+/// 增加时先抵消`debt`，否则新增的permit会被还没完成的回收拿走，实际并发数就一直低于配置
+fn resize_semaphore(sem: &Arc<Semaphore>, debt: &Arc<Mutex<usize>>, old: usize, new: usize) {
+    let mut debt_guard = debt.lock();
+    if new > old {
+        let to_add = new - old;
+        let paid = to_add.min(*debt_guard);
+        *debt_guard -= paid;
+        sem.add_permits(to_add - paid);
+        return;
+    }
+    let to_forget = old - new;
+    let forgotten = sem.forget_permits(to_forget);
+    let remaining = to_forget - forgotten;
+    if remaining == 0 {
+        return;
+    }
+    let is_reclaiming = *debt_guard > 0;
+    *debt_guard += remaining;
+    // `debt`不为0时一定有`reclaim_permits`在运行，不需要再启动一个
+    if !is_reclaiming {
+        tauri::async_runtime::spawn(reclaim_permits(sem.clone(), debt.clone()));
+    }
+}
+
+/// 每当有permit被释放就回收一个，直到`debt`为0
+async fn reclaim_permits(sem: Arc<Semaphore>, debt: Arc<Mutex<usize>>) {
+    loop {
+        let Ok(permit) = sem.clone().acquire_owned().await else {
+            return;
+        };
+        let mut debt = debt.lock();
+        if *debt == 0 {
+            // 等待期间`debt`已被增加并发数抵消，这个permit会在drop时归还
+            return;
+        }
+        *debt -= 1;
+        permit.forget();
+        if *debt == 0 {
+            return;
         }
     }
 }
@@ -159,10 +762,26 @@ struct DownloadTask {
     state_sender: watch::Sender<DownloadTaskState>,
     downloaded_img_count: Arc<AtomicU32>,
     total_img_count: Arc<AtomicU32>,
+    /// 用户选择立即开始，不受`download_schedule`限制
+    force_start: Arc<AtomicBool>,
+    /// 这个任务单独指定的图片格式，为`None`时使用`config.download_format`
+    format_override: Option<DownloadFormat>,
+    /// 图片请求连续返回429的次数，有图片下载成功时清零
+    rate_limited_count: Arc<AtomicU32>,
+    /// 开启`dedupe_pages`时，已保存的图片内容的哈希到页码和保存路径的映射
+    page_hashes: Arc<Mutex<HashMap<[u8; 32], (u32, PathBuf)>>>,
+    /// 开启`dedupe_pages`时，重复的页码到与它内容相同的页码的映射
+    duplicate_pages: Arc<Mutex<BTreeMap<u32, u32>>>,
+    /// 任务结束的时间，用于自动移除已结束的任务时判断先后
+    finished_at: Arc<Mutex<Option<Instant>>>,
+    /// 这个任务在当前这一秒内下载的字节数，每秒清零一次
+    byte_count: Arc<AtomicU64>,
+    /// 这个任务上一秒的下载速度，单位为字节每秒
+    byte_per_sec: Arc<AtomicU64>,
 }
 
 impl DownloadTask {
-    pub fn new(app: AppHandle, comic: Comic) -> Self {
+    pub fn new(app: AppHandle, comic: Comic, format_override: Option<DownloadFormat>) -> Self {
         let download_manager = app.state::<DownloadManager>().inner().clone();
         let (state_sender, _) = watch::channel(DownloadTaskState::Pending);
         Self {
@@ -172,9 +791,49 @@ impl DownloadTask {
             state_sender,
             downloaded_img_count: Arc::new(AtomicU32::new(0)),
             total_img_count: Arc::new(AtomicU32::new(0)),
+            force_start: Arc::new(AtomicBool::new(false)),
+            format_override,
+            rate_limited_count: Arc::new(AtomicU32::new(0)),
+            page_hashes: Arc::new(Mutex::new(HashMap::new())),
+            duplicate_pages: Arc::new(Mutex::new(BTreeMap::new())),
+            finished_at: Arc::new(Mutex::new(None)),
+            byte_count: Arc::new(AtomicU64::new(0)),
+            byte_per_sec: Arc::new(AtomicU64::new(0)),
         }
     }
 
+    /// 图片请求返回429时调用，连续达到`RATE_LIMITED_THRESHOLD`次时将任务设为`RateLimited`
+    fn on_rate_limited(&self) {
+        let comic_id = self.comic.id;
+        let comic_title = &self.comic.title;
+        let count = self.rate_limited_count.fetch_add(1, Ordering::Relaxed) + 1;
+        if count < RATE_LIMITED_THRESHOLD {
+            return;
+        }
+        if *self.state_sender.borrow() == DownloadTaskState::Downloading {
+            tracing::warn!(
+                comic_id,
+                comic_title,
+                "图片请求连续`{count}`次返回429，IP受限，已暂停下载，请稍后恢复"
+            );
+            self.set_state(DownloadTaskState::RateLimited);
+        }
+    }
+
+    /// 这个任务实际使用的图片格式
+    fn download_format(&self) -> DownloadFormat {
+        self.format_override
+            .unwrap_or_else(|| self.app.state::<RwLock<Config>>().read().download_format)
+    }
+
+    /// 记录了实际使用的图片格式和重复页的漫画信息，用于保存元数据
+    fn comic_for_metadata(&self) -> Comic {
+        let mut comic = self.comic.as_ref().clone();
+        comic.download_format = Some(self.download_format());
+        comic.duplicate_pages = self.duplicate_pages.lock().clone();
+        comic
+    }
+
     async fn process(self) {
         let download_comic_task = self.download_comic();
         tokio::pin!(download_comic_task);
@@ -184,7 +843,10 @@ impl DownloadTask {
         let mut permit = None;
         loop {
             let state_is_downloading = *state_receiver.borrow() == DownloadTaskState::Downloading;
-            let state_is_pending = *state_receiver.borrow() == DownloadTaskState::Pending;
+            let state_is_pending = matches!(
+                *state_receiver.borrow(),
+                DownloadTaskState::Pending | DownloadTaskState::Queued
+            );
             tokio::select! {
                 () = &mut download_comic_task, if state_is_downloading && permit.is_some() => break,
                 control_flow = self.acquire_comic_permit(&mut permit), if state_is_pending => {
@@ -201,20 +863,21 @@ impl DownloadTask {
                 }
             }
         }
+        *self.finished_at.lock() = Some(Instant::now());
+        self.download_manager.prune_finished_download_tasks();
     }
 
     #[allow(clippy::cast_possible_truncation)]
     async fn download_comic(&self) {
         let comic_id = self.comic.id;
         let comic_title = &self.comic.title;
-        // 获取此漫画每张图片的下载链接
+        // 获取此漫画每张图片的下载链接，按页码排序，以免网站返回的图片列表顺序错乱
         let img_urls = self
             .comic
             .img_list
-            .iter()
-            .map(|img| &img.url)
-            .filter(|url| !url.ends_with("shoucang.jpg")) // 过滤掉最后一张图片
-            .map(|url| format!("https:{url}"))
+            .sorted_by_caption()
+            .into_iter()
+            .map(ImgInImgList::full_url)
             .collect::<Vec<_>>();
         // 总共需要下载的图片数量
         self.total_img_count
@@ -224,7 +887,7 @@ impl DownloadTask {
         let Some(temp_download_dir) = self.create_temp_download_dir() else {
             return;
         };
-        // 清理临时下载目录中与`config.download_format`对不上的文件
+        // 清理临时下载目录中与这个任务的图片格式对不上的文件
         self.clean_temp_download_dir(&temp_download_dir);
 
         let mut join_set = JoinSet::new();
@@ -258,19 +921,50 @@ impl DownloadTask {
 
             self.set_state(DownloadTaskState::Failed);
             self.emit_download_task_event();
+            self.notify_failed(format!("{downloaded_img_count}/{total_img_count}页"));
 
             return;
         }
+        // 开始下载之前保存的元数据中还没有重复页的映射，需要重新保存
+        if !self.duplicate_pages.lock().is_empty() {
+            if let Err(err) = self.save_metadata(&temp_download_dir) {
+                let err_title = format!("`{comic_title}`保存重复页的映射失败");
+                let string_chain = err.to_string_chain();
+                tracing::error!(err_title, message = string_chain);
+
+                self.set_state(DownloadTaskState::Failed);
+                self.emit_download_task_event();
+                self.notify_failed(err_title);
+
+                return;
+            }
+        }
         // 此漫画的图片全部下载成功
-        if let Err(err) = self.rename_temp_download_dir(&temp_download_dir) {
-            let err_title = format!("`{comic_title}`重命名临时下载目录失败");
-            let string_chain = err.to_string_chain();
-            tracing::error!(err_title, message = string_chain);
+        let download_dir = match self.rename_temp_download_dir(&temp_download_dir) {
+            Ok(RenameOutcome::Renamed(download_dir)) => download_dir,
+            Ok(RenameOutcome::Skipped) => {
+                tracing::info!(comic_id, comic_title, "下载目录中已有同名目录，已跳过");
+                self.sleep_between_comics().await;
+                // 已有的目录就是这部漫画，视为下载完成
+                self.set_state(DownloadTaskState::Completed);
+                self.emit_download_task_event();
+                let outcome = DownloadOutcome::Skipped {
+                    comic_title: comic_title.clone(),
+                };
+                notifier::notify_download_finished(&self.app, outcome);
+                return;
+            }
+            Err(err) => {
+                let err_title = format!("`{comic_title}`重命名临时下载目录失败");
+                let string_chain = err.to_string_chain();
+                tracing::error!(err_title, message = string_chain);
 
-            self.set_state(DownloadTaskState::Failed);
-            self.emit_download_task_event();
+                self.set_state(DownloadTaskState::Failed);
+                self.emit_download_task_event();
+                self.notify_failed(err_title);
 
-            return;
+                return;
+            }
         };
         tracing::trace!(
             comic_id,
@@ -278,11 +972,35 @@ impl DownloadTask {
             "重命名临时下载目录`{temp_download_dir:?}`成功"
         );
         tracing::info!(comic_id, comic_title, "漫画下载成功");
+        let app = self.app.clone();
+        // 更新索引时需要遍历漫画目录，放到专门的线程中执行
+        if let Err(err) =
+            tauri::async_runtime::spawn_blocking(move || library::upsert_index(&app, &download_dir))
+                .await
+                .map_err(anyhow::Error::from)
+        {
+            let err_title = format!("`{comic_title}`下载完成后更新已下载漫画的索引失败");
+            let string_chain = err.to_string_chain();
+            tracing::error!(err_title, message = string_chain);
+        }
 
         self.sleep_between_comics().await;
         // 发送下载结束事件
         self.set_state(DownloadTaskState::Completed);
         self.emit_download_task_event();
+        let outcome = DownloadOutcome::Completed {
+            comic_title: comic_title.clone(),
+            img_count: total_img_count,
+        };
+        notifier::notify_download_finished(&self.app, outcome);
+    }
+
+    fn notify_failed(&self, reason: String) {
+        let outcome = DownloadOutcome::Failed {
+            comic_title: self.comic.title.clone(),
+            reason,
+        };
+        notifier::notify_download_finished(&self.app, outcome);
     }
 
     fn create_temp_download_dir(&self) -> Option<PathBuf> {
@@ -304,6 +1022,7 @@ impl DownloadTask {
 
             self.set_state(DownloadTaskState::Failed);
             self.emit_download_task_event();
+            self.notify_failed(err_title);
 
             return None;
         };
@@ -317,7 +1036,7 @@ impl DownloadTask {
         Some(temp_download_dir)
     }
 
-    /// 删除临时下载目录中与`config.download_format`对不上的文件
+    /// 删除临时下载目录中与这个任务的图片格式对不上的文件
     fn clean_temp_download_dir(&self, temp_download_dir: &Path) {
         let comic_id = self.comic.id;
         let comic_title = &self.comic.title;
@@ -333,14 +1052,14 @@ impl DownloadTask {
             }
         };
 
-        let download_format = self.app.state::<RwLock<Config>>().read().download_format;
-        let extension = download_format.extension();
+        let extension = self.download_format().extension();
         for path in entries.filter_map(Result::ok).map(|entry| entry.path()) {
-            // path有扩展名，且能转换为utf8，并与`config.download_format`一致，才保留
+            // path有扩展名，且能转换为utf8，并与这个任务的图片格式一致，才保留
+            // 下载到一半的`.partial`文件是原始数据，与图片格式无关，也要保留以便断点续传
             let should_keep = path
                 .extension()
                 .and_then(|ext| ext.to_str())
-                .is_some_and(|ext| Some(ext) == extension);
+                .is_some_and(|ext| Some(ext) == extension || ext == "partial");
             if should_keep {
                 continue;
             }
@@ -370,32 +1089,52 @@ impl DownloadTask {
 
         self.emit_download_task_event();
 
+        if permit.is_none() {
+            self.wait_for_download_window().await;
+        }
+
         *permit = match permit.take() {
             // 如果有permit，则直接用
             Some(permit) => Some(permit),
-            // 如果没有permit，则获取permit
-            None => match self
-                .download_manager
-                .comic_sem
-                .acquire()
-                .await
-                .map_err(anyhow::Error::from)
-            {
+            // 如果没有空闲的permit，则进入`Queued`，等待其他漫画下载完成
+            None => match self.download_manager.comic_sem.try_acquire() {
                 Ok(permit) => Some(permit),
-                Err(err) => {
-                    let err_title = format!("`{comic_title}`获取下载漫画的permit失败");
-                    let string_chain = err.to_string_chain();
-                    tracing::error!(err_title, message = string_chain);
-
-                    self.set_state(DownloadTaskState::Failed);
-                    self.emit_download_task_event();
-
-                    return ControlFlow::Break(());
+                Err(_) => {
+                    self.state_sender.send_if_modified(|state| {
+                        let is_pending = *state == DownloadTaskState::Pending;
+                        if is_pending {
+                            *state = DownloadTaskState::Queued;
+                        }
+                        is_pending
+                    });
+                    match self
+                        .download_manager
+                        .comic_sem
+                        .acquire()
+                        .await
+                        .map_err(anyhow::Error::from)
+                    {
+                        Ok(permit) => Some(permit),
+                        Err(err) => {
+                            let err_title = format!("`{comic_title}`获取下载漫画的permit失败");
+                            let string_chain = err.to_string_chain();
+                            tracing::error!(err_title, message = string_chain);
+
+                            self.set_state(DownloadTaskState::Failed);
+                            self.emit_download_task_event();
+
+                            return ControlFlow::Break(());
+                        }
+                    }
                 }
             },
         };
-        // 如果当前任务状态不是`Pending`，则不将任务状态设置为`Downloading`
-        if *self.state_sender.borrow() != DownloadTaskState::Pending {
+        // 如果当前任务状态不是`Pending`或`Queued`，则不将任务状态设置为`Downloading`
+        let state = *self.state_sender.borrow();
+        if !matches!(
+            state,
+            DownloadTaskState::Pending | DownloadTaskState::Queued
+        ) {
             return ControlFlow::Continue(());
         }
         // 将任务状态设置为`Downloading`
@@ -423,8 +1162,8 @@ impl DownloadTask {
         self.emit_download_task_event();
         let state = *state_receiver.borrow();
         match state {
-            DownloadTaskState::Paused => {
-                tracing::debug!(comic_id, comic_title, "漫画暂停中");
+            DownloadTaskState::Paused | DownloadTaskState::RateLimited => {
+                tracing::debug!(comic_id, comic_title, "漫画暂停中({state:?})");
                 if let Some(permit) = permit.take() {
                     drop(permit);
                 };
@@ -438,6 +1177,32 @@ impl DownloadTask {
         }
     }
 
+    /// 等到`download_schedule`的时间段开始，期间每秒发送一次`WaitingForScheduleEvent`
+    ///
+    /// 用户选择立即开始或修改了配置后会立即结束等待
+    async fn wait_for_download_window(&self) {
+        let comic_id = self.comic.id;
+        loop {
+            if self.force_start.load(Ordering::Relaxed) {
+                return;
+            }
+            let remaining_sec = self
+                .app
+                .state::<RwLock<Config>>()
+                .read()
+                .secs_until_download_window();
+            if remaining_sec == 0 {
+                return;
+            }
+            let _ = WaitingForScheduleEvent {
+                comic_id,
+                remaining_sec,
+            }
+            .emit(&self.app);
+            sleep(Duration::from_secs(1)).await;
+        }
+    }
+
     async fn sleep_between_comics(&self) {
         let comic_id = self.comic.id;
         let mut remaining_sec = self
@@ -467,55 +1232,106 @@ impl DownloadTask {
     }
 
     fn emit_download_task_event(&self) {
+        let state = *self.state_sender.borrow();
+        let downloaded_img_count = self.downloaded_img_count.load(Ordering::Relaxed);
+        let total_img_count = self.total_img_count.load(Ordering::Relaxed);
+        let progress = if state == DownloadTaskState::Completed {
+            1.0
+        } else if total_img_count == 0 {
+            0.0
+        } else {
+            (f64::from(downloaded_img_count) / f64::from(total_img_count)).min(1.0)
+        };
+        let bytes_per_sec = if state == DownloadTaskState::Downloading {
+            self.byte_per_sec.load(Ordering::Relaxed)
+        } else {
+            0
+        };
         let _ = DownloadTaskEvent {
-            state: *self.state_sender.borrow(),
-            comic: self.comic.as_ref().clone(),
-            downloaded_img_count: self.downloaded_img_count.load(Ordering::Relaxed),
-            total_img_count: self.total_img_count.load(Ordering::Relaxed),
+            state,
+            comic: ComicBrief::from(self.comic.as_ref()),
+            downloaded_img_count,
+            total_img_count,
+            progress,
+            bytes_per_sec,
         }
         .emit(&self.app);
     }
 
-    #[allow(clippy::needless_pass_by_value)]
+    /// 关闭了`write_metadata_json`时不在临时下载目录中保存元数据，下载完成后保存到元数据索引
     pub fn save_metadata(&self, temp_download_dir: &Path) -> anyhow::Result<()> {
-        let mut comic = self.comic.as_ref().clone();
-        // 将所有comic的is_downloaded字段设置为None，这样能使is_downloaded字段在序列化时被忽略
-        comic.is_downloaded = None;
-
-        let comic_title = &comic.title;
-        let comic_json = serde_json::to_string_pretty(&comic).context(format!(
-            "`{comic_title}`的元数据保存失败，将Comic序列化为json失败"
-        ))?;
-
-        let metadata_path = temp_download_dir.join("元数据.json");
-
-        std::fs::write(&metadata_path, comic_json).context(format!(
-            "`{comic_title}`的元数据保存失败，写入文件`{metadata_path:?}`失败"
-        ))?;
-
-        Ok(())
+        let write_metadata_json = self
+            .app
+            .state::<RwLock<Config>>()
+            .read()
+            .write_metadata_json;
+        if !write_metadata_json {
+            return Ok(());
+        }
+        self.comic_for_metadata().save_metadata(temp_download_dir)
     }
 
-    fn rename_temp_download_dir(&self, temp_download_dir: &Path) -> anyhow::Result<()> {
+    fn rename_temp_download_dir(&self, temp_download_dir: &Path) -> anyhow::Result<RenameOutcome> {
         let Some(parent) = temp_download_dir.parent() else {
             return Err(anyhow!("无法获取`{temp_download_dir:?}`的父目录"));
         };
 
-        let download_dir = parent.join(&self.comic.title);
+        let mut download_dir = parent.join(&self.comic.title);
+        let mut comic = self.comic_for_metadata();
+        let write_metadata_json = self
+            .app
+            .state::<RwLock<Config>>()
+            .read()
+            .write_metadata_json;
 
         if download_dir.exists() {
-            std::fs::remove_dir_all(&download_dir)
-                .context(format!("删除目录`{download_dir:?}`失败"))?;
+            let on_existing_folder = self.app.state::<RwLock<Config>>().read().on_existing_folder;
+            match on_existing_folder {
+                OnExistingFolder::Overwrite => std::fs::remove_dir_all(&download_dir)
+                    .context(format!("删除目录`{download_dir:?}`失败"))?,
+                OnExistingFolder::Skip => {
+                    // 保留已有的目录，新下载的内容不再需要，删掉以免之后被当作残留的临时下载目录
+                    if let Err(err) = std::fs::remove_dir_all(temp_download_dir) {
+                        let err_title = format!("删除临时下载目录`{temp_download_dir:?}`失败");
+                        let string_chain = anyhow::Error::from(err).to_string_chain();
+                        tracing::warn!(err_title, message = string_chain);
+                    }
+                    return Ok(RenameOutcome::Skipped);
+                }
+                OnExistingFolder::RenameWithSuffix => {
+                    download_dir = library::available_dir(&download_dir);
+                    // 目录名即标题，元数据中的标题也要改成新的目录名，否则索引中找不到这个目录
+                    comic.title = download_dir
+                        .file_name()
+                        .map(|name| name.to_string_lossy().to_string())
+                        .unwrap_or_default();
+                    if write_metadata_json {
+                        comic.save_metadata(temp_download_dir)?;
+                    }
+                }
+            }
         }
 
         std::fs::rename(temp_download_dir, &download_dir).context(format!(
             "将`{temp_download_dir:?}`重命名为`{download_dir:?}`失败"
         ))?;
 
-        Ok(())
+        if !write_metadata_json {
+            metadata_store::put(&self.app, &comic).context("保存元数据到元数据索引失败")?;
+        }
+
+        Ok(RenameOutcome::Renamed(download_dir))
     }
 }
 
+/// `rename_temp_download_dir`的结果
+enum RenameOutcome {
+    /// 临时下载目录已重命名为正式的下载目录
+    Renamed(PathBuf),
+    /// 已有同名目录且`on_existing_folder`为`Skip`，保留了已有的目录，临时下载目录已删除
+    Skipped,
+}
+
 #[derive(Clone)]
 struct DownloadImgTask {
     app: AppHandle,
@@ -578,7 +1394,7 @@ impl DownloadImgTask {
 
         tracing::trace!(comic_id, comic_title, url, "开始下载图片");
 
-        let download_format = self.app.state::<RwLock<Config>>().read().download_format;
+        let download_format = self.download_task.download_format();
         if let Some(extension) = download_format.extension() {
             // 如果图片已存在，则跳过下载
             let save_path = self
@@ -593,14 +1409,34 @@ impl DownloadImgTask {
                 return;
             }
         }
-        // 下载图片
-        let (img_data, img_format) = match self.wnacg_client().get_img_data_and_format(url).await {
-            Ok(data_and_format) => data_and_format,
-            Err(err) => {
-                let err_title = format!("下载图片`{url}`失败");
-                let string_chain = err.to_string_chain();
-                tracing::error!(err_title, message = string_chain);
-                return;
+        // 下载到一半的原始数据保存在这里，下次下载这张图片时从中断的地方继续
+        let partial_path = self
+            .temp_download_dir
+            .join(format!("{:04}.partial", self.index + 1));
+        // 下载图片，返回429时稍后重试，连续429过多时任务会进入`RateLimited`，恢复后再继续重试
+        let (img_data, img_format) = loop {
+            let img_data_and_format = self
+                .wnacg_client()
+                .get_img_data_and_format(url, comic_id, download_format, Some(&partial_path))
+                .await;
+            match img_data_and_format {
+                Ok(data_and_format) => {
+                    self.download_task
+                        .rate_limited_count
+                        .store(0, Ordering::Relaxed);
+                    break data_and_format;
+                }
+                Err(err) if err.is::<RateLimitedError>() => {
+                    tracing::debug!(comic_id, comic_title, url, "图片请求返回429，稍后重试");
+                    self.download_task.on_rate_limited();
+                    sleep(RATE_LIMITED_RETRY_INTERVAL).await;
+                }
+                Err(err) => {
+                    let err_title = format!("下载图片`{url}`失败");
+                    let string_chain = err.to_string_chain();
+                    tracing::error!(err_title, message = string_chain);
+                    return;
+                }
             }
         };
 
@@ -623,7 +1459,7 @@ impl DownloadImgTask {
             .temp_download_dir
             .join(format!("{:04}.{extension}", self.index + 1));
         // 保存图片
-        if let Err(err) = std::fs::write(&save_path, &img_data).map_err(anyhow::Error::from) {
+        if let Err(err) = self.save_img(&save_path, &img_data) {
             let err_title = format!("保存图片`{save_path:?}`失败");
             let string_chain = err.to_string_chain();
             tracing::error!(err_title, message = string_chain);
@@ -634,6 +1470,9 @@ impl DownloadImgTask {
         self.download_manager
             .byte_per_sec
             .fetch_add(img_data.len() as u64, Ordering::Relaxed);
+        self.download_task
+            .byte_count
+            .fetch_add(img_data.len() as u64, Ordering::Relaxed);
         tracing::trace!(comic_id, url, comic_title, "图片下载成功");
 
         self.download_task
@@ -649,6 +1488,43 @@ impl DownloadImgTask {
         sleep(Duration::from_secs(img_download_interval_sec)).await;
     }
 
+    /// 开启`dedupe_pages`时，内容与已保存的某一页相同的图片硬链接(失败时复制)到那一页的文件，而不是再保存一份
+    fn save_img(&self, save_path: &Path, img_data: &[u8]) -> anyhow::Result<()> {
+        let dedupe_pages = self.app.state::<RwLock<Config>>().read().dedupe_pages;
+        if !dedupe_pages {
+            std::fs::write(save_path, img_data)?;
+            return Ok(());
+        }
+
+        let page_num = self.index as u32 + 1;
+        let hash: [u8; 32] = Sha256::digest(img_data).into();
+        // 持有锁直到文件写入完成，以免内容相同的图片同时下载完成时都被当成第一次出现
+        let mut page_hashes = self.download_task.page_hashes.lock();
+        match page_hashes.get(&hash) {
+            Some((src_page_num, src_path)) => {
+                std::fs::hard_link(src_path, save_path)
+                    .or_else(|_| std::fs::copy(src_path, save_path).map(|_| ()))
+                    .context(format!("将`{src_path:?}`链接或复制到`{save_path:?}`失败"))?;
+                let comic_id = self.download_task.comic.id;
+                let comic_title = &self.download_task.comic.title;
+                tracing::debug!(
+                    comic_id,
+                    comic_title,
+                    "第`{page_num}`页与第`{src_page_num}`页内容相同，已去重"
+                );
+                self.download_task
+                    .duplicate_pages
+                    .lock()
+                    .insert(page_num, *src_page_num);
+            }
+            None => {
+                std::fs::write(save_path, img_data)?;
+                page_hashes.insert(hash, (page_num, save_path.to_path_buf()));
+            }
+        }
+        Ok(())
+    }
+
     async fn acquire_img_permit<'a>(
         &'a self,
         permit: &mut Option<SemaphorePermit<'a>>,
@@ -693,7 +1569,7 @@ impl DownloadImgTask {
 
         let state = *state_receiver.borrow();
         match state {
-            DownloadTaskState::Paused => {
+            DownloadTaskState::Paused | DownloadTaskState::RateLimited => {
                 tracing::trace!(comic_id, comic_title, url, "图片暂停下载");
                 if let Some(permit) = permit.take() {
                     drop(permit);
@@ -712,3 +1588,52 @@ impl DownloadImgTask {
         self.app.state::<WnacgClient>().inner().clone()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 等待`reclaim_permits`处理完已释放的permit
+    async fn settle() {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    #[tokio::test]
+    async fn resize_semaphore_reclaims_permits_after_release() {
+        let sem = Arc::new(Semaphore::new(3));
+        let debt = Arc::new(Mutex::new(0));
+        let in_use = sem.clone().acquire_many_owned(2).await.unwrap();
+        resize_semaphore(&sem, &debt, 3, 1);
+        assert_eq!(sem.available_permits(), 0);
+        drop(in_use);
+        settle().await;
+        assert_eq!(sem.available_permits(), 1);
+        assert_eq!(*debt.lock(), 0);
+    }
+
+    #[tokio::test]
+    async fn resize_semaphore_raise_pays_off_pending_shrink() {
+        let sem = Arc::new(Semaphore::new(3));
+        let debt = Arc::new(Mutex::new(0));
+        let in_use = sem.clone().acquire_many_owned(3).await.unwrap();
+        resize_semaphore(&sem, &debt, 3, 1);
+        resize_semaphore(&sem, &debt, 1, 4);
+        assert_eq!(sem.available_permits(), 1);
+        drop(in_use);
+        settle().await;
+        assert_eq!(sem.available_permits(), 4);
+        assert_eq!(*debt.lock(), 0);
+    }
+
+    #[tokio::test]
+    async fn resize_semaphore_shrinks_twice_while_in_use() {
+        let sem = Arc::new(Semaphore::new(4));
+        let debt = Arc::new(Mutex::new(0));
+        let in_use = sem.clone().acquire_many_owned(4).await.unwrap();
+        resize_semaphore(&sem, &debt, 4, 3);
+        resize_semaphore(&sem, &debt, 3, 1);
+        drop(in_use);
+        settle().await;
+        assert_eq!(sem.available_permits(), 1);
+    }
+}
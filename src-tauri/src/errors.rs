@@ -1,26 +1,200 @@
+use std::fmt::Display;
+
 use serde::Serialize;
 use specta::Type;
 
-use crate::extensions::AnyhowErrorToStringChain;
+use crate::{config::InvalidConfigField, extensions::AnyhowErrorToStringChain};
 
 pub type CommandResult<T> = Result<T, CommandError>;
 
 #[derive(Debug, Type, Serialize)]
 pub struct CommandError {
+    pub code: ErrorCode,
     pub err_title: String,
     pub err_message: String,
 }
 
+/// 错误的种类，前端可以根据它显示翻译后的提示或做特殊处理(例如提示重新登录)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Type, Serialize)]
+pub enum ErrorCode {
+    Unknown,
+    /// 未登录或cookie已过期
+    NotLoggedIn,
+    /// 网络请求失败
+    NetworkError,
+    /// 网站返回的内容无法解析
+    ParseError,
+    /// 请求过于频繁，IP被封
+    RateLimited,
+    /// 漫画不存在或已被删除
+    NotFound,
+    /// 读写文件失败
+    IoError,
+    /// 配置项不合法
+    InvalidConfig,
+}
+
+impl ErrorCode {
+    /// 从错误链中推断错误的种类，多种错误同时存在时按下面的顺序优先
+    pub fn infer(err: &anyhow::Error) -> ErrorCode {
+        if is::<SessionExpiredError>(err) {
+            ErrorCode::NotLoggedIn
+        } else if is::<RateLimitedError>(err) {
+            ErrorCode::RateLimited
+        } else if is::<ComicNotFoundError>(err) {
+            ErrorCode::NotFound
+        } else if is::<ParseError>(err) {
+            ErrorCode::ParseError
+        } else if is::<InvalidConfigField>(err) {
+            ErrorCode::InvalidConfig
+        } else if is::<reqwest::Error>(err) || is::<reqwest_middleware::Error>(err) {
+            ErrorCode::NetworkError
+        } else if is::<std::io::Error>(err) {
+            ErrorCode::IoError
+        } else {
+            ErrorCode::Unknown
+        }
+    }
+}
+
+/// 错误链中是否有`T`类型的错误
+///
+/// `err.chain()`中看不到用`.context(T)`附加的`T`，所以还要用`err.is`检查每一层context
+fn is<T>(err: &anyhow::Error) -> bool
+where
+    T: std::error::Error + Send + Sync + 'static,
+{
+    err.is::<T>() || err.chain().any(|err| err.is::<T>())
+}
+
 impl CommandError {
+    /// 错误的种类由`ErrorCode::infer`从错误链中推断
     pub fn from<E>(err_title: &str, err: E) -> Self
+    where
+        E: Into<anyhow::Error>,
+    {
+        let err = err.into();
+        let code = ErrorCode::infer(&err);
+        Self::with_code(code, err_title, err)
+    }
+
+    /// 无法从错误链中推断错误的种类时，用这个函数指定
+    pub fn with_code<E>(code: ErrorCode, err_title: &str, err: E) -> Self
     where
         E: Into<anyhow::Error>,
     {
         let string_chain = err.into().to_string_chain();
-        tracing::error!(err_title, message = string_chain);
+        tracing::error!(err_title, ?code, message = string_chain);
         Self {
+            code,
             err_title: err_title.to_string(),
             err_message: string_chain,
         }
     }
 }
+
+/// 漫画不存在或已被删除
+#[derive(Debug)]
+pub struct ComicNotFoundError {
+    pub id: i64,
+}
+
+impl Display for ComicNotFoundError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "漫画ID为`{}`的漫画不存在或已被删除", self.id)
+    }
+}
+
+impl std::error::Error for ComicNotFoundError {}
+
+/// 未登录，cookie已过期或无效
+#[derive(Debug)]
+pub struct SessionExpiredError;
+
+impl Display for SessionExpiredError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "未登录，cookie已过期或cookie无效")
+    }
+}
+
+impl std::error::Error for SessionExpiredError {}
+
+/// 请求过于频繁，IP被封
+#[derive(Debug)]
+pub struct RateLimitedError;
+
+impl Display for RateLimitedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "IP被封，请在更多设置中减少并发数或设置下载完成后的休息时间，以此降低下载速度，稍后再试")
+    }
+}
+
+impl std::error::Error for RateLimitedError {}
+
+/// 导出被用户取消
+#[derive(Debug)]
+pub struct ExportCancelledError;
+
+impl Display for ExportCancelledError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "导出已取消")
+    }
+}
+
+impl std::error::Error for ExportCancelledError {}
+
+/// 网站返回的内容无法解析，通常是网站改版导致的
+#[derive(Debug)]
+pub struct ParseError {
+    pub type_name: &'static str,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "无法将网站返回的内容解析为`{}`", self.type_name)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+
+    use super::*;
+
+    #[test]
+    fn infer_finds_error_attached_as_context() {
+        let err = anyhow!("html中没有找到标题")
+            .context(ParseError { type_name: "Comic" })
+            .context("获取漫画失败");
+        assert_eq!(ErrorCode::infer(&err), ErrorCode::ParseError);
+    }
+
+    #[test]
+    fn infer_finds_root_error_under_context() {
+        let err = anyhow::Error::from(RateLimitedError).context("获取漫画失败");
+        assert_eq!(ErrorCode::infer(&err), ErrorCode::RateLimited);
+    }
+
+    #[test]
+    fn infer_finds_source_of_root_error() {
+        let io_err = std::io::Error::new(std::io::ErrorKind::NotFound, "文件不存在");
+        let err = anyhow::Error::from(io_err).context("读取元数据失败");
+        assert_eq!(ErrorCode::infer(&err), ErrorCode::IoError);
+    }
+
+    #[test]
+    fn infer_prefers_session_expired() {
+        let err = anyhow::Error::from(SessionExpiredError).context(ParseError {
+            type_name: "UserProfile",
+        });
+        assert_eq!(ErrorCode::infer(&err), ErrorCode::NotLoggedIn);
+    }
+
+    #[test]
+    fn infer_unknown_error() {
+        let err = anyhow!("未知错误").context("获取漫画失败");
+        assert_eq!(ErrorCode::infer(&err), ErrorCode::Unknown);
+    }
+}
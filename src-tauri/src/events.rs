@@ -1,12 +1,13 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, path::PathBuf};
 
 use serde::{Deserialize, Serialize};
 use specta::Type;
 use tauri_specta::Event;
 
 use crate::{
+    config::Config,
     download_manager::DownloadTaskState,
-    types::{Comic, LogLevel},
+    types::{Comic, ComicBrief, ComicInFavorite, ComicInSearch, LogLevel},
 };
 
 #[derive(Debug, Clone, Serialize, Deserialize, Type, Event)]
@@ -21,19 +22,41 @@ pub struct LogEvent {
     pub line_number: i64,
 }
 
+/// 下载任务状态或进度变化，下载过程中会频繁发送，所以只包含`ComicBrief`
+///
+/// 完整的`Comic`只在创建任务时通过`DownloadTaskCreatedEvent`发送一次
 #[derive(Debug, Clone, Serialize, Deserialize, Type, Event)]
 #[serde(rename_all = "camelCase")]
 pub struct DownloadTaskEvent {
     pub state: DownloadTaskState,
-    pub comic: Comic,
+    pub comic: ComicBrief,
     pub downloaded_img_count: u32,
     pub total_img_count: u32,
+    /// 下载进度，范围为0.0到1.0，`Completed`时总是1.0，图片总数未知时为0.0
+    pub progress: f64,
+    /// 这个任务最近一秒的下载速度，单位为字节每秒，不在`Downloading`状态时为0
+    pub bytes_per_sec: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type, Event)]
+#[serde(rename_all = "camelCase")]
+pub struct DownloadTaskCreatedEvent {
+    pub comic: Comic,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type, Event)]
+#[serde(rename_all = "camelCase")]
+pub struct DownloadTaskRemovedEvent {
+    pub comic_id: i64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Type, Event)]
 #[serde(rename_all = "camelCase")]
 pub struct DownloadSpeedEvent {
+    /// 格式化后的下载速度，例如`1.23 MB/s`
     pub speed: String,
+    /// 最近一秒的下载速度，单位为字节每秒，前端可以自行格式化或用于绘制图表
+    pub bytes_per_sec: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Type, Event)]
@@ -43,6 +66,14 @@ pub struct DownloadSleepingEvent {
     pub remaining_sec: u64,
 }
 
+/// 下载任务在等待`download_schedule`的时间段开始
+#[derive(Debug, Clone, Serialize, Deserialize, Type, Event)]
+#[serde(rename_all = "camelCase")]
+pub struct WaitingForScheduleEvent {
+    pub comic_id: i64,
+    pub remaining_sec: u64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Type, Event)]
 #[serde(tag = "event", content = "data")]
 pub enum ExportPdfEvent {
@@ -51,6 +82,10 @@ pub enum ExportPdfEvent {
 
     #[serde(rename_all = "camelCase")]
     End { uuid: String },
+
+    /// 被`cancel_export`取消，没有生成pdf
+    #[serde(rename_all = "camelCase")]
+    Cancelled { uuid: String },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Type, Event)]
@@ -62,3 +97,150 @@ pub enum ExportCbzEvent {
     #[serde(rename_all = "camelCase")]
     End { uuid: String },
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type, Event)]
+#[serde(rename_all = "camelCase")]
+pub struct DownloadDirChangedEvent {
+    pub old_download_dir: PathBuf,
+    pub new_download_dir: PathBuf,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type, Event)]
+#[serde(tag = "event", content = "data")]
+pub enum MigrateLibraryEvent {
+    #[serde(rename_all = "camelCase")]
+    Start { total: u32 },
+
+    #[serde(rename_all = "camelCase")]
+    Progress {
+        title: String,
+        current: u32,
+        total: u32,
+    },
+
+    #[serde(rename_all = "camelCase")]
+    End { moved_count: u32, failed_count: u32 },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type, Event)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigChangedEvent {
+    pub config: Config,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type, Event)]
+#[serde(rename_all = "camelCase")]
+pub struct ComicDeletedEvent {
+    pub comic_id: i64,
+}
+
+/// 任意请求发现未登录(cookie已过期或无效)，前端应提示重新登录
+///
+/// 同一个cookie只会发送一次
+#[derive(Debug, Clone, Serialize, Deserialize, Type, Event)]
+pub struct AuthRequiredEvent;
+
+/// cookie过期后用保存的账号密码自动重新登录成功，新的cookie已保存
+#[derive(Debug, Clone, Serialize, Deserialize, Type, Event)]
+pub struct SessionRefreshedEvent;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type, Event)]
+#[serde(tag = "event", content = "data")]
+pub enum VerifyLibraryEvent {
+    #[serde(rename_all = "camelCase")]
+    Start { total: u32 },
+
+    #[serde(rename_all = "camelCase")]
+    Progress {
+        title: String,
+        current: u32,
+        total: u32,
+    },
+
+    #[serde(rename_all = "camelCase")]
+    End { issue_count: u32, cancelled: bool },
+}
+
+/// 订阅的标签有新漫画
+#[derive(Debug, Clone, Serialize, Deserialize, Type, Event)]
+#[serde(rename_all = "camelCase")]
+pub struct NewComicsEvent {
+    pub tag: String,
+    pub comics: Vec<ComicInSearch>,
+}
+
+/// 签到成功，自动签到和手动签到都会发送
+#[derive(Debug, Clone, Serialize, Deserialize, Type, Event)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckInEvent {
+    /// 今天已经签到过，这次没有获得积分
+    pub already_checked_in: bool,
+    /// 这次签到获得的积分，网站的提示中没有时为None
+    pub points: Option<i64>,
+}
+
+/// 监视的书架中有新漫画，已加入下载队列
+#[derive(Debug, Clone, Serialize, Deserialize, Type, Event)]
+#[serde(rename_all = "camelCase")]
+pub struct ShelfComicsQueuedEvent {
+    pub shelf_id: i64,
+    pub comics: Vec<ComicInFavorite>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type, Event)]
+#[serde(tag = "event", content = "data")]
+pub enum ExportAllEvent {
+    #[serde(rename_all = "camelCase")]
+    Start { total: u32 },
+
+    #[serde(rename_all = "camelCase")]
+    Progress {
+        title: String,
+        current: u32,
+        total: u32,
+        /// 导出文件比下载的文件新，不需要重新导出
+        skipped: bool,
+    },
+
+    #[serde(rename_all = "camelCase")]
+    End {
+        exported_count: u32,
+        skipped_count: u32,
+        failed_count: u32,
+        cancelled: bool,
+    },
+}
+
+/// 当前网络是否按流量计费的变化，只在开启`pause_on_metered`时发送
+#[derive(Debug, Clone, Serialize, Deserialize, Type, Event)]
+#[serde(tag = "event", content = "data")]
+pub enum MeteredNetworkEvent {
+    /// 切换到了按流量计费的网络，`paused_count`为因此暂停的任务数量
+    #[serde(rename_all = "camelCase")]
+    Metered { paused_count: usize },
+
+    /// 切换回了不按流量计费的网络，`resumed_count`为自动恢复的任务数量
+    ///
+    /// 期间被用户手动暂停、恢复或取消的任务不会被自动恢复
+    #[serde(rename_all = "camelCase")]
+    Unmetered { resumed_count: usize },
+}
+
+/// 下载目录所在磁盘的剩余空间变化，单位为MB
+#[derive(Debug, Clone, Serialize, Deserialize, Type, Event)]
+#[serde(tag = "event", content = "data")]
+pub enum DiskSpaceEvent {
+    /// 剩余空间低于`min_free_space_mb`，所有下载任务已暂停
+    #[serde(rename_all = "camelCase")]
+    Low {
+        available_mb: u64,
+        min_free_space_mb: u64,
+    },
+
+    /// 剩余空间已恢复，开启`auto_resume_on_space`时`resumed_count`为自动恢复的任务数量
+    #[serde(rename_all = "camelCase")]
+    Recovered {
+        available_mb: u64,
+        resumed_count: usize,
+    },
+}
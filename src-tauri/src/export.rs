@@ -1,27 +1,138 @@
 use std::{
+    collections::{HashMap, HashSet},
     ffi::OsStr,
-    io::{Read, Write},
+    io::{Cursor, Read, Write},
     path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, LazyLock,
+    },
+    time::SystemTime,
 };
 
 use anyhow::{anyhow, Context};
+use image::{imageops::FilterType, DynamicImage};
 use lopdf::{
     content::{Content, Operation},
-    dictionary, Document, Object, Stream,
+    dictionary, Document, Object, ObjectId, Stream,
 };
-use parking_lot::RwLock;
+use parking_lot::{Condvar, Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use specta::Type;
 use tauri::{AppHandle, Manager};
 use tauri_specta::Event;
 use zip::{write::SimpleFileOptions, ZipWriter};
 
 use crate::{
     config::Config,
-    events::{ExportCbzEvent, ExportPdfEvent},
+    errors::ExportCancelledError,
+    events::{ExportAllEvent, ExportCbzEvent, ExportPdfEvent},
+    extensions::AnyhowErrorToStringChain,
+    library,
     types::{Comic, ComicInfo},
+    utils::filename_filter,
+    wnacg_client::WnacgClient,
 };
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum Archive {
+/// 正在导出的漫画id
+static EXPORTING_COMIC_IDS: LazyLock<Mutex<HashSet<i64>>> = LazyLock::new(Mutex::default);
+
+/// 在导出期间将漫画id记录在`EXPORTING_COMIC_IDS`中，drop时移除
+struct ExportingGuard {
+    comic_id: i64,
+}
+
+impl ExportingGuard {
+    fn new(comic_id: i64) -> Self {
+        EXPORTING_COMIC_IDS.lock().insert(comic_id);
+        Self { comic_id }
+    }
+}
+
+impl Drop for ExportingGuard {
+    fn drop(&mut self) {
+        EXPORTING_COMIC_IDS.lock().remove(&self.comic_id);
+    }
+}
+
+/// 正在导出的pdf的取消标记，键为`ExportPdfEvent`中的uuid
+static PDF_CANCEL_FLAGS: LazyLock<Mutex<HashMap<String, Arc<AtomicBool>>>> =
+    LazyLock::new(Mutex::default);
+
+/// 在导出pdf期间将取消标记记录在`PDF_CANCEL_FLAGS`中，drop时移除
+struct PdfCancelGuard {
+    uuid: String,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl PdfCancelGuard {
+    fn new(uuid: &str) -> Self {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        PDF_CANCEL_FLAGS
+            .lock()
+            .insert(uuid.to_string(), cancelled.clone());
+        Self {
+            uuid: uuid.to_string(),
+            cancelled,
+        }
+    }
+}
+
+impl Drop for PdfCancelGuard {
+    fn drop(&mut self) {
+        PDF_CANCEL_FLAGS.lock().remove(&self.uuid);
+    }
+}
+
+/// 取消uuid为`uuid`的pdf导出，在当前图片处理完后停止，返回是否找到了该导出
+pub fn cancel_export(uuid: &str) -> bool {
+    let Some(cancelled) = PDF_CANCEL_FLAGS.lock().get(uuid).cloned() else {
+        return false;
+    };
+    cancelled.store(true, Ordering::Relaxed);
+    true
+}
+
+/// `export_all`是否被取消
+static EXPORT_ALL_CANCELLED: AtomicBool = AtomicBool::new(false);
+
+/// 正在进行的导出任务数量，与`EXPORT_SLOT_RELEASED`一起作为导出任务的信号量
+static RUNNING_EXPORT_COUNT: Mutex<usize> = Mutex::new(0);
+static EXPORT_SLOT_RELEASED: Condvar = Condvar::new();
+
+/// 导出任务的许可，drop时归还
+struct ExportPermit;
+
+impl ExportPermit {
+    /// 阻塞直到正在进行的导出任务数量小于`config.export_concurrency`
+    ///
+    /// 每次被唤醒时都重新读取配置，修改配置后立即生效
+    fn acquire(app: &AppHandle) -> Self {
+        let config = app.state::<RwLock<Config>>();
+        let mut running_count = RUNNING_EXPORT_COUNT.lock();
+        while *running_count >= config.read().export_concurrency {
+            EXPORT_SLOT_RELEASED.wait(&mut running_count);
+        }
+        *running_count += 1;
+        Self
+    }
+}
+
+impl Drop for ExportPermit {
+    fn drop(&mut self) {
+        *RUNNING_EXPORT_COUNT.lock() -= 1;
+        EXPORT_SLOT_RELEASED.notify_all();
+    }
+}
+
+/// 漫画是否正在导出
+pub fn is_exporting(comic_id: i64) -> bool {
+    EXPORTING_COMIC_IDS.lock().contains(&comic_id)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum Archive {
     Cbz,
     Pdf,
 }
@@ -37,7 +148,9 @@ impl Archive {
 
 #[allow(clippy::cast_possible_wrap)]
 #[allow(clippy::cast_possible_truncation)]
-pub fn cbz(app: &AppHandle, comic: Comic) -> anyhow::Result<()> {
+/// `cover_data`不为`None`时，将其作为第一页(`0000`)插入
+pub fn cbz(app: &AppHandle, comic: Comic, cover_data: Option<&[u8]>) -> anyhow::Result<()> {
+    let _exporting_guard = ExportingGuard::new(comic.id);
     let comic_title = &comic.title.clone();
     // 生成格式化的xml
     let cfg = yaserde::ser::Config {
@@ -51,9 +164,17 @@ pub fn cbz(app: &AppHandle, comic: Comic) -> anyhow::Result<()> {
         title: comic_title.clone(),
     }
     .emit(app);
+    // 等待其他导出任务完成，以免同时导出太多漫画
+    let _export_permit = ExportPermit::acquire(app);
 
     let comic_download_dir = get_comic_download_dir(app, &comic);
     let comic_export_dir = get_comic_export_dir(app, &comic);
+    let zip_path = get_export_path(app, &comic, Archive::Cbz);
+    let (dedup_pages, include_metadata_json) = {
+        let config = app.state::<RwLock<Config>>();
+        let config = config.read();
+        (config.dedup_pages, config.include_metadata_json)
+    };
     // 生成ComicInfo
     let comic_info = ComicInfo::from(comic);
     // 序列化ComicInfo为xml
@@ -63,8 +184,6 @@ pub fn cbz(app: &AppHandle, comic: Comic) -> anyhow::Result<()> {
     std::fs::create_dir_all(&comic_export_dir)
         .context(format!("`{comic_title}`创建目录`{comic_export_dir:?}`失败"))?;
     // 创建cbz文件
-    let extension = Archive::Cbz.extension();
-    let zip_path = comic_export_dir.join(format!("{comic_title}.{extension}"));
     let zip_file = std::fs::File::create(&zip_path)
         .context(format!("`{comic_title}`创建文件`{zip_path:?}`失败"))?;
     let mut zip_writer = ZipWriter::new(zip_file);
@@ -77,18 +196,55 @@ pub fn cbz(app: &AppHandle, comic: Comic) -> anyhow::Result<()> {
     zip_writer
         .write_all(comic_info_xml.as_bytes())
         .context(format!("`{comic_title}`写入`ComicInfo.xml`失败"))?;
+    // 把元数据.json写入cbz，没有元数据.json时跳过
+    let metadata_path = comic_download_dir.join("元数据.json");
+    if include_metadata_json && metadata_path.is_file() {
+        let metadata = std::fs::read(&metadata_path)
+            .context(format!("`{comic_title}`读取`{metadata_path:?}`失败"))?;
+        zip_writer
+            .start_file("元数据.json", SimpleFileOptions::default())
+            .context(format!(
+                "`{comic_title}在`{zip_path:?}`创建`元数据.json`失败"
+            ))?;
+        zip_writer
+            .write_all(&metadata)
+            .context(format!("`{comic_title}`写入`元数据.json`失败"))?;
+    }
     // 遍历下载目录，将文件写入cbz
-    let image_paths = std::fs::read_dir(&comic_download_dir)
+    let mut image_paths = std::fs::read_dir(&comic_download_dir)
         .context(format!(
             "`{comic_title}`读取目录`{comic_download_dir:?}`失败"
         ))?
         .filter_map(Result::ok)
         .map(|entry| entry.path())
-        .filter(|path| path.extension() != Some(OsStr::new("json"))); // 过滤掉元数据.json文件;
+        .filter(|path| path.extension() != Some(OsStr::new("json"))) // 过滤掉元数据.json文件
+        .collect::<Vec<_>>();
+    // 按文件名排序，这样才能检测出连续重复的图片
+    image_paths.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+    // 把封面作为第一页写入cbz
+    if let Some(cover_data) = cover_data.filter(|data| !is_cover_first_page(data, &image_paths)) {
+        let extension = image::guess_format(cover_data)
+            .ok()
+            .and_then(|format| format.extensions_str().first().copied())
+            .unwrap_or("jpg");
+        let filename = format!("0000.{extension}");
+        zip_writer
+            .start_file(&filename, SimpleFileOptions::default())
+            .context(format!(
+                "`{comic_title}在`{zip_path:?}`创建`{filename}`失败"
+            ))?;
+        zip_writer
+            .write_all(cover_data)
+            .context(format!("`{comic_title}`将封面写入`{zip_path:?}`失败"))?;
+    }
+    let mut deduplicator = PageDeduplicator::new(dedup_pages);
     for image_path in image_paths {
         if !image_path.is_file() {
             continue;
         }
+        if deduplicator.is_duplicate(&image_path)? {
+            continue;
+        }
 
         let filename = match image_path.file_name() {
             Some(name) => name.to_string_lossy(),
@@ -110,39 +266,68 @@ pub fn cbz(app: &AppHandle, comic: Comic) -> anyhow::Result<()> {
     zip_writer
         .finish()
         .context(format!("`{comic_title}`关闭`{zip_path:?}`失败"))?;
+    deduplicator.log_skipped_count(&zip_path);
     // 发送导出cbz完成事件
     let _ = ExportCbzEvent::End { uuid: event_uuid }.emit(app);
 
     Ok(())
 }
 
-pub fn pdf(app: &AppHandle, comic: &Comic) -> anyhow::Result<()> {
+/// `cover_data`不为`None`时，将其作为第一页插入
+///
+/// 被`cancel_export`取消时返回`ExportCancelledError`，不会留下不完整的pdf
+pub fn pdf(app: &AppHandle, comic: &Comic, cover_data: Option<&[u8]>) -> anyhow::Result<()> {
+    let _exporting_guard = ExportingGuard::new(comic.id);
     let title = &comic.title;
     let event_uuid = uuid::Uuid::new_v4().to_string();
+    let cancel_guard = PdfCancelGuard::new(&event_uuid);
     // 发送开始创建pdf事件
     let _ = ExportPdfEvent::Start {
         uuid: event_uuid.clone(),
         title: title.clone(),
     }
     .emit(app);
+    // 等待其他导出任务完成，以免同时导出太多漫画
+    let _export_permit = ExportPermit::acquire(app);
     let comic_download_dir = get_comic_download_dir(app, comic);
     let comic_export_dir = get_comic_export_dir(app, comic);
     // 保证导出目录存在
     std::fs::create_dir_all(&comic_export_dir)
         .context(format!("创建目录`{comic_export_dir:?}`失败"))?;
     // 创建pdf
-    let extension = Archive::Pdf.extension();
-    let pdf_path = comic_export_dir.join(format!("{title}.{extension}"));
-    create_pdf(&comic_download_dir, &pdf_path).context("创建pdf失败")?;
+    let pdf_path = get_export_path(app, comic, Archive::Pdf);
+    let dedup_pages = app.state::<RwLock<Config>>().read().dedup_pages;
+    let result = create_pdf(
+        &comic_download_dir,
+        &pdf_path,
+        dedup_pages,
+        cover_data,
+        &cancel_guard.cancelled,
+    );
+    if let Err(err) = result {
+        if err.is::<ExportCancelledError>() {
+            let _ = ExportPdfEvent::Cancelled { uuid: event_uuid }.emit(app);
+            return Err(err);
+        }
+        return Err(err.context("创建pdf失败"));
+    }
     // 发送创建pdf完成事件
     let _ = ExportPdfEvent::End { uuid: event_uuid }.emit(app);
     Ok(())
 }
 
 /// 用`comic_download_dir`中的图片创建PDF，保存到`pdf_path`中
+///
+/// 每处理一张图片前检查`cancelled`，被取消时不保存pdf
 #[allow(clippy::similar_names)]
 #[allow(clippy::cast_possible_truncation)]
-fn create_pdf(comic_download_dir: &Path, pdf_path: &Path) -> anyhow::Result<()> {
+fn create_pdf(
+    comic_download_dir: &Path,
+    pdf_path: &Path,
+    dedup_pages: bool,
+    cover_data: Option<&[u8]>,
+    cancelled: &AtomicBool,
+) -> anyhow::Result<()> {
     let mut image_paths = std::fs::read_dir(comic_download_dir)
         .context(format!("读取目录`{comic_download_dir:?}`失败"))?
         .filter_map(Result::ok)
@@ -154,51 +339,36 @@ fn create_pdf(comic_download_dir: &Path, pdf_path: &Path) -> anyhow::Result<()>
     let mut doc = Document::with_version("1.5");
     let pages_id = doc.new_object_id();
     let mut page_ids = vec![];
+    let mut deduplicator = PageDeduplicator::new(dedup_pages);
+
+    if let Some(cover_data) = cover_data.filter(|data| !is_cover_first_page(data, &image_paths)) {
+        let (width, height) = image::ImageReader::new(Cursor::new(cover_data))
+            .with_guessed_format()
+            .context("识别封面的格式失败")?
+            .into_dimensions()
+            .context("获取封面的尺寸失败")?;
+        let page_id = add_image_page(&mut doc, pages_id, cover_data.to_vec(), width, height)
+            .context("将封面添加到pdf失败")?;
+        page_ids.push(page_id);
+    }
 
     for image_path in image_paths {
+        if cancelled.load(Ordering::Relaxed) {
+            return Err(ExportCancelledError.into());
+        }
         if !image_path.is_file() {
             continue;
         }
+        if deduplicator.is_duplicate(&image_path)? {
+            continue;
+        }
 
         let buffer = read_image_to_buffer(&image_path)
             .context(format!("将`{image_path:?}`读取到buffer失败"))?;
         let (width, height) = image::image_dimensions(&image_path)
             .context(format!("获取`{image_path:?}`的尺寸失败"))?;
-        let image_stream = lopdf::xobject::image_from(buffer)
-            .context(format!("创建`{image_path:?}`的图片流失败"))?;
-        // 将图片流添加到doc中
-        let img_id = doc.add_object(image_stream);
-        // 图片的名称，用于 Do 操作在页面上显示图片
-        let img_name = format!("X{}", img_id.0);
-        // 用于设置图片在页面上的位置和大小
-        let cm_operation = Operation::new(
-            "cm",
-            vec![
-                width.into(),
-                0.into(),
-                0.into(),
-                height.into(),
-                0.into(),
-                0.into(),
-            ],
-        );
-        // 用于显示图片
-        let do_operation = Operation::new("Do", vec![Object::Name(img_name.as_bytes().to_vec())]);
-        // 创建页面，设置图片的位置和大小，然后显示图片
-        // 因为是从零开始创建PDF，所以没必要用 q 和 Q 操作保存和恢复图形状态
-        let content = Content {
-            operations: vec![cm_operation, do_operation],
-        };
-        let content_id = doc.add_object(Stream::new(dictionary! {}, content.encode()?));
-        let page_id = doc.add_object(dictionary! {
-            "Type" => "Page",
-            "Parent" => pages_id,
-            "Contents" => content_id,
-            "MediaBox" => vec![0.into(), 0.into(), width.into(), height.into()],
-        });
-        // 将图片以 XObject 的形式添加到文档中
-        // Do 操作只能引用 XObject(所以前面定义的 Do 操作的参数是 img_name, 而不是 img_id)
-        doc.add_xobject(page_id, img_name.as_bytes(), img_id)?;
+        let page_id = add_image_page(&mut doc, pages_id, buffer, width, height)
+            .context(format!("将`{image_path:?}`添加到pdf失败"))?;
         // 记录新创建的页面的 ID
         page_ids.push(page_id);
     }
@@ -218,11 +388,180 @@ fn create_pdf(comic_download_dir: &Path, pdf_path: &Path) -> anyhow::Result<()>
 
     doc.compress();
 
+    if cancelled.load(Ordering::Relaxed) {
+        return Err(ExportCancelledError.into());
+    }
     doc.save(pdf_path)
         .context(format!("保存`{pdf_path:?}`失败"))?;
+    deduplicator.log_skipped_count(pdf_path);
     Ok(())
 }
 
+/// 在`doc`中创建一个只显示一张图片的页面，返回页面的 ID
+fn add_image_page(
+    doc: &mut Document,
+    pages_id: ObjectId,
+    buffer: Vec<u8>,
+    width: u32,
+    height: u32,
+) -> anyhow::Result<ObjectId> {
+    let image_stream = lopdf::xobject::image_from(buffer).context("创建图片流失败")?;
+    // 将图片流添加到doc中
+    let img_id = doc.add_object(image_stream);
+    // 图片的名称，用于 Do 操作在页面上显示图片
+    let img_name = format!("X{}", img_id.0);
+    // 用于设置图片在页面上的位置和大小
+    let cm_operation = Operation::new(
+        "cm",
+        vec![
+            width.into(),
+            0.into(),
+            0.into(),
+            height.into(),
+            0.into(),
+            0.into(),
+        ],
+    );
+    // 用于显示图片
+    let do_operation = Operation::new("Do", vec![Object::Name(img_name.as_bytes().to_vec())]);
+    // 创建页面，设置图片的位置和大小，然后显示图片
+    // 因为是从零开始创建PDF，所以没必要用 q 和 Q 操作保存和恢复图形状态
+    let content = Content {
+        operations: vec![cm_operation, do_operation],
+    };
+    let content_id = doc.add_object(Stream::new(dictionary! {}, content.encode()?));
+    let page_id = doc.add_object(dictionary! {
+        "Type" => "Page",
+        "Parent" => pages_id,
+        "Contents" => content_id,
+        "MediaBox" => vec![0.into(), 0.into(), width.into(), height.into()],
+    });
+    // 将图片以 XObject 的形式添加到文档中
+    // Do 操作只能引用 XObject(所以前面定义的 Do 操作的参数是 img_name, 而不是 img_id)
+    doc.add_xobject(page_id, img_name.as_bytes(), img_id)?;
+    Ok(page_id)
+}
+
+/// 开启了`cover_as_first_page`时获取封面，优先使用缓存中的封面
+///
+/// 获取失败时只记录日志，导出时不插入封面
+pub async fn get_cover_page(app: &AppHandle, comic: &Comic) -> Option<Vec<u8>> {
+    if !app.state::<RwLock<Config>>().read().cover_as_first_page {
+        return None;
+    }
+    match app
+        .state::<WnacgClient>()
+        .get_cover_data(&comic.cover)
+        .await
+    {
+        Ok(cover_data) => Some(cover_data.to_vec()),
+        Err(err) => {
+            let err_title = format!("`{}`获取封面失败，导出时不插入封面", comic.title);
+            let string_chain = err.to_string_chain();
+            tracing::warn!(err_title, message = string_chain);
+            None
+        }
+    }
+}
+
+/// 比较封面与第一张图片时，两者都缩小到这个边长的灰度图
+const COVER_COMPARE_SIZE: u32 = 32;
+/// 缩小后的灰度图每个像素的平均差异不超过这个值时，视为同一张图
+const COVER_MAX_MEAN_DIFF: u32 = 12;
+/// 宽高比的相对差异超过这个值时，直接视为不同的图
+const COVER_MAX_ASPECT_RATIO_DIFF: f64 = 0.05;
+
+/// 封面与第一张图片是同一张图时，不需要再插入封面
+///
+/// 封面是网站生成的缩略图，内容不会与原图完全相同，所以解码后用`is_same_picture`比较
+fn is_cover_first_page(cover_data: &[u8], image_paths: &[PathBuf]) -> bool {
+    let Some(first_image_path) = image_paths.iter().find(|path| path.is_file()) else {
+        return false;
+    };
+    let Ok(first_image_data) = std::fs::read(first_image_path) else {
+        return false;
+    };
+    if cover_data == first_image_data {
+        return true;
+    }
+    let (Ok(cover), Ok(first_image)) = (
+        image::load_from_memory(cover_data),
+        image::load_from_memory(&first_image_data),
+    ) else {
+        return false;
+    };
+    is_same_picture(&cover, &first_image)
+}
+
+/// 宽高比相近，且缩小到`COVER_COMPARE_SIZE`的灰度图后平均每个像素的差异不超过`COVER_MAX_MEAN_DIFF`
+fn is_same_picture(a: &DynamicImage, b: &DynamicImage) -> bool {
+    if a.width() == 0 || a.height() == 0 || b.width() == 0 || b.height() == 0 {
+        return false;
+    }
+    let aspect_ratio = |img: &DynamicImage| f64::from(img.width()) / f64::from(img.height());
+    if (aspect_ratio(a) / aspect_ratio(b) - 1.0).abs() > COVER_MAX_ASPECT_RATIO_DIFF {
+        return false;
+    }
+    let shrink = |img: &DynamicImage| {
+        img.resize_exact(COVER_COMPARE_SIZE, COVER_COMPARE_SIZE, FilterType::Triangle)
+            .to_luma8()
+    };
+    let (a, b) = (shrink(a), shrink(b));
+    let total_diff: u32 = a
+        .pixels()
+        .zip(b.pixels())
+        .map(|(pa, pb)| u32::from(pa[0].abs_diff(pb[0])))
+        .sum();
+    total_diff <= COVER_MAX_MEAN_DIFF * COVER_COMPARE_SIZE * COVER_COMPARE_SIZE
+}
+
+/// 导出时用于跳过连续重复的图片
+///
+/// 只有内容完全相同(SHA-256相同)的图片才会被视为重复，视觉上相似的图片不会被跳过
+struct PageDeduplicator {
+    enabled: bool,
+    last_hash: Option<Vec<u8>>,
+    skipped_count: usize,
+}
+
+impl PageDeduplicator {
+    fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            last_hash: None,
+            skipped_count: 0,
+        }
+    }
+
+    /// `image_path`的内容是否与上一张图片完全相同
+    fn is_duplicate(&mut self, image_path: &Path) -> anyhow::Result<bool> {
+        if !self.enabled {
+            return Ok(false);
+        }
+        let mut file =
+            std::fs::File::open(image_path).context(format!("打开`{image_path:?}`失败"))?;
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut file, &mut hasher)
+            .context(format!("计算`{image_path:?}`的哈希值失败"))?;
+        let hash = hasher.finalize().to_vec();
+
+        let is_duplicate = self.last_hash.as_ref() == Some(&hash);
+        if is_duplicate {
+            self.skipped_count += 1;
+            tracing::debug!("`{image_path:?}`与上一张图片完全相同，跳过");
+        }
+        self.last_hash = Some(hash);
+        Ok(is_duplicate)
+    }
+
+    fn log_skipped_count(&self, export_path: &Path) {
+        if self.skipped_count > 0 {
+            let skipped_count = self.skipped_count;
+            tracing::info!("导出`{export_path:?}`时跳过了`{skipped_count}`张重复的图片");
+        }
+    }
+}
+
 /// 读取`image_path`中的图片数据到buffer中
 fn read_image_to_buffer(image_path: &Path) -> anyhow::Result<Vec<u8>> {
     let file = std::fs::File::open(image_path).context(format!("打开`{image_path:?}`失败"))?;
@@ -247,3 +586,175 @@ fn get_comic_export_dir(app: &AppHandle, comic: &Comic) -> PathBuf {
         .export_dir
         .join(&comic.title)
 }
+
+pub fn cancel_export_all() {
+    EXPORT_ALL_CANCELLED.store(true, Ordering::Relaxed);
+}
+
+/// 将所有已下载的漫画导出为`archive`格式，已经是最新的导出文件会被跳过
+///
+/// 逐个导出，单个漫画导出失败不影响其他漫画，被`cancel_export_all`取消时在当前漫画导出完成后停止
+#[allow(clippy::cast_possible_truncation)]
+pub async fn export_all(app: &AppHandle, archive: Archive) -> anyhow::Result<()> {
+    EXPORT_ALL_CANCELLED.store(false, Ordering::Relaxed);
+
+    let download_dir = app.state::<RwLock<Config>>().read().download_dir.clone();
+    let comics = library::get_downloaded_comics(app, &download_dir)?;
+
+    let total = comics.len() as u32;
+    let _ = ExportAllEvent::Start { total }.emit(app);
+
+    let (mut exported_count, mut skipped_count, mut failed_count) = (0, 0, 0);
+    let mut cancelled = false;
+    for (i, comic) in comics.into_iter().enumerate() {
+        if EXPORT_ALL_CANCELLED.load(Ordering::Relaxed) {
+            cancelled = true;
+            break;
+        }
+        let title = comic.title.clone();
+        let skipped = is_export_up_to_date(app, &comic, archive);
+        let _ = ExportAllEvent::Progress {
+            title: title.clone(),
+            current: i as u32 + 1,
+            total,
+            skipped,
+        }
+        .emit(app);
+        if skipped {
+            skipped_count += 1;
+            continue;
+        }
+
+        let cover_data = get_cover_page(app, &comic).await;
+        let app = app.clone();
+        let result = tauri::async_runtime::spawn_blocking(move || match archive {
+            Archive::Cbz => cbz(&app, comic, cover_data.as_deref()),
+            Archive::Pdf => pdf(&app, &comic, cover_data.as_deref()),
+        })
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(|result| result);
+
+        match result {
+            Ok(()) => exported_count += 1,
+            // 单独取消了这部漫画的导出，继续导出其他漫画
+            Err(err) if err.is::<ExportCancelledError>() => skipped_count += 1,
+            Err(err) => {
+                failed_count += 1;
+                let extension = archive.extension();
+                let err_title = format!("批量导出时，漫画`{title}`导出{extension}失败");
+                let string_chain = err.to_string_chain();
+                tracing::error!(err_title, message = string_chain);
+            }
+        }
+    }
+
+    let _ = ExportAllEvent::End {
+        exported_count,
+        skipped_count,
+        failed_count,
+        cancelled,
+    }
+    .emit(app);
+
+    Ok(())
+}
+
+/// 导出文件是否存在且比下载目录中的所有文件都新
+fn is_export_up_to_date(app: &AppHandle, comic: &Comic, archive: Archive) -> bool {
+    let export_path = get_export_path(app, comic, archive);
+    let Some(export_modified) = modified_time(&export_path) else {
+        return false;
+    };
+    let comic_download_dir = get_comic_download_dir(app, comic);
+    let Ok(entries) = std::fs::read_dir(&comic_download_dir) else {
+        return false;
+    };
+    // 目录本身的修改时间反映了文件的增删，文件的修改时间反映了文件内容的变化
+    let download_modified = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| modified_time(&entry.path()))
+        .chain(modified_time(&comic_download_dir))
+        .max();
+    download_modified.is_some_and(|download_modified| export_modified > download_modified)
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    path.metadata().ok()?.modified().ok()
+}
+
+/// 漫画导出为`archive`格式后的文件路径
+pub fn get_export_path(app: &AppHandle, comic: &Comic, archive: Archive) -> PathBuf {
+    let comic_export_dir = get_comic_export_dir(app, comic);
+    let export_name = get_export_name(app, comic);
+    let extension = archive.extension();
+    comic_export_dir.join(format!("{export_name}.{extension}"))
+}
+
+/// 用`config.export_name_template`生成导出文件名(不含扩展名)
+fn get_export_name(app: &AppHandle, comic: &Comic) -> String {
+    let template = app
+        .state::<RwLock<Config>>()
+        .read()
+        .export_name_template
+        .clone();
+    // 每个占位符的值都单独过滤，以免生成非法的文件名
+    let export_name = template
+        .replace("{id}", &comic.id.to_string())
+        .replace("{title}", &filename_filter(&comic.title))
+        .replace("{category}", &filename_filter(&comic.category));
+    let export_name = filename_filter(&export_name);
+    // 模板生成的文件名为空时，使用漫画标题
+    if export_name.is_empty() {
+        return comic.title.clone();
+    }
+    export_name
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{ImageFormat, RgbImage};
+
+    use super::*;
+
+    /// 宽`width`高`height`的渐变图，`invert`为`true`时颜色反转
+    fn gradient(width: u32, height: u32, invert: bool) -> DynamicImage {
+        let img = RgbImage::from_fn(width, height, |x, y| {
+            let value = u8::try_from(x * 127 / width + y * 127 / height).unwrap();
+            let value = if invert { 255 - value } else { value };
+            image::Rgb([value, value / 2, 255 - value])
+        });
+        DynamicImage::ImageRgb8(img)
+    }
+
+    /// 模拟网站生成的缩略图：缩小后再用jpeg重新编码
+    fn thumbnail(img: &DynamicImage, width: u32, height: u32) -> DynamicImage {
+        let mut data = Vec::new();
+        img.resize_exact(width, height, FilterType::Triangle)
+            .to_rgb8()
+            .write_to(&mut Cursor::new(&mut data), ImageFormat::Jpeg)
+            .unwrap();
+        image::load_from_memory(&data).unwrap()
+    }
+
+    #[test]
+    fn is_same_picture_matches_thumbnail_of_first_page() {
+        let first_page = gradient(800, 1200, false);
+        let cover = thumbnail(&first_page, 200, 299);
+        assert!(is_same_picture(&cover, &first_page));
+    }
+
+    #[test]
+    fn is_same_picture_rejects_different_content() {
+        let first_page = gradient(800, 1200, false);
+        let cover = thumbnail(&gradient(800, 1200, true), 200, 300);
+        assert!(!is_same_picture(&cover, &first_page));
+    }
+
+    #[test]
+    fn is_same_picture_rejects_different_aspect_ratio() {
+        let first_page = gradient(800, 1200, false);
+        let cover = thumbnail(&first_page, 200, 200);
+        assert!(!is_same_picture(&cover, &first_page));
+    }
+}
@@ -1,6 +1,3 @@
-use anyhow::anyhow;
-use scraper::error::SelectorErrorKind;
-
 pub trait AnyhowErrorToStringChain {
     /// 将 `anyhow::Error` 转换为chain格式  
     /// # Example  
@@ -21,13 +18,3 @@ impl AnyhowErrorToStringChain for anyhow::Error {
             })
     }
 }
-
-pub trait ToAnyhow<T> {
-    fn to_anyhow(self) -> anyhow::Result<T>;
-}
-
-impl<T> ToAnyhow<T> for Result<T, SelectorErrorKind<'_>> {
-    fn to_anyhow(self) -> anyhow::Result<T> {
-        self.map_err(|e| anyhow!(e.to_string()))
-    }
-}
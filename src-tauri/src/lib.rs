@@ -1,11 +1,25 @@
+mod bandwidth_stats;
+mod batch_download;
+mod check_in;
 mod commands;
 mod config;
+mod cookie_store;
+mod cover_cache;
+mod diagnostics;
 mod download_manager;
 mod errors;
 mod events;
 mod export;
 mod extensions;
+mod library;
 mod logger;
+mod metadata_store;
+mod metered_network;
+mod notifier;
+mod retry_strategy;
+mod search_cache;
+mod shelf_watcher;
+mod subscription;
 mod types;
 mod utils;
 mod wnacg_client;
@@ -14,10 +28,16 @@ use anyhow::Context;
 use config::Config;
 use download_manager::DownloadManager;
 use events::{
-    DownloadSleepingEvent, DownloadSpeedEvent, DownloadTaskEvent, ExportCbzEvent, ExportPdfEvent,
-    LogEvent,
+    AuthRequiredEvent, CheckInEvent, ComicDeletedEvent, ConfigChangedEvent, DiskSpaceEvent,
+    DownloadDirChangedEvent, DownloadSleepingEvent, DownloadSpeedEvent, DownloadTaskCreatedEvent,
+    DownloadTaskEvent, DownloadTaskRemovedEvent, ExportAllEvent, ExportCbzEvent, ExportPdfEvent,
+    LogEvent, MeteredNetworkEvent, MigrateLibraryEvent, NewComicsEvent, SessionRefreshedEvent,
+    ShelfComicsQueuedEvent, VerifyLibraryEvent, WaitingForScheduleEvent,
 };
+use extensions::AnyhowErrorToStringChain;
+use library::{LibraryIndex, LibraryMigrationState};
 use parking_lot::RwLock;
+use subscription::Subscriptions;
 use tauri::{Manager, Wry};
 use wnacg_client::WnacgClient;
 
@@ -31,33 +51,102 @@ fn generate_context() -> tauri::Context<Wry> {
 pub fn run() {
     let builder = tauri_specta::Builder::<Wry>::new()
         .commands(tauri_specta::collect_commands![
-            greet,
+            get_app_version,
             get_config,
             save_config,
+            set_log_level,
+            validate_config,
+            reset_config,
+            backup_config,
+            restore_config,
             login,
+            save_credentials,
+            delete_credentials,
             get_user_profile,
+            check_in,
             search_by_keyword,
             search_by_tag,
             get_comic,
+            get_comic_images,
+            extract_comic_ids,
             get_favorite,
+            get_all_favorites,
+            diff_favorites_against_library,
             create_download_task,
+            create_download_tasks_from_input,
+            download_search_page,
+            get_download_task,
             pause_download_task,
             resume_download_task,
+            pause_all_download_tasks,
+            resume_all_download_tasks,
+            force_start_download_task,
+            set_power_state,
             cancel_download_task,
+            clear_finished_download_tasks,
+            clear_completed_download_tasks,
+            remove_download_task,
             get_downloaded_comics,
+            refresh_library,
+            get_library_disk_usage,
+            find_duplicate_comics,
+            import_local_comic,
+            get_bandwidth_stats,
             export_pdf,
             export_cbz,
+            export_all_downloaded,
+            cancel_export_all_downloaded,
+            cancel_export,
             get_logs_dir_size,
+            get_log_files,
+            clear_logs,
+            get_buffered_logs,
+            query_logs,
+            open_log_dir,
+            export_diagnostic_bundle,
             show_path_in_file_manager,
+            show_comic_in_file_manager,
+            show_export_in_file_manager,
             get_cover_data,
+            get_comic_preview,
+            migrate_library,
+            refresh_comic_metadata,
+            delete_downloaded_comic,
+            get_comic_pages,
+            get_comic_page_dimensions,
+            search_library,
+            list_orphan_temp_dirs,
+            clean_orphan_temp_dirs,
+            verify_library,
+            cancel_verify_library,
+            rename_downloaded_comic,
+            list_subscriptions,
+            add_subscription,
+            remove_subscription,
         ])
         .events(tauri_specta::collect_events![
             LogEvent,
             DownloadTaskEvent,
+            DownloadTaskCreatedEvent,
+            DownloadTaskRemovedEvent,
             DownloadSpeedEvent,
             ExportPdfEvent,
             ExportCbzEvent,
+            ExportAllEvent,
             DownloadSleepingEvent,
+            DownloadDirChangedEvent,
+            MigrateLibraryEvent,
+            ConfigChangedEvent,
+            ComicDeletedEvent,
+            VerifyLibraryEvent,
+            NewComicsEvent,
+            ShelfComicsQueuedEvent,
+            DiskSpaceEvent,
+            WaitingForScheduleEvent,
+            AuthRequiredEvent,
+            SessionRefreshedEvent,
+            CheckInEvent,
+            MeteredNetworkEvent,
         ]);
 
     #[cfg(debug_assertions)]
@@ -74,20 +163,25 @@ pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_opener::init())
+        .plugin(tauri_plugin_notification::init())
         .invoke_handler(builder.invoke_handler())
         .setup(move |app| {
             builder.mount_events(app);
 
-            let app_data_dir = app
-                .path()
-                .app_data_dir()
-                .context("获取app_data_dir目录失败")?;
+            // 便携模式下数据目录为exe所在目录，否则为app_data_dir
+            let data_dir = utils::data_dir(app.handle()).context("获取数据目录失败")?;
 
-            std::fs::create_dir_all(&app_data_dir)
-                .context(format!("创建app_data_dir目录`{app_data_dir:?}`失败"))?;
+            std::fs::create_dir_all(&data_dir)
+                .context(format!("创建数据目录`{data_dir:?}`失败"))?;
 
-            let config = RwLock::new(Config::new(app.handle())?);
-            app.manage(config);
+            let config = Config::new(app.handle())?;
+            library::allow_asset_access(app.handle(), &config.download_dir);
+            cover_cache::allow_asset_access(app.handle());
+            app.manage(RwLock::new(config));
+
+            app.manage(RwLock::new(LibraryMigrationState::default()));
+
+            app.manage(RwLock::new(LibraryIndex::default()));
 
             let wnacg_client = WnacgClient::new(app.handle().clone());
             app.manage(wnacg_client);
@@ -97,8 +191,36 @@ pub fn run() {
 
             logger::init(app.handle())?;
 
+            config::check_download_dir_on_startup(app.handle());
+
+            // 漫画很多时重建索引比较耗时，放到后台进行，以免拖慢启动
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn_blocking(move || library::rebuild_index(&app_handle));
+
+            let subscriptions = Subscriptions::load(app.handle()).unwrap_or_else(|err| {
+                tracing::error!("读取订阅失败，将使用空的订阅列表: {err:#}");
+                Subscriptions::default()
+            });
+            app.manage(RwLock::new(subscriptions));
+            tauri::async_runtime::spawn(subscription::check_subscriptions_loop(
+                app.handle().clone(),
+            ));
+            tauri::async_runtime::spawn(shelf_watcher::watch_shelf_loop(app.handle().clone()));
+            tauri::async_runtime::spawn(bandwidth_stats::flush_loop(app.handle().clone()));
+            tauri::async_runtime::spawn(check_in::auto_check_in_loop(app.handle().clone()));
+            tauri::async_runtime::spawn(metered_network::watch_metered_loop(app.handle().clone()));
+
             Ok(())
         })
-        .run(generate_context())
-        .expect("error while running tauri application");
+        .build(generate_context())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            if let tauri::RunEvent::Exit = event {
+                if let Err(err) = bandwidth_stats::flush(app) {
+                    let err_title = "退出时保存流量统计失败";
+                    let string_chain = err.to_string_chain();
+                    tracing::error!(err_title, message = string_chain);
+                }
+            }
+        });
 }
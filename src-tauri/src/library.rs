@@ -0,0 +1,1228 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+    time::SystemTime,
+};
+
+use anyhow::{anyhow, Context};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri::{AppHandle, Manager};
+use tauri_specta::Event;
+
+use crate::{
+    config::Config,
+    events::{MigrateLibraryEvent, VerifyLibraryEvent},
+    extensions::AnyhowErrorToStringChain,
+    metadata_store,
+    types::{Comic, ComicInFavorite, GetAllFavoritesResult},
+    utils::{self, filename_filter},
+};
+
+/// 已下载漫画中的一页
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct ComicPage {
+    /// 页码，从1开始
+    pub index: u32,
+    /// 图片的绝对路径，前端用`convertFileSrc`转换后即可在<img>中显示
+    pub path: PathBuf,
+}
+
+/// 修改下载目录后，记录旧的下载目录，等待用户决定是否迁移
+#[derive(Default)]
+pub struct LibraryMigrationState {
+    pub old_download_dir: Option<PathBuf>,
+}
+
+/// 已下载漫画的内存索引，避免每次搜索都读取所有元数据文件
+#[derive(Default)]
+pub struct LibraryIndex {
+    /// 小写的标题和对应的漫画，按元数据的修改时间排序，最新的排在最前面
+    entries: Vec<(String, Comic)>,
+    /// 所有已下载漫画的id
+    comic_ids: HashSet<i64>,
+    /// 元数据文件的路径到修改时间和漫画的映射，重建索引时修改时间没变的元数据文件不需要重新读取
+    metadata_cache: HashMap<PathBuf, (SystemTime, Comic)>,
+    /// 漫画目录的路径到修改时间和占用磁盘空间的映射，重建索引时修改时间没变的目录不需要重新遍历
+    size_cache: HashMap<PathBuf, (SystemTime, u64)>,
+    /// 是否已经完成过至少一次重建，启动时在后台重建，完成前索引是空的
+    is_ready: bool,
+}
+
+impl LibraryIndex {
+    pub fn contains(&self, comic_id: i64) -> bool {
+        self.comic_ids.contains(&comic_id)
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.is_ready
+    }
+
+    /// 给本地导入的漫画分配的id，为负数且不与已有的漫画重复
+    pub fn next_local_comic_id(&self) -> i64 {
+        self.comic_ids.iter().copied().min().unwrap_or(0).min(0) - 1
+    }
+
+    /// 所有已下载的漫画，最新下载的排在最前面
+    pub fn comics(&self) -> Vec<Comic> {
+        self.entries
+            .iter()
+            .map(|(_, comic)| comic.clone())
+            .collect()
+    }
+
+    /// 所有已下载的漫画，按`sort_by`排序
+    pub fn sorted_comics(&self, sort_by: LibrarySortBy) -> Vec<Comic> {
+        let mut entries: Vec<&(String, Comic)> = self.entries.iter().collect();
+        match sort_by {
+            // `entries`本身就是按下载时间排序的
+            LibrarySortBy::DownloadTime => {}
+            LibrarySortBy::Title => entries.sort_by(|(a, _), (b, _)| a.cmp(b)),
+            LibrarySortBy::Size => entries.sort_by(|(_, a), (_, b)| {
+                b.disk_size_bytes
+                    .unwrap_or(0)
+                    .cmp(&a.disk_size_bytes.unwrap_or(0))
+            }),
+        }
+        entries
+            .into_iter()
+            .map(|(_, comic)| comic.clone())
+            .collect()
+    }
+
+    /// 已下载漫画的标题(即目录名)到id的映射
+    pub fn comic_ids_by_title(&self) -> HashMap<String, i64> {
+        self.entries
+            .iter()
+            .map(|(_, comic)| (comic.title.clone(), comic.id))
+            .collect()
+    }
+
+    /// 标题不区分大小写地包含`query`，且标签名和分类完全匹配(为`None`时不限制)
+    pub fn search(&self, query: &str, tag: Option<&str>, category: Option<&str>) -> Vec<Comic> {
+        let query = query.to_lowercase();
+        self.entries
+            .iter()
+            .filter(|(title, _)| title.contains(&query))
+            .filter(|(_, comic)| tag.is_none_or(|tag| comic.tags.iter().any(|t| t.name == tag)))
+            .filter(|(_, comic)| category.is_none_or(|category| comic.category == category))
+            .map(|(_, comic)| comic.clone())
+            .collect()
+    }
+
+    /// 从索引中移除一部漫画，同时移除它在缓存中的条目
+    pub fn remove(&mut self, comic_id: i64) {
+        self.remove_where(|comic| comic.id == comic_id);
+    }
+
+    /// 加入或替换一部漫画，id相同或标题(即目录名)不区分大小写地相同的旧条目会被移除
+    ///
+    /// 刚写入的漫画就是最新修改的，放在最前面
+    fn upsert(
+        &mut self,
+        comic: Comic,
+        metadata: Option<(PathBuf, SystemTime)>,
+        size: Option<(PathBuf, SystemTime, u64)>,
+    ) {
+        let comic_id = comic.id;
+        let lowercase_title = comic.title.to_lowercase();
+        self.remove_where(|c| c.id == comic_id || c.title.to_lowercase() == lowercase_title);
+        if let Some((metadata_path, modify_time)) = metadata {
+            self.metadata_cache
+                .insert(metadata_path, (modify_time, comic.clone()));
+        }
+        if let Some((comic_dir, modify_time, size)) = size {
+            self.size_cache.insert(comic_dir, (modify_time, size));
+        }
+        self.comic_ids.insert(comic_id);
+        self.entries.insert(0, (lowercase_title, comic));
+    }
+
+    fn remove_where(&mut self, predicate: impl Fn(&Comic) -> bool) {
+        let removed_titles: HashSet<String> = self
+            .entries
+            .iter()
+            .filter(|(_, comic)| predicate(comic))
+            .map(|(_, comic)| comic.title.clone())
+            .collect();
+        self.entries.retain(|(_, comic)| !predicate(comic));
+        self.metadata_cache
+            .retain(|_, (_, comic)| !predicate(comic));
+        self.size_cache.retain(|comic_dir, _| {
+            comic_dir
+                .file_name()
+                .is_none_or(|name| !removed_titles.contains(name.to_string_lossy().as_ref()))
+        });
+        self.comic_ids = self.entries.iter().map(|(_, comic)| comic.id).collect();
+    }
+}
+
+/// 已下载漫画列表的排序方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum LibrarySortBy {
+    /// 按标题排序，不区分大小写
+    Title,
+    /// 最新下载的排在最前面
+    DownloadTime,
+    /// 占用磁盘空间最大的排在最前面
+    Size,
+}
+
+/// 重新扫描下载目录，增量地重建`LibraryIndex`
+///
+/// 只重新读取新增或修改时间变化的元数据文件，只重新遍历修改时间变化的漫画目录，已删除的漫画会从索引中移除  
+/// 迁移漫画、修改下载目录或用户要求刷新时调用，只涉及一部漫画时用`upsert_index`或`LibraryIndex::remove`  
+/// 需要遍历漫画目录计算占用的磁盘空间，在异步上下文中应在`spawn_blocking`中调用
+pub fn rebuild_index(app: &AppHandle) {
+    let start = std::time::Instant::now();
+    let download_dir = app.state::<RwLock<Config>>().read().download_dir.clone();
+    let metadata_files = match list_metadata_files(&download_dir) {
+        Ok(metadata_files) => metadata_files,
+        Err(err) => {
+            let err_title = "重建已下载漫画的索引失败";
+            let string_chain = err.to_string_chain();
+            tracing::error!(err_title, message = string_chain);
+            return;
+        }
+    };
+
+    let index = app.state::<RwLock<LibraryIndex>>();
+    // 先取出修改时间没变的漫画，读取其他元数据文件时不持有锁
+    let (cached_comics, cached_sizes): (Vec<Option<Comic>>, _) = {
+        let index = index.read();
+        let cached_comics = metadata_files
+            .iter()
+            .map(
+                |(metadata_path, modify_time)| match index.metadata_cache.get(metadata_path) {
+                    Some((cached_time, comic)) if cached_time == modify_time => Some(comic.clone()),
+                    _ => None,
+                },
+            )
+            .collect();
+        (cached_comics, index.size_cache.clone())
+    };
+
+    let mut reread_count = 0;
+    let mut metadata_cache = HashMap::new();
+    let mut timed_entries = vec![];
+    for ((metadata_path, modify_time), cached_comic) in
+        metadata_files.into_iter().zip(cached_comics)
+    {
+        let comic = match cached_comic {
+            Some(comic) => comic,
+            None => {
+                reread_count += 1;
+                match Comic::from_metadata(app, &metadata_path) {
+                    Ok(comic) => comic,
+                    Err(err) => {
+                        let err_title = format!("读取元数据文件`{metadata_path:?}`失败");
+                        let string_chain = err.to_string_chain();
+                        tracing::error!(err_title, message = string_chain);
+                        continue;
+                    }
+                }
+            }
+        };
+        timed_entries.push((modify_time, comic.clone()));
+        metadata_cache.insert(metadata_path, (modify_time, comic));
+    }
+    // 关闭了`write_metadata_json`时下载的漫画没有元数据文件，从元数据索引中读取
+    let comic_ids = timed_entries.iter().map(|(_, comic)| comic.id).collect();
+    match metadata_store::comics_without_metadata_json(app, &download_dir, &comic_ids) {
+        Ok(comics) => timed_entries.extend(comics),
+        Err(err) => {
+            let err_title = "读取元数据索引失败";
+            let string_chain = err.to_string_chain();
+            tracing::error!(err_title, message = string_chain);
+        }
+    }
+    timed_entries.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+    let mut rewalk_count = 0;
+    let mut size_cache = HashMap::new();
+    for (_, comic) in &mut timed_entries {
+        let comic_dir = download_dir.join(&comic.title);
+        let Some(modify_time) = comic_dir.metadata().and_then(|m| m.modified()).ok() else {
+            continue;
+        };
+        let size = match cached_sizes.get(&comic_dir) {
+            Some((cached_time, size)) if *cached_time == modify_time => *size,
+            _ => {
+                rewalk_count += 1;
+                walk_size(&comic_dir)
+            }
+        };
+        comic.disk_size_bytes = Some(size);
+        size_cache.insert(comic_dir, (modify_time, size));
+    }
+
+    let entries: Vec<(String, Comic)> = timed_entries
+        .into_iter()
+        .map(|(_, comic)| (comic.title.to_lowercase(), comic))
+        .collect();
+
+    let comic_count = entries.len();
+    let comic_ids = entries.iter().map(|(_, comic)| comic.id).collect();
+    *index.write() = LibraryIndex {
+        entries,
+        comic_ids,
+        metadata_cache,
+        size_cache,
+        is_ready: true,
+    };
+    tracing::debug!(
+        "重建已下载漫画的索引成功，共`{comic_count}`部漫画，重新读取了`{reread_count}`个元数据文件，重新遍历了`{rewalk_count}`个漫画目录，耗时`{:?}`",
+        start.elapsed()
+    );
+}
+
+/// 只重新读取`comic_dir`这一部漫画并更新索引，不遍历整个下载目录
+///
+/// 下载完成、导入、重命名或更新元数据后调用，`comic_dir`的目录名即漫画标题  
+/// 索引还没完成第一次重建时什么都不做，那次重建会包含这部漫画  
+/// 需要遍历漫画目录计算占用的磁盘空间，在异步上下文中应在`spawn_blocking`中调用
+pub fn upsert_index(app: &AppHandle, comic_dir: &Path) {
+    let index = app.state::<RwLock<LibraryIndex>>();
+    if !index.read().is_ready() {
+        return;
+    }
+    let (mut comic, metadata) = match read_comic_in_dir(app, comic_dir) {
+        Ok(comic_and_metadata) => comic_and_metadata,
+        Err(err) => {
+            let err_title = format!("更新已下载漫画的索引失败，读取`{comic_dir:?}`中的漫画失败");
+            let string_chain = err.to_string_chain();
+            tracing::error!(err_title, message = string_chain);
+            return;
+        }
+    };
+    let size = comic_dir
+        .metadata()
+        .and_then(|m| m.modified())
+        .ok()
+        .map(|modify_time| (comic_dir.to_path_buf(), modify_time, walk_size(comic_dir)));
+    comic.disk_size_bytes = size.as_ref().map(|(_, _, size)| *size);
+    index.write().upsert(comic, metadata, size);
+}
+
+/// 读取`comic_dir`中的漫画，有元数据文件时同时返回元数据文件的路径和修改时间
+fn read_comic_in_dir(
+    app: &AppHandle,
+    comic_dir: &Path,
+) -> anyhow::Result<(Comic, Option<(PathBuf, SystemTime)>)> {
+    let metadata_path = comic_dir.join("元数据.json");
+    if metadata_path.exists() {
+        let modify_time = metadata_path
+            .metadata()
+            .and_then(|m| m.modified())
+            .context(format!("获取`{metadata_path:?}`的修改时间失败"))?;
+        let comic = Comic::from_metadata(app, &metadata_path)?;
+        return Ok((comic, Some((metadata_path, modify_time))));
+    }
+    // 关闭了`write_metadata_json`时下载的漫画没有元数据文件，从元数据索引中读取
+    let dir_name = comic_dir
+        .file_name()
+        .context(format!("获取`{comic_dir:?}`的目录名失败"))?
+        .to_string_lossy();
+    let mut comic = metadata_store::get(app, &dir_name)?.context(format!(
+        "`{comic_dir:?}`中没有元数据文件，元数据索引中也没有这部漫画"
+    ))?;
+    comic.is_downloaded = Some(true);
+    Ok((comic, None))
+}
+
+/// `download_dir`中所有元数据文件的路径和修改时间，最新的排在最前面
+fn list_metadata_files(download_dir: &Path) -> anyhow::Result<Vec<(PathBuf, SystemTime)>> {
+    let mut metadata_path_with_modify_time = std::fs::read_dir(download_dir)
+        .context(format!("读取下载目录`{download_dir:?}`失败"))?
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            if entry.file_name().to_string_lossy().starts_with(".下载中-") {
+                return None;
+            }
+            let metadata_path = entry.path().join("元数据.json");
+            if !metadata_path.exists() {
+                return None;
+            }
+            let modify_time = metadata_path.metadata().ok()?.modified().ok()?;
+            Some((metadata_path, modify_time))
+        })
+        .collect::<Vec<_>>();
+    // 按照文件修改时间排序，最新的排在最前面
+    metadata_path_with_modify_time.sort_by(|(_, a), (_, b)| b.cmp(a));
+    Ok(metadata_path_with_modify_time)
+}
+
+/// 从`download_dir`中的元数据文件(以及元数据索引)读取所有已下载的漫画，最新下载的排在最前面
+///
+/// 每次都会读取所有元数据文件，只需要当前下载目录中的漫画时应使用`LibraryIndex`
+pub fn get_downloaded_comics(app: &AppHandle, download_dir: &Path) -> anyhow::Result<Vec<Comic>> {
+    let metadata_files = list_metadata_files(download_dir)?;
+    // 从元数据文件中读取Comic
+    let mut timed_comics = metadata_files
+        .iter()
+        .filter_map(|(metadata_path, modify_time)| {
+            match Comic::from_metadata(app, metadata_path).map_err(anyhow::Error::from) {
+                Ok(comic) => Some((*modify_time, comic)),
+                Err(err) => {
+                    let err_title = format!("读取元数据文件`{metadata_path:?}`失败");
+                    let string_chain = err.to_string_chain();
+                    tracing::error!(err_title, message = string_chain);
+                    None
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+    let comic_ids = timed_comics.iter().map(|(_, comic)| comic.id).collect();
+    timed_comics.extend(metadata_store::comics_without_metadata_json(
+        app,
+        download_dir,
+        &comic_ids,
+    )?);
+    timed_comics.sort_by(|(a, _), (b, _)| b.cmp(a));
+    let downloaded_comics = timed_comics.into_iter().map(|(_, comic)| comic).collect();
+    Ok(downloaded_comics)
+}
+
+/// 在`download_dir`中根据元数据中的漫画id找到已下载的漫画目录
+pub fn find_comic_dir(download_dir: &Path, comic_id: i64) -> anyhow::Result<Option<PathBuf>> {
+    let comic_dir = find_comic(download_dir, comic_id)?.map(|(comic_dir, _)| comic_dir);
+    Ok(comic_dir)
+}
+
+/// 在`download_dir`中根据元数据中的漫画id找到已下载的漫画目录及其元数据
+pub fn find_comic(download_dir: &Path, comic_id: i64) -> anyhow::Result<Option<(PathBuf, Comic)>> {
+    let comic = std::fs::read_dir(download_dir)
+        .context(format!("读取下载目录`{download_dir:?}`失败"))?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            !path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with(".下载中-"))
+        })
+        .find_map(|path| {
+            let comic_json = std::fs::read_to_string(path.join("元数据.json")).ok()?;
+            let comic = serde_json::from_str::<Comic>(&comic_json).ok()?;
+            (comic.id == comic_id).then_some((path, comic))
+        });
+    Ok(comic)
+}
+
+/// 获取漫画目录中所有的页面，按页码排序
+///
+/// 只包含文件名为页码的图片(例如`0001.jpg`)，元数据等其他文件会被排除
+pub fn get_pages(comic_dir: &Path) -> anyhow::Result<Vec<ComicPage>> {
+    const IMAGE_EXTENSIONS: [&str; 3] = ["jpg", "png", "webp"];
+
+    let mut pages = std::fs::read_dir(comic_dir)
+        .context(format!("读取目录`{comic_dir:?}`失败"))?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter_map(|path| {
+            let extension = path.extension()?.to_str()?;
+            if !IMAGE_EXTENSIONS.contains(&extension) {
+                return None;
+            }
+            let index = path.file_stem()?.to_str()?.parse::<u32>().ok()?;
+            Some(ComicPage { index, path })
+        })
+        .collect::<Vec<_>>();
+    pages.sort_by_key(|page| page.index);
+    Ok(pages)
+}
+
+/// 按页码顺序获取每一页图片的尺寸(宽, 高)，只读取图片头部
+///
+/// 第`i`个元素对应第`i + 1`页，图片缺失或损坏时为`None`
+#[allow(clippy::cast_possible_truncation)]
+pub fn get_page_dimensions(
+    comic_dir: &Path,
+    page_count: usize,
+) -> anyhow::Result<Vec<Option<(u32, u32)>>> {
+    let pages = get_pages(comic_dir)?;
+    let max_index = pages
+        .iter()
+        .map(|page| page.index as usize)
+        .max()
+        .unwrap_or(0);
+    let mut dimensions = vec![None; page_count.max(max_index)];
+    for page in pages {
+        let Some(slot) = (page.index as usize)
+            .checked_sub(1)
+            .and_then(|i| dimensions.get_mut(i))
+        else {
+            continue;
+        };
+        *slot = image::image_dimensions(&page.path).ok();
+    }
+    Ok(dimensions)
+}
+
+/// 允许前端通过asset协议访问`download_dir`中的文件，只开放下载目录，不开放其他目录
+pub fn allow_asset_access(app: &AppHandle, download_dir: &Path) {
+    if let Err(err) = app
+        .asset_protocol_scope()
+        .allow_directory(download_dir, true)
+        .map_err(anyhow::Error::from)
+    {
+        let err_title = format!("允许访问下载目录`{download_dir:?}`失败");
+        let string_chain = err.to_string_chain();
+        tracing::error!(err_title, message = string_chain);
+    }
+}
+
+/// 漫画是否已下载，目录名与标题相同或者索引中有该漫画id(目录被重命名过)都视为已下载
+pub fn is_downloaded(app: &AppHandle, comic_id: i64, comic_title: &str) -> bool {
+    let download_dir = app.state::<RwLock<Config>>().read().download_dir.clone();
+    download_dir.join(comic_title).exists()
+        || app
+            .state::<RwLock<LibraryIndex>>()
+            .read()
+            .contains(comic_id)
+}
+
+/// 将已下载的漫画重命名为`new_title`，同时重命名目录和更新元数据中的标题
+///
+/// 新标题会经过`filename_filter`处理，如果新旧标题只有大小写不同，
+/// 则先重命名为临时目录，以兼容不区分大小写的文件系统
+pub fn rename_comic(download_dir: &Path, comic_id: i64, new_title: &str) -> anyhow::Result<Comic> {
+    let new_title = filename_filter(new_title);
+    if new_title.is_empty() || new_title.starts_with('.') {
+        return Err(anyhow!("新标题`{new_title}`不能为空，也不能以`.`开头"));
+    }
+    let (old_comic_dir, mut comic) = find_comic(download_dir, comic_id)?
+        .context(format!("没有找到漫画ID为`{comic_id}`的已下载漫画"))?;
+
+    let comic_dir = download_dir.join(&new_title);
+    let old_dir_name = old_comic_dir
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    if old_dir_name == new_title {
+        // 目录名没变，只需要更新元数据
+    } else if old_dir_name.to_lowercase() == new_title.to_lowercase() {
+        let temp_dir = download_dir.join(format!(".重命名中-{new_title}"));
+        std::fs::rename(&old_comic_dir, &temp_dir)
+            .context(format!("将`{old_comic_dir:?}`重命名为`{temp_dir:?}`失败"))?;
+        std::fs::rename(&temp_dir, &comic_dir)
+            .context(format!("将`{temp_dir:?}`重命名为`{comic_dir:?}`失败"))?;
+    } else {
+        if comic_dir.exists() {
+            return Err(anyhow!("目录`{comic_dir:?}`已存在"));
+        }
+        std::fs::rename(&old_comic_dir, &comic_dir)
+            .context(format!("将`{old_comic_dir:?}`重命名为`{comic_dir:?}`失败"))?;
+    }
+
+    comic.title = new_title;
+    comic.save_metadata(&comic_dir)?;
+    comic.is_downloaded = Some(true);
+    Ok(comic)
+}
+
+/// 用最新的`comic`覆盖已下载漫画的元数据，如果标题变了，则重命名目录
+pub fn refresh_metadata(download_dir: &Path, mut comic: Comic) -> anyhow::Result<Comic> {
+    let comic_id = comic.id;
+    let comic_title = &comic.title;
+    let old_comic_dir = find_comic_dir(download_dir, comic_id)?
+        .context(format!("没有找到漫画ID为`{comic_id}`的已下载漫画"))?;
+
+    let comic_dir = download_dir.join(comic_title);
+    if old_comic_dir != comic_dir {
+        if comic_dir.exists() {
+            return Err(anyhow!(
+                "目录`{comic_dir:?}`已存在，无法将`{old_comic_dir:?}`重命名为新标题"
+            ));
+        }
+        std::fs::rename(&old_comic_dir, &comic_dir)
+            .context(format!("将`{old_comic_dir:?}`重命名为`{comic_dir:?}`失败"))?;
+    }
+
+    comic.save_metadata(&comic_dir)?;
+    comic.is_downloaded = Some(true);
+    Ok(comic)
+}
+
+/// 删除已下载的漫画目录，`to_trash`为true时移动到回收站
+pub fn delete_comic_dir(comic_dir: &Path, to_trash: bool) -> anyhow::Result<()> {
+    if to_trash {
+        trash::delete(comic_dir).context(format!("将`{comic_dir:?}`移动到回收站失败"))?;
+    } else {
+        std::fs::remove_dir_all(comic_dir).context(format!("删除`{comic_dir:?}`失败"))?;
+    }
+    Ok(())
+}
+
+/// 没有对应下载任务的临时下载目录(`.下载中-`开头)，通常是程序崩溃后留下的
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct OrphanTempDir {
+    /// 目录名
+    pub name: String,
+    /// 目录中所有文件的总大小，单位为字节
+    pub size: u64,
+    /// 已下载的图片数量
+    pub page_count: u32,
+    /// 元数据可读时为对应的漫画，前端可以用它重新创建下载任务，已下载的图片会被跳过
+    pub comic: Option<Comic>,
+}
+
+/// 获取`download_dir`中所有没有对应下载任务的临时下载目录
+///
+/// `is_task_active`用于判断漫画id对应的下载任务是否还未结束，
+/// `active_titles`为所有未结束的下载任务的漫画标题
+pub fn list_orphan_temp_dirs(
+    download_dir: &Path,
+    is_task_active: impl Fn(i64) -> bool,
+    active_titles: &HashSet<String>,
+) -> anyhow::Result<Vec<OrphanTempDir>> {
+    let mut orphan_temp_dirs = vec![];
+    for entry in std::fs::read_dir(download_dir)
+        .context(format!("读取下载目录`{download_dir:?}`失败"))?
+        .filter_map(Result::ok)
+    {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        let Some(comic_title) = name.strip_prefix(".下载中-") else {
+            continue;
+        };
+        if !path.is_dir() || active_titles.contains(comic_title) {
+            continue;
+        }
+        let comic = std::fs::read_to_string(path.join("元数据.json"))
+            .ok()
+            .and_then(|comic_json| serde_json::from_str::<Comic>(&comic_json).ok());
+        if comic.as_ref().is_some_and(|comic| is_task_active(comic.id)) {
+            continue;
+        }
+
+        let mut size = 0;
+        let mut page_count = 0;
+        for entry in std::fs::read_dir(&path)
+            .context(format!("读取目录`{path:?}`失败"))?
+            .filter_map(Result::ok)
+        {
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            size += metadata.len();
+            let entry_path = entry.path();
+            let is_page = entry_path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .is_some_and(|stem| stem.parse::<u32>().is_ok());
+            if is_page {
+                page_count += 1;
+            }
+        }
+
+        orphan_temp_dirs.push(OrphanTempDir {
+            name,
+            size,
+            page_count,
+            comic,
+        });
+    }
+    Ok(orphan_temp_dirs)
+}
+
+/// `verify`被取消时设为`true`
+static VERIFY_CANCELLED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum LibraryIssueKind {
+    /// 目录中没有`元数据.json`或无法解析
+    MissingMetadata,
+    /// 图片数量少于元数据中的图片数量
+    MissingPages,
+    /// 图片无法解码
+    CorruptedPage,
+}
+
+/// 检查已下载的漫画时发现的问题
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryIssue {
+    /// 元数据无法读取时为`None`
+    pub comic_id: Option<i64>,
+    /// 漫画标题，元数据无法读取时为目录名
+    pub title: String,
+    pub kind: LibraryIssueKind,
+    pub details: String,
+}
+
+/// 检查图片能否被完整解码
+pub fn validate_page(page_path: &Path) -> anyhow::Result<()> {
+    image::ImageReader::open(page_path)
+        .context(format!("打开图片`{page_path:?}`失败"))?
+        .with_guessed_format()
+        .context(format!("识别图片`{page_path:?}`的格式失败"))?
+        .decode()
+        .context(format!("解码图片`{page_path:?}`失败"))?;
+    Ok(())
+}
+
+/// 取消正在进行的`verify`
+pub fn cancel_verify() {
+    VERIFY_CANCELLED.store(true, Ordering::Relaxed);
+}
+
+/// 检查`download_dir`中所有已下载的漫画，返回发现的问题
+///
+/// 图片数量与清洗后的图片列表比较(不包含占位图片)，而不是网站上显示的图片数量  
+/// 被`cancel_verify`取消时，返回取消前发现的问题
+#[allow(clippy::cast_possible_truncation)]
+pub fn verify(app: &AppHandle, download_dir: &Path) -> anyhow::Result<Vec<LibraryIssue>> {
+    VERIFY_CANCELLED.store(false, Ordering::Relaxed);
+
+    let comic_dirs = std::fs::read_dir(download_dir)
+        .context(format!("读取下载目录`{download_dir:?}`失败"))?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .filter(|path| {
+            !path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with(".下载中-"))
+        })
+        .collect::<Vec<_>>();
+
+    let total = comic_dirs.len() as u32;
+    let _ = VerifyLibraryEvent::Start { total }.emit(app);
+
+    let mut issues = vec![];
+    let mut cancelled = false;
+    for (i, comic_dir) in comic_dirs.iter().enumerate() {
+        if VERIFY_CANCELLED.load(Ordering::Relaxed) {
+            cancelled = true;
+            break;
+        }
+        let dir_name = comic_dir
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let _ = VerifyLibraryEvent::Progress {
+            title: dir_name.clone(),
+            current: i as u32 + 1,
+            total,
+        }
+        .emit(app);
+
+        let metadata_path = comic_dir.join("元数据.json");
+        let comic = match Comic::from_metadata(app, &metadata_path) {
+            Ok(comic) => comic,
+            Err(err) => {
+                issues.push(LibraryIssue {
+                    comic_id: None,
+                    title: dir_name,
+                    kind: LibraryIssueKind::MissingMetadata,
+                    details: format!("{err:#}"),
+                });
+                continue;
+            }
+        };
+
+        let pages = get_pages(comic_dir)?;
+        let expected_count = comic.img_list.len() as u32;
+        let missing_indexes = (1..=expected_count)
+            .filter(|index| !pages.iter().any(|page| page.index == *index))
+            .collect::<Vec<_>>();
+        if !missing_indexes.is_empty() {
+            issues.push(LibraryIssue {
+                comic_id: Some(comic.id),
+                title: comic.title.clone(),
+                kind: LibraryIssueKind::MissingPages,
+                details: format!("应有`{expected_count}`张图片，缺少第{missing_indexes:?}张"),
+            });
+        }
+
+        for page in &pages {
+            if let Err(err) = validate_page(&page.path) {
+                issues.push(LibraryIssue {
+                    comic_id: Some(comic.id),
+                    title: comic.title.clone(),
+                    kind: LibraryIssueKind::CorruptedPage,
+                    details: format!("{err:#}"),
+                });
+            }
+        }
+    }
+
+    let _ = VerifyLibraryEvent::End {
+        issue_count: issues.len() as u32,
+        cancelled,
+    }
+    .emit(app);
+
+    Ok(issues)
+}
+
+/// 将`old_download_dir`中所有已下载的漫画移动到`new_download_dir`
+///
+/// 只移动有`元数据.json`的目录，临时下载目录(`.下载中-`开头)会被跳过，
+/// 正在下载的任务会继续在旧的下载目录中完成  
+/// 如果新的下载目录中已有同名目录，则在目录名后加上` (2)`、` (3)`等后缀，并把`元数据.json`中的标题改成新的目录名
+#[allow(clippy::cast_possible_truncation)]
+pub fn migrate(
+    app: &AppHandle,
+    old_download_dir: &Path,
+    new_download_dir: &Path,
+) -> anyhow::Result<()> {
+    std::fs::create_dir_all(new_download_dir)
+        .context(format!("创建目录`{new_download_dir:?}`失败"))?;
+
+    let comic_dirs = std::fs::read_dir(old_download_dir)
+        .context(format!("读取目录`{old_download_dir:?}`失败"))?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            let is_temp_dir = path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with(".下载中-"));
+            !is_temp_dir && path.join("元数据.json").exists()
+        })
+        .collect::<Vec<_>>();
+
+    let total = comic_dirs.len() as u32;
+    let _ = MigrateLibraryEvent::Start { total }.emit(app);
+
+    let mut moved_count = 0;
+    let mut failed_count = 0;
+    for (i, comic_dir) in comic_dirs.iter().enumerate() {
+        let Some(dir_name) = comic_dir.file_name() else {
+            continue;
+        };
+        let title = dir_name.to_string_lossy().to_string();
+        let preferred_dir = new_download_dir.join(dir_name);
+        let target_dir = available_dir(&preferred_dir);
+
+        let move_result = move_dir(comic_dir, &target_dir).and_then(|()| {
+            if target_dir == preferred_dir {
+                return Ok(());
+            }
+            // 按标题查找漫画目录的地方(重建索引、查找重复、判断是否已下载、导出)都要求标题与目录名一致
+            retitle_metadata(app, &target_dir)
+        });
+        match move_result {
+            Ok(()) => moved_count += 1,
+            Err(err) => {
+                failed_count += 1;
+                let err_title = format!("迁移`{comic_dir:?}`到`{target_dir:?}`失败");
+                let string_chain = err.to_string_chain();
+                tracing::error!(err_title, message = string_chain);
+            }
+        }
+
+        let _ = MigrateLibraryEvent::Progress {
+            title,
+            current: i as u32 + 1,
+            total,
+        }
+        .emit(app);
+    }
+
+    let _ = MigrateLibraryEvent::End {
+        moved_count,
+        failed_count,
+    }
+    .emit(app);
+
+    if failed_count > 0 {
+        return Err(anyhow!("有`{failed_count}`个漫画迁移失败，详情请查看日志"));
+    }
+
+    Ok(())
+}
+
+/// 把`comic_dir`中`元数据.json`的标题改为`comic_dir`的目录名
+fn retitle_metadata(app: &AppHandle, comic_dir: &Path) -> anyhow::Result<()> {
+    let Some(dir_name) = comic_dir.file_name() else {
+        return Err(anyhow!("`{comic_dir:?}`没有目录名"));
+    };
+    let mut comic = Comic::from_metadata(app, &comic_dir.join("元数据.json"))?;
+    comic.title = dir_name.to_string_lossy().to_string();
+    comic
+        .save_metadata(comic_dir)
+        .context(format!("修改`{comic_dir:?}`中元数据的标题失败"))
+}
+
+/// 将`source_dir`中的图片导入为下载目录中的漫画，`source_dir`本身不会被修改
+///
+/// 图片按文件名中的数字排序后复制为`0001.jpg`这样的页码文件名  
+/// `comic`为`None`时用`local_comic_id`和目录名生成最简单的元数据，`title`不为`None`时覆盖标题  
+/// 下载目录中已有同名目录时报错，不会覆盖
+#[allow(clippy::cast_possible_wrap)]
+pub fn import_local_comic(
+    download_dir: &Path,
+    source_dir: &Path,
+    comic: Option<Comic>,
+    title: Option<&str>,
+    local_comic_id: i64,
+) -> anyhow::Result<Comic> {
+    const IMAGE_EXTENSIONS: [&str; 4] = ["jpg", "jpeg", "png", "webp"];
+
+    if !source_dir.is_dir() {
+        return Err(anyhow!("`{source_dir:?}`不是目录"));
+    }
+    let mut image_paths: Vec<PathBuf> = std::fs::read_dir(source_dir)
+        .context(format!("读取目录`{source_dir:?}`失败"))?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter(|path| {
+            path.extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+        })
+        .collect();
+    if image_paths.is_empty() {
+        return Err(anyhow!(
+            "`{source_dir:?}`中没有图片，只支持{IMAGE_EXTENSIONS:?}格式"
+        ));
+    }
+    // `2.jpg`应该排在`10.jpg`前面
+    image_paths.sort_by_cached_key(|path| {
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let digits: String = stem
+            .chars()
+            .rev()
+            .take_while(char::is_ascii_digit)
+            .collect::<Vec<_>>()
+            .into_iter()
+            .rev()
+            .collect();
+        (stem.len() - digits.len(), digits.parse::<u64>().ok(), stem)
+    });
+
+    let dir_name = source_dir
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let mut comic = comic.unwrap_or_else(|| Comic {
+        id: local_comic_id,
+        title: dir_name,
+        image_count: image_paths.len() as i64,
+        ..Default::default()
+    });
+    if let Some(title) = title {
+        comic.title = title.to_string();
+    }
+    comic.title = filename_filter(&comic.title);
+    let comic_title = comic.title.clone();
+    if comic_title.is_empty() || comic_title.starts_with('.') {
+        return Err(anyhow!("标题`{comic_title}`不能为空，也不能以`.`开头"));
+    }
+
+    let comic_dir = download_dir.join(&comic_title);
+    if comic_dir.exists() {
+        return Err(anyhow!("下载目录中已有同名目录`{comic_dir:?}`"));
+    }
+    // 先复制到临时目录，全部完成后再重命名，以免导入到一半的目录出现在漫画库中
+    let temp_dir = download_dir.join(format!(".导入中-{comic_title}"));
+    std::fs::create_dir_all(&temp_dir).context(format!("创建目录`{temp_dir:?}`失败"))?;
+    let copy_result = copy_pages(&image_paths, &temp_dir)
+        .and_then(|()| comic.save_metadata(&temp_dir))
+        .and_then(|()| {
+            std::fs::rename(&temp_dir, &comic_dir)
+                .context(format!("将`{temp_dir:?}`重命名为`{comic_dir:?}`失败"))
+        });
+    if let Err(err) = copy_result {
+        let _ = std::fs::remove_dir_all(&temp_dir);
+        return Err(err);
+    }
+
+    comic.is_downloaded = Some(true);
+    Ok(comic)
+}
+
+/// 将图片按顺序复制到`dir`中，文件名为`0001.jpg`这样的页码
+fn copy_pages(image_paths: &[PathBuf], dir: &Path) -> anyhow::Result<()> {
+    for (i, image_path) in image_paths.iter().enumerate() {
+        let extension = image_path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let extension = if extension == "jpeg" {
+            "jpg"
+        } else {
+            &extension
+        };
+        let page_path = dir.join(format!("{:04}.{extension}", i + 1));
+        std::fs::copy(image_path, &page_path)
+            .context(format!("复制`{image_path:?}`到`{page_path:?}`失败"))?;
+    }
+    Ok(())
+}
+
+/// 如果`dir`已存在，则返回加上` (2)`、` (3)`等后缀后不存在的目录
+pub fn available_dir(dir: &Path) -> PathBuf {
+    if !dir.exists() {
+        return dir.to_path_buf();
+    }
+    let dir_name = dir
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    (2..)
+        .map(|i| dir.with_file_name(format!("{dir_name} ({i})")))
+        .find(|dir| !dir.exists())
+        .unwrap_or_else(|| dir.to_path_buf())
+}
+
+/// 移动目录，如果不能直接重命名(例如跨磁盘)，则先复制再删除
+fn move_dir(from: &Path, to: &Path) -> anyhow::Result<()> {
+    if std::fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    copy_dir_all(from, to).context(format!("复制`{from:?}`到`{to:?}`失败"))?;
+    std::fs::remove_dir_all(from).context(format!("删除`{from:?}`失败"))?;
+    Ok(())
+}
+
+fn copy_dir_all(from: &Path, to: &Path) -> anyhow::Result<()> {
+    std::fs::create_dir_all(to).context(format!("创建目录`{to:?}`失败"))?;
+    for entry in std::fs::read_dir(from).context(format!("读取目录`{from:?}`失败"))? {
+        let entry = entry?;
+        let path = entry.path();
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir_all(&path, &target)?;
+        } else {
+            std::fs::copy(&path, &target).context(format!("复制`{path:?}`到`{target:?}`失败"))?;
+        }
+    }
+    Ok(())
+}
+
+/// 单个漫画占用的磁盘空间，单位为字节
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct ComicDiskUsage {
+    /// 漫画标题(即目录名)
+    pub title: String,
+    /// 没有元数据的目录为`None`
+    pub comic_id: Option<i64>,
+    pub download_bytes: u64,
+    pub export_bytes: u64,
+    pub total_bytes: u64,
+}
+
+/// 下载目录和导出目录占用的磁盘空间，单位为字节
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryDiskUsage {
+    /// 按`total_bytes`从大到小排列
+    pub comics: Vec<ComicDiskUsage>,
+    pub download_bytes: u64,
+    pub export_bytes: u64,
+    pub total_bytes: u64,
+}
+
+/// 统计下载目录和导出目录中每个漫画目录占用的磁盘空间，同名的下载目录和导出目录合并为一项
+///
+/// 会遍历所有文件，漫画很多时比较耗时，应在`spawn_blocking`中调用
+pub fn disk_usage(
+    download_dir: &Path,
+    export_dir: &Path,
+    comic_ids_by_title: &HashMap<String, i64>,
+) -> anyhow::Result<LibraryDiskUsage> {
+    let mut comics: HashMap<String, ComicDiskUsage> = HashMap::new();
+    let mut download_bytes = 0;
+    let mut export_bytes = 0;
+
+    for (dir, is_export_dir) in [(download_dir, false), (export_dir, true)] {
+        if !dir.exists() {
+            continue;
+        }
+        for entry in std::fs::read_dir(dir).context(format!("读取目录`{dir:?}`失败"))? {
+            let entry = entry.context(format!("读取目录`{dir:?}`中的条目失败"))?;
+            let path = entry.path();
+            let size = walk_size(&path);
+            if is_export_dir {
+                export_bytes += size;
+            } else {
+                download_bytes += size;
+            }
+            if !path.is_dir() {
+                continue;
+            }
+            let title = entry.file_name().to_string_lossy().to_string();
+            let comic = comics
+                .entry(title.clone())
+                .or_insert_with(|| ComicDiskUsage {
+                    comic_id: comic_ids_by_title.get(&title).copied(),
+                    title,
+                    download_bytes: 0,
+                    export_bytes: 0,
+                    total_bytes: 0,
+                });
+            if is_export_dir {
+                comic.export_bytes += size;
+            } else {
+                comic.download_bytes += size;
+            }
+            comic.total_bytes += size;
+        }
+    }
+
+    let mut comics: Vec<ComicDiskUsage> = comics.into_values().collect();
+    comics.sort_by(|a, b| b.total_bytes.cmp(&a.total_bytes));
+
+    Ok(LibraryDiskUsage {
+        comics,
+        download_bytes,
+        export_bytes,
+        total_bytes: download_bytes + export_bytes,
+    })
+}
+
+/// `path`下所有文件大小之和，读取失败的文件忽略不计
+fn walk_size(path: &Path) -> u64 {
+    walkdir::WalkDir::new(path)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| entry.metadata().ok())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+/// 可能重复的漫画中的一部
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateComic {
+    pub id: i64,
+    pub title: String,
+    pub path: PathBuf,
+    pub size_bytes: u64,
+}
+
+/// 判断为可能重复的依据
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum DuplicateReason {
+    /// 规范化后的标题相同
+    SameTitle,
+    /// 图片数量和第一页的尺寸都相同
+    SameContent,
+}
+
+/// 一组可能重复的漫画
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateGroup {
+    pub reason: DuplicateReason,
+    pub comics: Vec<DuplicateComic>,
+}
+
+/// 找出可能重复的漫画(例如id不同但内容相同的重新上传)，只是候选，由用户决定删除哪些
+///
+/// 会读取第一页的图片头部并统计目录大小，漫画很多时比较耗时，应在`spawn_blocking`中调用
+pub fn find_duplicates(download_dir: &Path, comics: &[Comic]) -> Vec<DuplicateGroup> {
+    let mut by_title: HashMap<String, Vec<&Comic>> = HashMap::new();
+    let mut by_content: HashMap<(i64, (u32, u32)), Vec<&Comic>> = HashMap::new();
+    for comic in comics {
+        by_title
+            .entry(utils::normalize_title(&comic.title))
+            .or_default()
+            .push(comic);
+        let comic_dir = download_dir.join(&comic.title);
+        let first_page_dimensions = get_pages(&comic_dir)
+            .ok()
+            .and_then(|pages| pages.into_iter().next())
+            .and_then(|page| image::image_dimensions(&page.path).ok());
+        if let Some(dimensions) = first_page_dimensions {
+            by_content
+                .entry((comic.image_count, dimensions))
+                .or_default()
+                .push(comic);
+        }
+    }
+
+    let mut seen_id_sets: HashSet<Vec<i64>> = HashSet::new();
+    let mut groups = vec![];
+    let candidates = by_title
+        .into_values()
+        .map(|comics| (DuplicateReason::SameTitle, comics))
+        .chain(
+            by_content
+                .into_values()
+                .map(|comics| (DuplicateReason::SameContent, comics)),
+        );
+    for (reason, mut comics) in candidates {
+        if comics.len() < 2 {
+            continue;
+        }
+        comics.sort_by_key(|comic| comic.id);
+        // 标题和内容都相同的一组漫画只返回一次
+        let ids: Vec<i64> = comics.iter().map(|comic| comic.id).collect();
+        if !seen_id_sets.insert(ids) {
+            continue;
+        }
+        let comics = comics
+            .into_iter()
+            .map(|comic| {
+                let path = download_dir.join(&comic.title);
+                DuplicateComic {
+                    id: comic.id,
+                    title: comic.title.clone(),
+                    size_bytes: walk_size(&path),
+                    path,
+                }
+            })
+            .collect();
+        groups.push(DuplicateGroup { reason, comics });
+    }
+    groups
+}
+
+/// 书架与已下载漫画的对比结果
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct FavoritesDiff {
+    /// 在书架中但还没下载的漫画
+    pub missing: Vec<ComicInFavorite>,
+    /// 在书架中且已下载的漫画
+    pub downloaded: Vec<ComicInFavorite>,
+    /// 已下载但不在书架中的漫画，本地导入的漫画(id不是正数)不在其中
+    pub orphaned_local: Vec<Comic>,
+    /// 获取书架时发现的结果可能与网站不一致的说明
+    pub warnings: Vec<String>,
+}
+
+/// 按漫画id对比书架中的漫画和已下载的漫画
+pub fn diff_favorites(
+    favorites: GetAllFavoritesResult,
+    downloaded_comics: Vec<Comic>,
+) -> FavoritesDiff {
+    let downloaded_ids = downloaded_comics
+        .iter()
+        .map(|comic| comic.id)
+        .collect::<HashSet<_>>();
+    let favorite_ids = favorites
+        .comics
+        .iter()
+        .map(|comic| comic.id)
+        .collect::<HashSet<_>>();
+
+    let (downloaded, missing): (Vec<_>, Vec<_>) = favorites
+        .comics
+        .into_iter()
+        .partition(|comic| downloaded_ids.contains(&comic.id));
+    let orphaned_local = downloaded_comics
+        .into_iter()
+        .filter(|comic| comic.id > 0 && !favorite_ids.contains(&comic.id))
+        .collect();
+
+    FavoritesDiff {
+        missing,
+        downloaded,
+        orphaned_local,
+        warnings: favorites.warnings,
+    }
+}
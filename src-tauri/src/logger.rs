@@ -1,15 +1,20 @@
-use std::{io::Write, sync::OnceLock};
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::{File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
 
 use anyhow::Context;
 use notify::{RecommendedWatcher, Watcher};
 use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use specta::Type;
 use tauri::{AppHandle, Manager};
 use tauri_specta::Event;
 use tracing::{Level, Subscriber};
-use tracing_appender::{
-    non_blocking::WorkerGuard,
-    rolling::{RollingFileAppender, Rotation},
-};
+use tracing_appender::non_blocking::WorkerGuard;
 use tracing_subscriber::{
     filter::{filter_fn, FilterExt, Targets},
     fmt::{layer, time::LocalTime},
@@ -19,7 +24,13 @@ use tracing_subscriber::{
     Layer, Registry,
 };
 
-use crate::{config::Config, events::LogEvent, extensions::AnyhowErrorToStringChain};
+use crate::{
+    config::Config,
+    events::LogEvent,
+    extensions::AnyhowErrorToStringChain,
+    types::{LogFormat, LogLevel},
+    utils,
+};
 
 struct LogEventWriter {
     app: AppHandle,
@@ -30,6 +41,7 @@ impl Write for LogEventWriter {
         let log_string = String::from_utf8_lossy(buf);
         match serde_json::from_str::<LogEvent>(&log_string) {
             Ok(log_event) => {
+                buffer_log_event(log_event.clone());
                 let _ = log_event.emit(&self.app);
             }
             Err(err) => {
@@ -46,8 +58,152 @@ impl Write for LogEventWriter {
     }
 }
 
-static RELOAD_FN: OnceLock<Box<dyn Fn() -> anyhow::Result<()> + Send + Sync>> = OnceLock::new();
+/// 最多缓存的`LogEvent`数量
+const LOG_BUFFER_CAPACITY: usize = 500;
+/// 最近的`LogEvent`，前端挂载监听之前(例如启动时)发送的日志会丢失，挂载后可以用它补齐
+static LOG_BUFFER: parking_lot::Mutex<VecDeque<LogEvent>> =
+    parking_lot::Mutex::new(VecDeque::new());
+
+fn buffer_log_event(log_event: LogEvent) {
+    let mut log_buffer = LOG_BUFFER.lock();
+    if log_buffer.len() >= LOG_BUFFER_CAPACITY {
+        log_buffer.pop_front();
+    }
+    log_buffer.push_back(log_event);
+}
+
+/// 缓存的最近的`LogEvent`，从旧到新排列
+pub fn get_buffered_logs() -> Vec<LogEvent> {
+    LOG_BUFFER.lock().iter().cloned().collect()
+}
+
+const LOG_FILENAME_PREFIX: &str = "wnacg-downloader";
+/// 正在写入的日志文件名
+const CURRENT_LOG_FILENAME: &str = "wnacg-downloader.log";
+
+/// 按大小滚动的日志文件
+///
+/// 日志写入`wnacg-downloader.log`，超过`max_size`后重命名为`wnacg-downloader.{时间}.log`，
+/// 包括正在写入的文件在内，只保留最近的`retention_count`个日志文件
+struct SizeRollingWriter {
+    logs_dir: PathBuf,
+    max_size: u64,
+    retention_count: usize,
+    file: File,
+    size: u64,
+}
+
+impl SizeRollingWriter {
+    /// `start_new_file`为`true`时，先滚动已有的日志文件，再写入新的日志文件
+    fn new(
+        logs_dir: PathBuf,
+        max_size: u64,
+        retention_count: usize,
+        start_new_file: bool,
+    ) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(&logs_dir).context(format!("创建日志目录`{logs_dir:?}`失败"))?;
+        let path = logs_dir.join(CURRENT_LOG_FILENAME);
+        let file = open_log_file(&path)?;
+        let size = file
+            .metadata()
+            .context(format!("获取日志文件`{path:?}`的元数据失败"))?
+            .len();
+        let mut writer = Self {
+            logs_dir,
+            max_size,
+            retention_count,
+            file,
+            size,
+        };
+        if start_new_file && writer.size > 0 {
+            writer.roll()?;
+        } else {
+            writer.remove_old_log_files()?;
+        }
+        Ok(writer)
+    }
+
+    fn roll(&mut self) -> anyhow::Result<()> {
+        self.file.flush().context("刷新日志文件失败")?;
+        let current_path = self.logs_dir.join(CURRENT_LOG_FILENAME);
+        let now = chrono::Local::now().format("%Y-%m-%d_%H-%M-%S-%3f");
+        let rolled_path = self
+            .logs_dir
+            .join(format!("{LOG_FILENAME_PREFIX}.{now}.log"));
+        std::fs::rename(&current_path, &rolled_path).context(format!(
+            "将日志文件`{current_path:?}`重命名为`{rolled_path:?}`失败"
+        ))?;
+        self.file = open_log_file(&current_path)?;
+        self.size = 0;
+        self.remove_old_log_files()?;
+        Ok(())
+    }
+
+    /// 删除超出保留数量的旧日志文件，旧日志文件名中带有时间，按文件名排序即可
+    fn remove_old_log_files(&self) -> anyhow::Result<()> {
+        let logs_dir = &self.logs_dir;
+        let mut old_log_paths: Vec<PathBuf> = std::fs::read_dir(logs_dir)
+            .context(format!("读取日志目录`{logs_dir:?}`失败"))?
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| is_old_log_file(path))
+            .collect();
+        old_log_paths.sort();
+        // 正在写入的日志文件也占一个名额
+        let keep_count = self.retention_count.saturating_sub(1);
+        let remove_count = old_log_paths.len().saturating_sub(keep_count);
+        for path in old_log_paths.into_iter().take(remove_count) {
+            std::fs::remove_file(&path).context(format!("删除旧日志文件`{path:?}`失败"))?;
+        }
+        Ok(())
+    }
+}
+
+impl Write for SizeRollingWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let exceeds_max_size = self.size + buf.len() as u64 > self.max_size;
+        if self.size > 0 && exceeds_max_size {
+            // 这里不能用tracing输出日志，否则会写回这个writer
+            if let Err(err) = self.roll() {
+                eprintln!("滚动日志文件失败: {}", err.to_string_chain());
+            }
+        }
+        let len = self.file.write(buf)?;
+        self.size += len as u64;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn open_log_file(path: &Path) -> anyhow::Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .context(format!("打开日志文件`{path:?}`失败"))
+}
+
+/// 是否为已经滚动过的旧日志文件(也包括旧版本按天滚动的日志文件)
+fn is_old_log_file(path: &Path) -> bool {
+    let Some(filename) = path.file_name().and_then(|name| name.to_str()) else {
+        return false;
+    };
+    filename != CURRENT_LOG_FILENAME
+        && filename.starts_with(&format!("{LOG_FILENAME_PREFIX}."))
+        && filename.ends_with(".log")
+}
+
+/// 当前日志文件的格式，格式变化时需要开始写入新的日志文件
+static FILE_LOG_FORMAT: parking_lot::Mutex<Option<LogFormat>> = parking_lot::Mutex::new(None);
+
+/// 参数为`start_new_file`，为`true`时先滚动正在写入的日志文件，再写入新的日志文件
+static RELOAD_FN: OnceLock<Box<dyn Fn(bool) -> anyhow::Result<()> + Send + Sync>> = OnceLock::new();
 static GUARD: OnceLock<parking_lot::Mutex<Option<WorkerGuard>>> = OnceLock::new();
+static SET_LEVEL_FN: OnceLock<Box<dyn Fn(LogLevel) -> anyhow::Result<()> + Send + Sync>> =
+    OnceLock::new();
 
 pub fn init(app: &AppHandle) -> anyhow::Result<()> {
     let lib_module_path = module_path!();
@@ -56,8 +212,13 @@ pub fn init(app: &AppHandle) -> anyhow::Result<()> {
     ))?;
     // 过滤掉来自其他库的日志
     let target_filter = Targets::new().with_target(lib_target, Level::TRACE);
+    // 只输出级别不低于`config.log_level`的日志，可以在运行时修改
+    let log_level = app.state::<RwLock<Config>>().read().log_level;
+    let level_filter = Targets::new().with_target(lib_target, Level::from(log_level));
+    let (reloadable_level_filter, level_reload_handle) =
+        tracing_subscriber::reload::Layer::new(level_filter);
     // 输出到文件
-    let (file_layer, guard) = create_file_layer(app)?;
+    let (file_layer, guard) = create_file_layer(app, false)?;
     let (reloadable_file_layer, reload_handle) = tracing_subscriber::reload::Layer::new(file_layer);
     // 输出到控制台
     let console_layer = layer()
@@ -79,7 +240,7 @@ pub fn init(app: &AppHandle) -> anyhow::Result<()> {
         })));
 
     Registry::default()
-        .with(target_filter)
+        .with(reloadable_level_filter)
         .with(reloadable_file_layer)
         .with(console_layer)
         .with(log_event_layer)
@@ -88,20 +249,65 @@ pub fn init(app: &AppHandle) -> anyhow::Result<()> {
     GUARD.get_or_init(|| parking_lot::Mutex::new(guard));
     RELOAD_FN.get_or_init(move || {
         let app = app.clone();
-        Box::new(move || {
-            let (file_layer, guard) = create_file_layer(&app)?;
+        Box::new(move |start_new_file| {
+            let (file_layer, guard) = create_file_layer(&app, start_new_file)?;
             reload_handle.reload(file_layer).context("reload失败")?;
+            // 旧的guard在这里被drop，drop时会等待缓冲中的日志写入旧文件后再返回
             *GUARD.get().context("GUARD未初始化")?.lock() = guard;
             Ok(())
         })
     });
+    SET_LEVEL_FN.get_or_init(move || {
+        Box::new(move |log_level| {
+            let level_filter = Targets::new().with_target(lib_target, Level::from(log_level));
+            level_reload_handle
+                .reload(level_filter)
+                .context("reload失败")?;
+            Ok(())
+        })
+    });
     tauri::async_runtime::spawn(file_log_watcher(app.clone()));
 
     Ok(())
 }
 
 pub fn reload_file_logger() -> anyhow::Result<()> {
-    RELOAD_FN.get().context("RELOAD_FN未初始化")?()
+    RELOAD_FN.get().context("RELOAD_FN未初始化")?(false)
+}
+
+/// 清空日志
+///
+/// 启用了文件日志时，先切换到新的日志文件，等缓冲中的日志写完后再删除旧日志文件，
+/// 这样正在写入的日志不会丢失，之后的日志也会正常写入新文件
+pub fn clear_logs(app: &AppHandle) -> anyhow::Result<()> {
+    let enable_file_logger = app.state::<RwLock<Config>>().read().enable_file_logger;
+    if enable_file_logger {
+        RELOAD_FN.get().context("RELOAD_FN未初始化")?(true).context("切换到新的日志文件失败")?;
+    }
+
+    let logs_dir = logs_dir(app).context("获取日志目录失败")?;
+    if !logs_dir.exists() {
+        return Ok(());
+    }
+    let log_paths: Vec<PathBuf> = std::fs::read_dir(&logs_dir)
+        .context(format!("读取日志目录`{logs_dir:?}`失败"))?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            // 未启用文件日志时，没有正在写入的日志文件，可以一并删除
+            let is_current = path.file_name() == Some(CURRENT_LOG_FILENAME.as_ref());
+            is_old_log_file(path) || (!enable_file_logger && is_current)
+        })
+        .collect();
+    for path in log_paths {
+        std::fs::remove_file(&path).context(format!("删除日志文件`{path:?}`失败"))?;
+    }
+    Ok(())
+}
+
+/// 修改日志级别，立即生效，对所有输出(文件、控制台、前端)都有效
+pub fn set_level(log_level: LogLevel) -> anyhow::Result<()> {
+    SET_LEVEL_FN.get().context("SET_LEVEL_FN未初始化")?(log_level)
 }
 
 pub fn disable_file_logger() -> anyhow::Result<()> {
@@ -113,6 +319,7 @@ pub fn disable_file_logger() -> anyhow::Result<()> {
 
 fn create_file_layer<S>(
     app: &AppHandle,
+    start_new_file: bool,
 ) -> anyhow::Result<(Box<dyn Layer<S> + Send + Sync>, Option<WorkerGuard>)>
 where
     S: Subscriber + for<'a> LookupSpan<'a>,
@@ -128,13 +335,29 @@ where
             .with_line_number(true);
         return Ok((Box::new(sink_layer), None));
     }
+    let (log_max_size_mb, log_retention_count, log_format) = {
+        let config = app.state::<RwLock<Config>>();
+        let config = config.read();
+        (
+            config.log_max_size_mb,
+            config.log_retention_count,
+            config.log_format,
+        )
+    };
+    let format_changed = {
+        let mut file_log_format = FILE_LOG_FORMAT.lock();
+        let format_changed = file_log_format.is_some_and(|format| format != log_format);
+        *file_log_format = Some(log_format);
+        format_changed
+    };
     let logs_dir = logs_dir(app).context("获取日志目录失败")?;
-    let file_appender = RollingFileAppender::builder()
-        .filename_prefix("wnacg-downloader")
-        .filename_suffix("log")
-        .rotation(Rotation::DAILY)
-        .build(&logs_dir)
-        .context("创建RollingFileAppender失败")?;
+    let file_appender = SizeRollingWriter::new(
+        logs_dir,
+        log_max_size_mb.saturating_mul(1024 * 1024),
+        log_retention_count,
+        format_changed || start_new_file,
+    )
+    .context("创建SizeRollingWriter失败")?;
     let (non_blocking_appender, guard) = tracing_appender::non_blocking(file_appender);
     let file_layer = layer()
         .with_writer(non_blocking_appender)
@@ -142,7 +365,11 @@ where
         .with_ansi(false)
         .with_file(true)
         .with_line_number(true);
-    Ok((Box::new(file_layer), Some(guard)))
+    match log_format {
+        LogFormat::Text => Ok((Box::new(file_layer), Some(guard))),
+        // 与发送到前端的`LogEvent`结构相同
+        LogFormat::Json => Ok((Box::new(file_layer.json()), Some(guard))),
+    }
 }
 
 async fn file_log_watcher(app: AppHandle) {
@@ -193,7 +420,12 @@ async fn file_log_watcher(app: AppHandle) {
     while let Some(res) = receiver.recv().await {
         match res.map_err(anyhow::Error::from) {
             Ok(event) => {
-                if let notify::EventKind::Remove(_) = event.kind {
+                // 只有正在写入的日志文件被删除时才需要重置，删除旧日志文件不需要
+                let current_log_removed = event
+                    .paths
+                    .iter()
+                    .any(|path| path.file_name() == Some(CURRENT_LOG_FILENAME.as_ref()));
+                if matches!(event.kind, notify::EventKind::Remove(_)) && current_log_removed {
                     if let Err(err) = reload_file_logger() {
                         let err_title = "重置日志文件失败";
                         let string_chain = err.to_string_chain();
@@ -211,9 +443,111 @@ async fn file_log_watcher(app: AppHandle) {
 }
 
 pub fn logs_dir(app: &AppHandle) -> anyhow::Result<std::path::PathBuf> {
-    let app_data_dir = app
-        .path()
-        .app_data_dir()
-        .context("获取app_data_dir目录失败")?;
-    Ok(app_data_dir.join("日志"))
+    let data_dir = utils::data_dir(app).context("获取数据目录失败")?;
+    Ok(data_dir.join("日志"))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct LogFile {
+    pub filename: String,
+    pub size: u64,
+    /// 是否为正在写入的日志文件
+    pub is_current: bool,
+}
+
+/// 日志目录中的所有日志文件，正在写入的排在最前，其余按从新到旧排列
+pub fn get_log_files(app: &AppHandle) -> anyhow::Result<Vec<LogFile>> {
+    let logs_dir = logs_dir(app).context("获取日志目录失败")?;
+    if !logs_dir.exists() {
+        return Ok(vec![]);
+    }
+    let mut log_files: Vec<LogFile> = std::fs::read_dir(&logs_dir)
+        .context(format!("读取日志目录`{logs_dir:?}`失败"))?
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let path = entry.path();
+            let filename = path.file_name()?.to_str()?.to_string();
+            let is_current = filename == CURRENT_LOG_FILENAME;
+            if !is_current && !is_old_log_file(&path) {
+                return None;
+            }
+            let size = entry.metadata().ok()?.len();
+            Some(LogFile {
+                filename,
+                size,
+                is_current,
+            })
+        })
+        .collect();
+    log_files.sort_by(|a, b| {
+        b.is_current
+            .cmp(&a.is_current)
+            .then_with(|| b.filename.cmp(&a.filename))
+    });
+    Ok(log_files)
+}
+
+/// 从日志文件中读取最近的`limit`条级别不低于`min_level`的日志，从新到旧排列
+///
+/// 先读正在写入的日志文件，不够时再读更旧的日志文件  
+/// 两种日志格式都支持，文本格式的日志中除`message`外的字段都会放在`message`中
+pub fn query_logs(
+    app: &AppHandle,
+    min_level: Option<LogLevel>,
+    limit: usize,
+) -> anyhow::Result<Vec<LogEvent>> {
+    let logs_dir = logs_dir(app).context("获取日志目录失败")?;
+    let mut log_events = vec![];
+    // `get_log_files`返回的日志文件已经是从新到旧排列的
+    for log_file in get_log_files(app)? {
+        let log_path = logs_dir.join(&log_file.filename);
+        let content = match std::fs::read_to_string(&log_path) {
+            Ok(content) => content,
+            // 日志文件可能刚好被滚动删除，跳过即可
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err).context(format!("读取日志文件`{log_path:?}`失败")),
+        };
+        let matched_events = content
+            .lines()
+            .rev()
+            .filter_map(parse_log_line)
+            .filter(|log_event| min_level.is_none_or(|min_level| log_event.level >= min_level));
+        for log_event in matched_events {
+            if log_events.len() >= limit {
+                return Ok(log_events);
+            }
+            log_events.push(log_event);
+        }
+    }
+    Ok(log_events)
+}
+
+/// 将日志文件中的一行解析为`LogEvent`，无法解析的行(例如空行)返回`None`
+fn parse_log_line(line: &str) -> Option<LogEvent> {
+    if line.starts_with('{') {
+        return serde_json::from_str(line).ok();
+    }
+    // 文本格式为`{timestamp} {level} {target}: {filename}:{line_number}: {fields}`
+    let (timestamp, rest) = line.split_once(' ')?;
+    let rest = rest.trim_start();
+    let (level, rest) = rest.split_once(' ')?;
+    let level = level.parse::<LogLevel>().ok()?;
+    let (target, rest) = rest.trim_start().split_once(": ")?;
+    let (location, message) = rest.split_once(": ").unwrap_or((rest, ""));
+    let (filename, line_number) = location.rsplit_once(':')?;
+    let line_number = line_number.parse::<i64>().ok()?;
+
+    let fields = HashMap::from([(
+        "message".to_string(),
+        serde_json::Value::String(message.to_string()),
+    )]);
+    Some(LogEvent {
+        timestamp: timestamp.to_string(),
+        level,
+        fields,
+        target: target.to_string(),
+        filename: filename.to_string(),
+        line_number,
+    })
 }
@@ -0,0 +1,80 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow::Context;
+use tauri::AppHandle;
+
+use crate::{types::Comic, utils};
+
+/// 读写文件时需要先读再写，避免多个下载任务同时完成时互相覆盖
+static FILE_LOCK: parking_lot::Mutex<()> = parking_lot::Mutex::new(());
+
+/// 关闭`write_metadata_json`后，元数据集中保存在数据目录中的`元数据索引.json`，键为漫画目录名
+fn store_path(app: &AppHandle) -> anyhow::Result<PathBuf> {
+    Ok(utils::data_dir(app)?.join("元数据索引.json"))
+}
+
+fn load(app: &AppHandle) -> anyhow::Result<HashMap<String, Comic>> {
+    let path = store_path(app)?;
+    if !path.exists() {
+        return Ok(HashMap::new());
+    }
+    let store_string = std::fs::read_to_string(&path).context(format!("读取文件`{path:?}`失败"))?;
+    let store = serde_json::from_str(&store_string)
+        .context(format!("`{path:?}`不是合法的元数据索引文件"))?;
+    Ok(store)
+}
+
+/// 保存漫画的元数据，`comic.title`即漫画目录名
+pub fn put(app: &AppHandle, comic: &Comic) -> anyhow::Result<()> {
+    let _lock = FILE_LOCK.lock();
+    let mut store = load(app)?;
+    let mut comic = comic.clone();
+    comic.is_downloaded = None;
+    comic.disk_size_bytes = None;
+    store.insert(comic.title.clone(), comic);
+    let path = store_path(app)?;
+    let store_string = serde_json::to_string_pretty(&store)?;
+    std::fs::write(&path, store_string).context(format!("写入文件`{path:?}`失败"))?;
+    Ok(())
+}
+
+/// 元数据索引中目录名为`dir_name`的漫画
+pub fn get(app: &AppHandle, dir_name: &str) -> anyhow::Result<Option<Comic>> {
+    let mut store = {
+        let _lock = FILE_LOCK.lock();
+        load(app)?
+    };
+    Ok(store.remove(dir_name))
+}
+
+/// `download_dir`中没有`元数据.json`、但在元数据索引中的漫画，以及漫画目录的修改时间
+///
+/// 目录已被删除的漫画和`exclude_ids`中的漫画会被忽略
+pub fn comics_without_metadata_json(
+    app: &AppHandle,
+    download_dir: &Path,
+    exclude_ids: &HashSet<i64>,
+) -> anyhow::Result<Vec<(SystemTime, Comic)>> {
+    let store = {
+        let _lock = FILE_LOCK.lock();
+        load(app)?
+    };
+    let comics = store
+        .into_iter()
+        .filter(|(_, comic)| !exclude_ids.contains(&comic.id))
+        .filter_map(|(dir_name, mut comic)| {
+            let comic_dir = download_dir.join(&dir_name);
+            if comic_dir.join("元数据.json").exists() {
+                return None;
+            }
+            let modify_time = comic_dir.metadata().ok()?.modified().ok()?;
+            comic.is_downloaded = Some(true);
+            Some((modify_time, comic))
+        })
+        .collect();
+    Ok(comics)
+}
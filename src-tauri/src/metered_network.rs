@@ -0,0 +1,99 @@
+use std::time::Duration;
+
+use parking_lot::RwLock;
+use tauri::{AppHandle, Manager};
+use tokio::time::sleep;
+
+use crate::{
+    config::Config, download_manager::DownloadManager, extensions::AnyhowErrorToStringChain,
+};
+
+/// 检查当前网络是否按流量计费的间隔
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// 查询当前网络是否按流量计费，不同平台有不同的实现
+trait NetworkCost: Send + Sync + 'static {
+    fn is_metered(&self) -> anyhow::Result<bool>;
+}
+
+/// 通过WinRT的`NetworkInformation`查询当前网络连接的计费方式
+#[cfg(windows)]
+struct WindowsNetworkCost;
+
+#[cfg(windows)]
+impl NetworkCost for WindowsNetworkCost {
+    fn is_metered(&self) -> anyhow::Result<bool> {
+        use anyhow::Context;
+        use windows::Networking::Connectivity::{NetworkCostType, NetworkInformation};
+
+        // 没有网络连接时返回错误，视为不按流量计费
+        let Ok(profile) = NetworkInformation::GetInternetConnectionProfile() else {
+            return Ok(false);
+        };
+        let cost = profile
+            .GetConnectionCost()
+            .context("获取网络连接的计费信息失败")?;
+        let cost_type = cost
+            .NetworkCostType()
+            .context("获取网络连接的计费方式失败")?;
+        let is_metered = matches!(
+            cost_type,
+            NetworkCostType::Fixed | NetworkCostType::Variable
+        ) || cost.Roaming().unwrap_or(false)
+            || cost.OverDataLimit().unwrap_or(false);
+        Ok(is_metered)
+    }
+}
+
+/// 其他平台无法查询网络的计费方式，总是视为不按流量计费
+#[cfg(not(windows))]
+struct UnsupportedNetworkCost;
+
+#[cfg(not(windows))]
+impl NetworkCost for UnsupportedNetworkCost {
+    fn is_metered(&self) -> anyhow::Result<bool> {
+        Ok(false)
+    }
+}
+
+#[cfg(windows)]
+fn network_cost() -> impl NetworkCost {
+    WindowsNetworkCost
+}
+
+#[cfg(not(windows))]
+fn network_cost() -> impl NetworkCost {
+    UnsupportedNetworkCost
+}
+
+/// 开启`config.pause_on_metered`时，每隔`CHECK_INTERVAL`检查一次当前网络是否按流量计费，
+/// 切换到按流量计费的网络时暂停所有下载任务，切换回来后恢复这些任务
+///
+/// 只有Windows能查询网络的计费方式，其他平台上什么都不做
+pub async fn watch_metered_loop(app: AppHandle) {
+    loop {
+        let pause_on_metered = app.state::<RwLock<Config>>().read().pause_on_metered;
+        // 关闭`pause_on_metered`时视为不按流量计费，恢复之前因此暂停的任务
+        let is_metered = if pause_on_metered {
+            let is_metered = tauri::async_runtime::spawn_blocking(|| network_cost().is_metered())
+                .await
+                .map_err(anyhow::Error::from)
+                .and_then(|result| result);
+            match is_metered {
+                Ok(is_metered) => is_metered,
+                Err(err) => {
+                    let err_title = "检查当前网络是否按流量计费失败";
+                    let string_chain = err.to_string_chain();
+                    tracing::warn!(err_title, message = string_chain);
+                    sleep(CHECK_INTERVAL).await;
+                    continue;
+                }
+            }
+        } else {
+            false
+        };
+        app.state::<DownloadManager>()
+            .on_metered_changed(is_metered);
+        sleep(CHECK_INTERVAL).await;
+    }
+}
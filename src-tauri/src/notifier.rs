@@ -0,0 +1,104 @@
+use std::time::Duration;
+
+use parking_lot::{Mutex, RwLock};
+use tauri::{AppHandle, Manager};
+use tauri_plugin_notification::NotificationExt;
+
+use crate::{config::Config, extensions::AnyhowErrorToStringChain};
+
+/// 在这段时间内结束的下载任务会合并为一条通知，以免批量下载时通知刷屏
+const BATCH_WINDOW: Duration = Duration::from_secs(3);
+
+/// 等待合并发送通知的下载结果
+static PENDING_OUTCOMES: Mutex<Vec<DownloadOutcome>> = Mutex::new(vec![]);
+
+pub enum DownloadOutcome {
+    Completed {
+        comic_title: String,
+        img_count: u32,
+    },
+    Failed {
+        comic_title: String,
+        reason: String,
+    },
+    /// 下载目录中已有同名目录，按`OnExistingFolder::Skip`保留了已有的目录
+    Skipped {
+        comic_title: String,
+    },
+}
+
+/// 如果开启了`notify_on_completion`，则在`BATCH_WINDOW`后发送系统通知
+pub fn notify_download_finished(app: &AppHandle, outcome: DownloadOutcome) {
+    if !app.state::<RwLock<Config>>().read().notify_on_completion {
+        return;
+    }
+    let mut pending_outcomes = PENDING_OUTCOMES.lock();
+    let is_first = pending_outcomes.is_empty();
+    pending_outcomes.push(outcome);
+    if !is_first {
+        // 已经有等待发送的通知了，会一并发送
+        return;
+    }
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        tokio::time::sleep(BATCH_WINDOW).await;
+        let outcomes = std::mem::take(&mut *PENDING_OUTCOMES.lock());
+        show_notification(&app, &outcomes);
+    });
+}
+
+fn show_notification(app: &AppHandle, outcomes: &[DownloadOutcome]) {
+    let (title, body) = match outcomes {
+        [] => return,
+        [DownloadOutcome::Completed {
+            comic_title,
+            img_count,
+        }] => (
+            "下载完成".to_string(),
+            format!("{comic_title}\n{img_count}/{img_count}页"),
+        ),
+        [DownloadOutcome::Failed {
+            comic_title,
+            reason,
+        }] => ("下载失败".to_string(), format!("{comic_title}\n{reason}")),
+        [DownloadOutcome::Skipped { comic_title }] => (
+            "已跳过".to_string(),
+            format!("{comic_title}\n下载目录中已有同名目录"),
+        ),
+        _ => {
+            let failed_titles = outcomes
+                .iter()
+                .filter_map(|outcome| match outcome {
+                    DownloadOutcome::Failed { comic_title, .. } => Some(comic_title.as_str()),
+                    DownloadOutcome::Completed { .. } | DownloadOutcome::Skipped { .. } => None,
+                })
+                .collect::<Vec<_>>();
+            let skipped_count = outcomes
+                .iter()
+                .filter(|outcome| matches!(outcome, DownloadOutcome::Skipped { .. }))
+                .count();
+            let completed_count = outcomes.len() - failed_titles.len() - skipped_count;
+            let mut body = format!("成功{completed_count}部，失败{}部", failed_titles.len());
+            if skipped_count > 0 {
+                body.push_str(&format!("，跳过{skipped_count}部"));
+            }
+            if !failed_titles.is_empty() {
+                body.push_str(&format!("\n失败: {}", failed_titles.join("、")));
+            }
+            (format!("{}部漫画下载结束", outcomes.len()), body)
+        }
+    };
+
+    if let Err(err) = app
+        .notification()
+        .builder()
+        .title(title)
+        .body(body)
+        .show()
+        .map_err(anyhow::Error::from)
+    {
+        let err_title = "发送系统通知失败";
+        let string_chain = err.to_string_chain();
+        tracing::error!(err_title, message = string_chain);
+    }
+}
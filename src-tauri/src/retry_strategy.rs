@@ -0,0 +1,190 @@
+use std::time::{Duration, Instant};
+
+use reqwest::{header::RETRY_AFTER, Request, Response, StatusCode};
+use reqwest_middleware::{Middleware, Next};
+use reqwest_retry::{default_on_request_failure, Retryable, RetryableStrategy};
+
+/// 决定`RetryTransientMiddleware`是否重试
+///
+/// 带有`Retry-After`的429交给`RetryAfterMiddleware`处理，这里视为不可重试，以免两边重复重试
+pub struct ApiRetryStrategy;
+
+impl RetryableStrategy for ApiRetryStrategy {
+    fn handle(&self, res: &reqwest_middleware::Result<Response>) -> Option<Retryable> {
+        match res {
+            Ok(resp) => {
+                let status = resp.status();
+                if status == StatusCode::TOO_MANY_REQUESTS {
+                    if retry_after(resp).is_some() {
+                        return Some(Retryable::Fatal);
+                    }
+                    return Some(Retryable::Transient);
+                }
+                if status.is_server_error() || status == StatusCode::REQUEST_TIMEOUT {
+                    Some(Retryable::Transient)
+                } else if status.is_client_error() {
+                    // 404等错误重试也不会有不同的结果
+                    Some(Retryable::Fatal)
+                } else {
+                    None
+                }
+            }
+            // 超时、连接失败等网络错误
+            Err(err) => default_on_request_failure(err),
+        }
+    }
+}
+
+/// 收到带有`Retry-After`的429时，等待指定的时间后重试
+///
+/// 等待时间会累计，超过`budget`时不再重试，直接返回429
+pub struct RetryAfterMiddleware {
+    pub budget: Duration,
+}
+
+#[async_trait::async_trait]
+impl Middleware for RetryAfterMiddleware {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut http::Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        let start = Instant::now();
+        loop {
+            // body是流的请求无法克隆，也就无法重试
+            let Some(retry_req) = req.try_clone() else {
+                return next.run(req, extensions).await;
+            };
+            let resp = next.clone().run(retry_req, extensions).await?;
+            if resp.status() != StatusCode::TOO_MANY_REQUESTS {
+                return Ok(resp);
+            }
+            let Some(retry_after) = retry_after(&resp) else {
+                return Ok(resp);
+            };
+            if start.elapsed() + retry_after > self.budget {
+                return Ok(resp);
+            }
+            tracing::debug!(
+                url = req.url().as_str(),
+                "请求过于频繁，{}秒后重试",
+                retry_after.as_secs()
+            );
+            tokio::time::sleep(retry_after).await;
+        }
+    }
+}
+
+/// 解析以秒为单位的`Retry-After`，不支持HTTP日期格式
+fn retry_after(resp: &Response) -> Option<Duration> {
+    let retry_after = resp.headers().get(RETRY_AFTER)?.to_str().ok()?;
+    let secs = retry_after.trim().parse::<u64>().ok()?;
+    Some(Duration::from_secs(secs))
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest_middleware::ClientWithMiddleware;
+    use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+
+    /// 只挂载`RetryTransientMiddleware`，重试间隔缩短到1毫秒
+    fn transient_client() -> ClientWithMiddleware {
+        let retry_policy = ExponentialBackoff::builder()
+            .retry_bounds(Duration::from_millis(1), Duration::from_millis(1))
+            .build_with_max_retries(3);
+        reqwest_middleware::ClientBuilder::new(reqwest::Client::new())
+            .with(RetryTransientMiddleware::new_with_policy_and_strategy(
+                retry_policy,
+                ApiRetryStrategy,
+            ))
+            .build()
+    }
+
+    fn retry_after_client(budget: Duration) -> ClientWithMiddleware {
+        reqwest_middleware::ClientBuilder::new(reqwest::Client::new())
+            .with(RetryAfterMiddleware { budget })
+            .build()
+    }
+
+    /// 第一次请求返回`first`，之后都返回200
+    async fn mock_server(first: ResponseTemplate) -> MockServer {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/"))
+            .respond_with(first)
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+        server
+    }
+
+    async fn request_count(server: &MockServer) -> usize {
+        server.received_requests().await.unwrap().len()
+    }
+
+    #[tokio::test]
+    async fn api_retry_strategy_retries_429_without_retry_after() {
+        let server = mock_server(ResponseTemplate::new(429)).await;
+        let resp = transient_client().get(server.uri()).send().await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(request_count(&server).await, 2);
+    }
+
+    #[tokio::test]
+    async fn api_retry_strategy_leaves_429_with_retry_after_to_middleware() {
+        let first = ResponseTemplate::new(429).insert_header("Retry-After", "0");
+        let server = mock_server(first).await;
+        let resp = transient_client().get(server.uri()).send().await.unwrap();
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(request_count(&server).await, 1);
+    }
+
+    #[tokio::test]
+    async fn api_retry_strategy_does_not_retry_404() {
+        let server = mock_server(ResponseTemplate::new(404)).await;
+        let resp = transient_client().get(server.uri()).send().await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        assert_eq!(request_count(&server).await, 1);
+    }
+
+    #[tokio::test]
+    async fn retry_after_middleware_retries_429_then_200() {
+        let first = ResponseTemplate::new(429).insert_header("Retry-After", "0");
+        let server = mock_server(first).await;
+        let client = retry_after_client(Duration::from_secs(10));
+        let resp = client.get(server.uri()).send().await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(request_count(&server).await, 2);
+    }
+
+    #[tokio::test]
+    async fn retry_after_middleware_gives_up_over_budget() {
+        let first = ResponseTemplate::new(429).insert_header("Retry-After", "60");
+        let server = mock_server(first).await;
+        let client = retry_after_client(Duration::from_secs(10));
+        let resp = client.get(server.uri()).send().await.unwrap();
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(request_count(&server).await, 1);
+    }
+
+    #[tokio::test]
+    async fn retry_after_middleware_does_not_retry_404() {
+        let server = mock_server(ResponseTemplate::new(404)).await;
+        let client = retry_after_client(Duration::from_secs(10));
+        let resp = client.get(server.uri()).send().await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        assert_eq!(request_count(&server).await, 1);
+    }
+}
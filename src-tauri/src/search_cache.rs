@@ -0,0 +1,92 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri::async_runtime::JoinHandle;
+
+use crate::types::SearchResult;
+
+/// 搜索结果在缓存中的有效期
+const CACHE_TTL: Duration = Duration::from_secs(5 * 60);
+/// 预取时遇到`RateLimitedError`后，暂停预取的时长
+const RATE_LIMITED_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// 搜索的查询条件，关键词或标签名
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Type)]
+pub enum SearchQuery {
+    Keyword(String),
+    Tag(String),
+}
+
+/// 短时间内的搜索结果缓存，只缓存最近一次搜索的查询条件对应的结果
+///
+/// 查询条件变化时清空缓存，并取消正在进行的预取
+#[derive(Default)]
+pub struct SearchCache {
+    current_query: Option<SearchQuery>,
+    results: HashMap<i64, (Instant, SearchResult)>,
+    prefetch_task: Option<JoinHandle<()>>,
+    rate_limited_until: Option<Instant>,
+}
+
+impl SearchCache {
+    /// 用户发起搜索时调用，查询条件变化时清空缓存并取消预取
+    pub fn on_search(&mut self, query: &SearchQuery) {
+        if self.current_query.as_ref() == Some(query) {
+            return;
+        }
+        self.current_query = Some(query.clone());
+        self.results.clear();
+        if let Some(prefetch_task) = self.prefetch_task.take() {
+            prefetch_task.abort();
+        }
+    }
+
+    pub fn get(&self, query: &SearchQuery, page_num: i64) -> Option<SearchResult> {
+        if self.current_query.as_ref() != Some(query) {
+            return None;
+        }
+        let (cached_at, search_result) = self.results.get(&page_num)?;
+        if cached_at.elapsed() > CACHE_TTL {
+            return None;
+        }
+        Some(search_result.clone())
+    }
+
+    pub fn contains(&self, query: &SearchQuery, page_num: i64) -> bool {
+        self.get(query, page_num).is_some()
+    }
+
+    /// 查询条件已经不是最近一次搜索的(例如预取完成前用户换了关键词)时丢弃结果
+    pub fn put(&mut self, query: &SearchQuery, page_num: i64, search_result: SearchResult) {
+        if self.current_query.as_ref() != Some(query) {
+            return;
+        }
+        self.results
+            .retain(|_, (cached_at, _)| cached_at.elapsed() <= CACHE_TTL);
+        self.results
+            .insert(page_num, (Instant::now(), search_result));
+    }
+
+    /// 记录新的预取任务，同时只保留一个预取任务
+    pub fn set_prefetch_task(&mut self, prefetch_task: JoinHandle<()>) {
+        if let Some(old_prefetch_task) = self.prefetch_task.replace(prefetch_task) {
+            old_prefetch_task.abort();
+        }
+    }
+
+    pub fn on_rate_limited(&mut self) {
+        self.rate_limited_until = Some(Instant::now() + RATE_LIMITED_COOLDOWN);
+        if let Some(prefetch_task) = self.prefetch_task.take() {
+            prefetch_task.abort();
+        }
+    }
+
+    pub fn is_rate_limited(&self) -> bool {
+        self.rate_limited_until
+            .is_some_and(|rate_limited_until| Instant::now() < rate_limited_until)
+    }
+}
@@ -0,0 +1,138 @@
+use std::{collections::HashSet, path::PathBuf, time::Duration};
+
+use anyhow::Context;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+use tauri_specta::Event;
+use tokio::time::sleep;
+
+use crate::{
+    batch_download, config::Config, cookie_store::CookieStore, download_manager::DownloadManager,
+    errors::SessionExpiredError, events::ShelfComicsQueuedEvent,
+    extensions::AnyhowErrorToStringChain, utils, wnacg_client::WnacgClient,
+};
+
+/// 监视的书架中已经见过的漫画id，保存在数据目录中的`watched_shelf.json`
+#[derive(Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SeenComics {
+    shelf_id: i64,
+    comic_ids: HashSet<i64>,
+}
+
+impl SeenComics {
+    fn load(app: &AppHandle) -> anyhow::Result<Option<SeenComics>> {
+        let path = seen_comics_path(app)?;
+        if !path.exists() {
+            return Ok(None);
+        }
+        let seen_comics_string =
+            std::fs::read_to_string(&path).context(format!("读取文件`{path:?}`失败"))?;
+        let seen_comics = serde_json::from_str(&seen_comics_string)
+            .context(format!("`{path:?}`不是合法的JSON文件"))?;
+        Ok(Some(seen_comics))
+    }
+
+    fn save(&self, app: &AppHandle) -> anyhow::Result<()> {
+        let path = seen_comics_path(app)?;
+        let seen_comics_string = serde_json::to_string_pretty(self)?;
+        std::fs::write(&path, seen_comics_string).context(format!("写入文件`{path:?}`失败"))?;
+        Ok(())
+    }
+}
+
+fn seen_comics_path(app: &AppHandle) -> anyhow::Result<PathBuf> {
+    Ok(utils::data_dir(app)?.join("watched_shelf.json"))
+}
+
+/// 设置了`config.watch_shelf_id`时，每隔`config.watch_interval_min`分钟检查一次书架的第一页，
+/// 自动下载新加入书架的漫画
+///
+/// cookie过期后暂停检查，直到cookie发生变化(重新登录)后再继续
+pub async fn watch_shelf_loop(app: AppHandle) {
+    let mut expired_cookie = None;
+    loop {
+        let (watch_shelf_id, watch_interval_min) = {
+            let config = app.state::<RwLock<Config>>();
+            let config = config.read();
+            (config.watch_shelf_id, config.watch_interval_min)
+        };
+        sleep(Duration::from_secs(watch_interval_min.saturating_mul(60))).await;
+
+        let Some(shelf_id) = watch_shelf_id else {
+            continue;
+        };
+        let cookie = CookieStore::get(&app);
+        if cookie.is_empty() || expired_cookie.as_ref() == Some(&cookie) {
+            continue;
+        }
+
+        let Err(err) = check_shelf(&app, shelf_id).await else {
+            continue;
+        };
+        if err.downcast_ref::<SessionExpiredError>().is_some() {
+            tracing::warn!("cookie已过期，暂停监视书架，重新登录后继续");
+            expired_cookie = Some(cookie);
+        } else {
+            let err_title = format!("检查书架`{shelf_id}`失败");
+            let string_chain = err.to_string_chain();
+            tracing::error!(err_title, message = string_chain);
+        }
+    }
+}
+
+/// 第一次检查某个书架时只记录书架中已有的漫画，不自动下载
+async fn check_shelf(app: &AppHandle, shelf_id: i64) -> anyhow::Result<()> {
+    let wnacg_client = app.state::<WnacgClient>().inner().clone();
+    let get_favorite_result = wnacg_client.get_favorite(shelf_id, 1).await?;
+
+    let seen_comics = SeenComics::load(app)?.filter(|seen| seen.shelf_id == shelf_id);
+    let Some(mut seen_comics) = seen_comics else {
+        let seen_comics = SeenComics {
+            shelf_id,
+            comic_ids: get_favorite_result.comics.iter().map(|c| c.id).collect(),
+        };
+        seen_comics.save(app)?;
+        tracing::debug!("开始监视书架`{shelf_id}`");
+        return Ok(());
+    };
+
+    let mut queued_comics = vec![];
+    for comic_in_favorite in get_favorite_result.comics {
+        if !seen_comics.comic_ids.insert(comic_in_favorite.id) {
+            continue;
+        }
+        if comic_in_favorite.is_downloaded {
+            continue;
+        }
+        let comic = wnacg_client
+            .get_comic(comic_in_favorite.id)
+            .await
+            .context(format!("获取漫画ID为`{}`的漫画失败", comic_in_favorite.id))?;
+        let excluded_tags = app.state::<RwLock<Config>>().read().excluded_tags.clone();
+        if let Some(tag) = batch_download::find_excluded_tag(&comic, &excluded_tags) {
+            let comic_id = comic.id;
+            let comic_title = &comic.title;
+            tracing::info!(comic_id, comic_title, "漫画带有排除的标签`{tag}`，已跳过");
+            continue;
+        }
+        app.state::<DownloadManager>()
+            .create_download_task(comic, None);
+        queued_comics.push(comic_in_favorite);
+    }
+    seen_comics.save(app)?;
+
+    if !queued_comics.is_empty() {
+        tracing::info!(
+            "书架`{shelf_id}`中有`{}`部新漫画，已加入下载队列",
+            queued_comics.len()
+        );
+        let _ = ShelfComicsQueuedEvent {
+            shelf_id,
+            comics: queued_comics,
+        }
+        .emit(app);
+    }
+    Ok(())
+}
@@ -0,0 +1,190 @@
+use std::{path::PathBuf, time::Duration};
+
+use anyhow::{anyhow, Context};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri::{AppHandle, Manager};
+use tauri_specta::Event;
+use tokio::time::sleep;
+
+use crate::{
+    config::Config, download_manager::DownloadManager, errors::RateLimitedError,
+    events::NewComicsEvent, extensions::AnyhowErrorToStringChain, search_cache::SearchQuery, utils,
+    wnacg_client::WnacgClient,
+};
+
+/// 检查相邻两个订阅之间的间隔，以免请求过于频繁
+const CHECK_INTERVAL_BETWEEN_TAGS: Duration = Duration::from_secs(3);
+
+/// 订阅的标签
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct Subscription {
+    pub tag: String,
+    /// 上次检查时看到的最新漫画id，为`None`表示还没检查过
+    pub last_seen_comic_id: Option<i64>,
+    /// 发现新漫画时是否自动下载
+    pub auto_download: bool,
+}
+
+/// 所有订阅，保存在数据目录中的`subscriptions.json`
+#[derive(Default)]
+pub struct Subscriptions {
+    subscriptions: Vec<Subscription>,
+}
+
+impl Subscriptions {
+    pub fn load(app: &AppHandle) -> anyhow::Result<Subscriptions> {
+        let path = subscriptions_path(app)?;
+        if !path.exists() {
+            return Ok(Subscriptions::default());
+        }
+        let subscriptions_string =
+            std::fs::read_to_string(&path).context(format!("读取文件`{path:?}`失败"))?;
+        let subscriptions = serde_json::from_str(&subscriptions_string)
+            .context(format!("`{path:?}`不是合法的订阅文件"))?;
+        Ok(Subscriptions { subscriptions })
+    }
+
+    fn save(&self, app: &AppHandle) -> anyhow::Result<()> {
+        let path = subscriptions_path(app)?;
+        let subscriptions_string = serde_json::to_string_pretty(&self.subscriptions)?;
+        std::fs::write(&path, subscriptions_string).context(format!("写入文件`{path:?}`失败"))?;
+        Ok(())
+    }
+
+    pub fn list(&self) -> Vec<Subscription> {
+        self.subscriptions.clone()
+    }
+
+    pub fn add(&mut self, app: &AppHandle, tag: String, auto_download: bool) -> anyhow::Result<()> {
+        if self.subscriptions.iter().any(|s| s.tag == tag) {
+            return Err(anyhow!("已经订阅了标签`{tag}`"));
+        }
+        self.subscriptions.push(Subscription {
+            tag,
+            last_seen_comic_id: None,
+            auto_download,
+        });
+        self.save(app)
+    }
+
+    pub fn remove(&mut self, app: &AppHandle, tag: &str) -> anyhow::Result<()> {
+        let len = self.subscriptions.len();
+        self.subscriptions.retain(|s| s.tag != tag);
+        if self.subscriptions.len() == len {
+            return Err(anyhow!("没有订阅标签`{tag}`"));
+        }
+        self.save(app)
+    }
+
+    /// 更新订阅的`last_seen_comic_id`，订阅在检查期间被删除时忽略
+    fn update_last_seen(
+        &mut self,
+        app: &AppHandle,
+        tag: &str,
+        comic_id: i64,
+    ) -> anyhow::Result<()> {
+        let Some(subscription) = self.subscriptions.iter_mut().find(|s| s.tag == tag) else {
+            return Ok(());
+        };
+        subscription.last_seen_comic_id = Some(comic_id);
+        self.save(app)
+    }
+}
+
+fn subscriptions_path(app: &AppHandle) -> anyhow::Result<PathBuf> {
+    Ok(utils::data_dir(app)?.join("subscriptions.json"))
+}
+
+/// 每隔`config.subscription_check_interval_min`分钟检查一次所有订阅
+pub async fn check_subscriptions_loop(app: AppHandle) {
+    loop {
+        let interval_min = app
+            .state::<RwLock<Config>>()
+            .read()
+            .subscription_check_interval_min;
+        sleep(Duration::from_secs(interval_min.saturating_mul(60))).await;
+
+        let subscriptions = app.state::<RwLock<Subscriptions>>().read().list();
+        if subscriptions.is_empty() {
+            continue;
+        }
+        // 离线时跳过这一轮检查
+        let config = app.state::<RwLock<Config>>().read().clone();
+        if let Err(err) = WnacgClient::check_reachable(&config).await {
+            tracing::debug!("无法访问网站，跳过这一轮订阅检查: {err:#}");
+            continue;
+        }
+
+        for subscription in subscriptions {
+            if let Err(err) = check_subscription(&app, &subscription).await {
+                let err_title = format!("检查订阅的标签`{}`失败", subscription.tag);
+                let string_chain = err.to_string_chain();
+                tracing::error!(err_title, message = string_chain);
+                // 被限流时继续检查只会让封禁持续更久，剩下的订阅留到下一轮
+                if err.is::<RateLimitedError>() {
+                    tracing::warn!("检查订阅时被限流，停止这一轮订阅检查");
+                    break;
+                }
+            }
+            sleep(CHECK_INTERVAL_BETWEEN_TAGS).await;
+        }
+    }
+}
+
+/// 只检查搜索结果的第一页，第一次检查时只记录最新的漫画id，不视为新漫画
+async fn check_subscription(app: &AppHandle, subscription: &Subscription) -> anyhow::Result<()> {
+    let tag = &subscription.tag;
+    let wnacg_client = app.state::<WnacgClient>().inner().clone();
+    let query = SearchQuery::Tag(tag.clone());
+    let search_result = wnacg_client.fetch_search_result(&query, 1).await?;
+
+    let Some(latest_comic_id) = search_result.comics.iter().map(|comic| comic.id).max() else {
+        return Ok(());
+    };
+    let new_comics = match subscription.last_seen_comic_id {
+        Some(last_seen_comic_id) => search_result
+            .comics
+            .into_iter()
+            .filter(|comic| comic.id > last_seen_comic_id)
+            .collect::<Vec<_>>(),
+        None => vec![],
+    };
+    if !new_comics.is_empty() {
+        tracing::info!("订阅的标签`{tag}`有`{}`部新漫画", new_comics.len());
+    }
+
+    if subscription.auto_download {
+        // 按id从小到大创建下载任务，每创建一个就推进`last_seen_comic_id`，
+        // 中途失败时，剩下的漫画在下一轮检查时仍会被视为新漫画
+        let mut new_comic_ids = new_comics.iter().map(|comic| comic.id).collect::<Vec<_>>();
+        new_comic_ids.sort_unstable();
+        for comic_id in new_comic_ids {
+            let comic = wnacg_client
+                .get_comic(comic_id)
+                .await
+                .context(format!("获取漫画ID为`{comic_id}`的漫画失败"))?;
+            app.state::<DownloadManager>()
+                .create_download_task(comic, None);
+            app.state::<RwLock<Subscriptions>>()
+                .write()
+                .update_last_seen(app, tag, comic_id)?;
+        }
+    }
+    app.state::<RwLock<Subscriptions>>()
+        .write()
+        .update_last_seen(app, tag, latest_comic_id)?;
+    if new_comics.is_empty() {
+        return Ok(());
+    }
+
+    let _ = NewComicsEvent {
+        tag: tag.clone(),
+        comics: new_comics,
+    }
+    .emit(app);
+
+    Ok(())
+}
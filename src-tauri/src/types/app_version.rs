@@ -0,0 +1,12 @@
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct AppVersion {
+    pub version: String,
+    /// 构建时的git commit hash，无法获取时为`None`
+    pub git_hash: Option<String>,
+    /// 是否为debug构建
+    pub is_debug: bool,
+}
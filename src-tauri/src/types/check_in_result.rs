@@ -0,0 +1,60 @@
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use crate::errors::SessionExpiredError;
+
+/// 签到接口返回的json
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckInResp {
+    pub ret: bool,
+    pub html: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckInResult {
+    /// 今天已经签到过，这次没有获得积分
+    pub already_checked_in: bool,
+    /// 这次签到获得的积分，提示中没有时为None
+    pub points: Option<i64>,
+    /// 网站返回的提示
+    pub message: String,
+}
+
+impl CheckInResult {
+    pub fn from_resp(resp: CheckInResp) -> anyhow::Result<CheckInResult> {
+        let message = resp.html.trim().to_string();
+        if resp.ret {
+            let points = parse_first_number(&message);
+            return Ok(CheckInResult {
+                already_checked_in: false,
+                points,
+                message,
+            });
+        }
+        // 简繁两种提示都可能出现
+        if ["已簽到", "已签到"].iter().any(|s| message.contains(s)) {
+            return Ok(CheckInResult {
+                already_checked_in: true,
+                points: None,
+                message,
+            });
+        }
+        if ["登錄", "登录"].iter().any(|s| message.contains(s)) {
+            return Err(SessionExpiredError.into());
+        }
+        Err(anyhow!("签到失败: {message}"))
+    }
+}
+
+/// 解析`text`中的第一个数字，例如`簽到成功，獲得 5 積分`解析为`5`
+fn parse_first_number(text: &str) -> Option<i64> {
+    let digits = text
+        .chars()
+        .skip_while(|c| !c.is_ascii_digit())
+        .take_while(char::is_ascii_digit)
+        .collect::<String>();
+    digits.parse::<i64>().ok()
+}
@@ -1,4 +1,4 @@
-use std::path::Path;
+use std::{collections::BTreeMap, path::Path, sync::LazyLock};
 
 use anyhow::Context;
 use parking_lot::RwLock;
@@ -7,9 +7,29 @@ use serde::{Deserialize, Serialize};
 use specta::Type;
 use tauri::{AppHandle, Manager};
 
-use crate::{config::Config, extensions::ToAnyhow, utils::filename_filter};
+use crate::{
+    config::Config,
+    extensions::AnyhowErrorToStringChain,
+    library,
+    utils::{filename_filter, normalize_url},
+    wnacg_client,
+};
 
-use super::{ImgList, Tag};
+use super::{DownloadFormat, ImgList, Tag};
+
+static ID_LINK_SELECTOR: LazyLock<Selector> =
+    LazyLock::new(|| Selector::parse("head > link").unwrap());
+static TITLE_H2_SELECTOR: LazyLock<Selector> =
+    LazyLock::new(|| Selector::parse("#bodywrap > h2").unwrap());
+static COVER_IMG_SELECTOR: LazyLock<Selector> =
+    LazyLock::new(|| Selector::parse(".asTBcell.uwthumb > img").unwrap());
+static LABEL_SELECTOR: LazyLock<Selector> =
+    LazyLock::new(|| Selector::parse(".asTBcell.uwconn > label").unwrap());
+static TAG_A_SELECTOR: LazyLock<Selector> = LazyLock::new(|| Selector::parse(".tagshow").unwrap());
+static INTRO_P_SELECTOR: LazyLock<Selector> =
+    LazyLock::new(|| Selector::parse(".asTBcell.uwconn > p").unwrap());
+static COVER_CELL_SELECTOR: LazyLock<Selector> =
+    LazyLock::new(|| Selector::parse(".asTBcell.uwthumb").unwrap());
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
 #[serde(rename_all = "camelCase")]
@@ -19,6 +39,9 @@ pub struct Comic {
     pub id: i64,
     /// 漫画标题
     pub title: String,
+    /// 漫画详情页的链接，旧版本保存的元数据中没有，读取时会重新生成
+    #[serde(default)]
+    pub url: String,
     /// 封面链接
     pub cover: String,
     /// 分类
@@ -29,23 +52,72 @@ pub struct Comic {
     pub tags: Vec<Tag>,
     /// 简介
     pub intro: String,
+    /// 语言(中文、日本語、English、한국어)，从分类和标签中推断，无法推断时为`None`
+    pub language: Option<String>,
+    /// 翻译组，从标题的方括号或标签中找出，没有时为`None`
+    pub group: Option<String>,
     /// 是否已下载
     #[serde(skip_serializing_if = "Option::is_none")]
     pub is_downloaded: Option<bool>,
+    /// 下载时实际使用的图片格式，只在下载完成后保存的元数据中有值
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub download_format: Option<DownloadFormat>,
+    /// 漫画目录占用的磁盘空间，单位为字节，只在已下载漫画的列表中有值，不会保存到元数据中
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disk_size_bytes: Option<u64>,
+    /// 开启`dedupe_pages`下载时，重复的页码到与它内容相同的页码的映射，页码从1开始
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub duplicate_pages: BTreeMap<u32, u32>,
     /// 图片列表
     pub img_list: ImgList,
 }
 
+/// 不含图片列表等大字段的漫画信息，用于频繁发送的事件
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct ComicBrief {
+    /// 漫画id
+    pub id: i64,
+    /// 漫画标题
+    pub title: String,
+    /// 封面链接
+    pub cover: String,
+    /// 分类
+    pub category: String,
+    /// 漫画有多少张图片
+    pub image_count: i64,
+}
+
+impl From<&Comic> for ComicBrief {
+    fn from(comic: &Comic) -> Self {
+        ComicBrief {
+            id: comic.id,
+            title: comic.title.clone(),
+            cover: comic.cover.clone(),
+            category: comic.category.clone(),
+            image_count: comic.image_count,
+        }
+    }
+}
+
 impl Comic {
+    pub fn from_html(app: &AppHandle, html: &str, img_list: ImgList) -> anyhow::Result<Comic> {
+        let mut comic = Self::parse_html(html, img_list)?;
+        let is_downloaded = library::is_downloaded(app, comic.id, &comic.title);
+        comic.is_downloaded = Some(is_downloaded);
+        Ok(comic)
+    }
+
+    /// 解析漫画详情页，不检查漫画是否已下载，返回的`is_downloaded`为`None`
     // TODO: 拆分成多个函数
     #[allow(clippy::too_many_lines)]
-    pub fn from_html(app: &AppHandle, html: &str, img_list: ImgList) -> anyhow::Result<Comic> {
+    fn parse_html(html: &str, img_list: ImgList) -> anyhow::Result<Comic> {
         let document = Html::parse_document(html);
 
         let document_html = document.html();
 
         let link = document
-            .select(&Selector::parse("head > link").to_anyhow()?)
+            .select(&ID_LINK_SELECTOR)
             .next()
             .context(format!("没有找到漫画id的<link>: {document_html}"))?;
         let link_html = link.html();
@@ -63,7 +135,7 @@ impl Comic {
             .context(format!("漫画id不是整数: {link_html}"))?;
 
         let h2 = document
-            .select(&Selector::parse("#bodywrap > h2").to_anyhow()?)
+            .select(&TITLE_H2_SELECTOR)
             .next()
             .context(format!("没有找到漫画标题的<h2>: {document_html}"))?;
         let h2_html = h2.html();
@@ -75,20 +147,18 @@ impl Comic {
         let title = filename_filter(title);
 
         let img = document
-            .select(&Selector::parse(".asTBcell.uwthumb > img").to_anyhow()?)
+            .select(&COVER_IMG_SELECTOR)
             .next()
             .context(format!("没有找到封面的<img>: {document_html}"))?;
         let img_html = img.html();
 
         let cover_src = img
             .attr("src")
-            .context(format!("封面的<img>没有src属性: {img_html}"))?
-            .trim_start_matches('/')
-            .to_string();
-        let cover = format!("https://{cover_src}");
+            .context(format!("封面的<img>没有src属性: {img_html}"))?;
+        let cover = normalize_url(cover_src);
 
         let label = document
-            .select(&Selector::parse(".asTBcell.uwconn > label").to_anyhow()?)
+            .select(&LABEL_SELECTOR)
             .next()
             .context(format!("没有找到分类的<label>: {document_html}"))?;
         let label_html = label.html();
@@ -102,7 +172,7 @@ impl Comic {
             .to_string();
 
         let label = document
-            .select(&Selector::parse(".asTBcell.uwconn > label").to_anyhow()?)
+            .select(&LABEL_SELECTOR)
             .nth(1)
             .context(format!("没有找到图片数量的<label>: {document_html}"))?;
         let label_html = label.html();
@@ -119,8 +189,7 @@ impl Comic {
             .context(format!("图片数量不是整数: {label_html}"))?;
 
         let mut tags = vec![];
-        let tag_selector = Selector::parse(".tagshow").to_anyhow()?;
-        for a in document.select(&tag_selector) {
+        for a in document.select(&TAG_A_SELECTOR) {
             let Some(text) = a.text().next() else {
                 // 有些标签的<a>没有文本，跳过这些标签
                 continue;
@@ -137,33 +206,72 @@ impl Comic {
             tags.push(Tag { name, url });
         }
 
+        // 有些漫画没有简介，此时简介为空字符串
         let intro = document
-            .select(&Selector::parse(".asTBcell.uwconn > p").to_anyhow()?)
+            .select(&INTRO_P_SELECTOR)
             .next()
-            .context(format!("没有找到简介的<p>: {document_html}"))?
-            .html();
+            .map(|p| p.html())
+            .unwrap_or_default();
 
-        let is_downloaded = app
-            .state::<RwLock<Config>>()
-            .read()
-            .download_dir
-            .join(&title)
-            .exists();
-        let is_downloaded = Some(is_downloaded);
+        let language = parse_language(&category, &tags);
+        let group = parse_group(&title, &tags);
 
         Ok(Comic {
             id,
             title,
+            url: wnacg_client::comic_url(id),
             cover,
             category,
             image_count,
             tags,
             intro,
-            is_downloaded,
+            language,
+            group,
+            is_downloaded: None,
+            download_format: None,
+            disk_size_bytes: None,
+            duplicate_pages: BTreeMap::new(),
             img_list,
         })
     }
 
+    /// 将漫画的元数据保存到`dir`中的`元数据.json`
+    pub fn save_metadata(&self, dir: &Path) -> anyhow::Result<()> {
+        let mut comic = self.clone();
+        // 将所有comic的is_downloaded字段设置为None，这样能使is_downloaded字段在序列化时被忽略
+        comic.is_downloaded = None;
+        comic.disk_size_bytes = None;
+
+        let comic_title = &comic.title;
+        let comic_json = serde_json::to_string_pretty(&comic).context(format!(
+            "`{comic_title}`的元数据保存失败，将Comic序列化为json失败"
+        ))?;
+
+        let metadata_path = dir.join("元数据.json");
+
+        std::fs::write(&metadata_path, comic_json).context(format!(
+            "`{comic_title}`的元数据保存失败，写入文件`{metadata_path:?}`失败"
+        ))?;
+
+        Ok(())
+    }
+
+    /// 检查html是否为漫画不存在时的页面
+    ///
+    /// 漫画被删除后，网站有时不返回404，而是返回200和一个提示页面，这个页面中没有封面的单元格
+    pub fn is_not_found_page(html: &str) -> bool {
+        const NOT_FOUND_TEXTS: [&str; 3] = ["相冊不存在", "已被刪除", "已被删除"];
+
+        let document = Html::parse_document(html);
+        let has_cover_cell = document.select(&COVER_CELL_SELECTOR).next().is_some();
+        if has_cover_cell {
+            return false;
+        }
+        let body_text = document.root_element().text().collect::<String>();
+        let has_not_found_text = NOT_FOUND_TEXTS.iter().any(|text| body_text.contains(text));
+        has_not_found_text || document.select(&TITLE_H2_SELECTOR).next().is_none()
+    }
+
     pub fn from_metadata(app: &AppHandle, metadata_path: &Path) -> anyhow::Result<Comic> {
         let comic_json = std::fs::read_to_string(metadata_path).context(format!(
             "从元数据转为Comic失败，读取元数据文件 {metadata_path:?} 失败"
@@ -171,15 +279,140 @@ impl Comic {
         let mut comic = serde_json::from_str::<Comic>(&comic_json).context(format!(
             "从元数据转为Comic失败，将 {metadata_path:?} 反序列化为Comic失败"
         ))?;
+        // 旧版本保存的元数据中可能包含占位图片，需要重新清洗
+        // 清洗失败(例如旧版本保存了相对路径的url)不影响这部漫画出现在已下载列表中，只记录日志
+        let (download_dir, placeholder_img_patterns) = {
+            let config = app.state::<RwLock<Config>>();
+            let config = config.read();
+            (
+                config.download_dir.clone(),
+                config.placeholder_img_patterns.clone(),
+            )
+        };
+        if let Err(err) = comic.img_list.sanitize(&placeholder_img_patterns) {
+            let err_title = format!("清洗 {metadata_path:?} 中的ImgList失败");
+            let string_chain = err.to_string_chain();
+            tracing::warn!(err_title, message = string_chain);
+        }
         // 这个comic中的is_downloaded字段是None，需要重新计算
-
-        let is_downloaded = app
-            .state::<RwLock<Config>>()
-            .read()
-            .download_dir
-            .join(&comic.title)
-            .exists();
+        let is_downloaded = download_dir.join(&comic.title).exists();
         comic.is_downloaded = Some(is_downloaded);
+        // 本地导入的漫画(id不是正数)在网站上没有对应的页面
+        comic.url = if comic.id > 0 {
+            wnacg_client::comic_url(comic.id)
+        } else {
+            String::new()
+        };
         Ok(comic)
     }
 }
+
+/// 语言的显示名称和对应的关键词，关键词用于匹配分类的后半部分(例如`同人誌／漢化`中的`漢化`)和标签名
+const LANGUAGE_KEYWORDS: [(&str, &[&str]); 4] = [
+    ("中文", &["漢化", "汉化", "中文", "chinese"]),
+    ("日本語", &["日語", "日语", "生肉", "japanese"]),
+    ("English", &["english", "英語", "英语"]),
+    ("한국어", &["韓語", "韩语", "korean"]),
+];
+
+/// 语言的显示名称对应的ISO 639-1代码，用于`ComicInfo.LanguageISO`
+pub fn language_iso(language: &str) -> Option<&'static str> {
+    match language {
+        "中文" => Some("zh"),
+        "日本語" => Some("ja"),
+        "English" => Some("en"),
+        "한국어" => Some("ko"),
+        _ => None,
+    }
+}
+
+/// 优先从分类推断语言，分类中没有语言时再从标签推断
+fn parse_language(category: &str, tags: &[Tag]) -> Option<String> {
+    let find_language = |text: &str| {
+        let text = text.trim().to_lowercase();
+        LANGUAGE_KEYWORDS
+            .iter()
+            .find(|(_, keywords)| keywords.contains(&text.as_str()))
+            .map(|(language, _)| (*language).to_string())
+    };
+    // 分类形如`同人誌／漢化`，后半部分是语言
+    category
+        .rsplit(['／', '/'])
+        .next()
+        .and_then(find_language)
+        .or_else(|| tags.iter().find_map(|tag| find_language(&tag.name)))
+}
+
+/// 优先从标题的方括号中找翻译组(例如`[XX漢化組]`)，找不到时再从标签中找
+fn parse_group(title: &str, tags: &[Tag]) -> Option<String> {
+    const GROUP_KEYWORDS: [&str; 6] = ["漢化", "汉化", "翻譯", "翻译", "中譯", "中译"];
+    /// 这些只表示是翻译版本，不是翻译组
+    const NOT_GROUPS: [&str; 2] = ["中國翻譯", "中国翻译"];
+    let is_group = |text: &str| {
+        GROUP_KEYWORDS.iter().any(|keyword| text.contains(keyword))
+            && !GROUP_KEYWORDS.contains(&text)
+            && !NOT_GROUPS.contains(&text)
+    };
+
+    let bracketed = title.split(['[', '【']).skip(1).filter_map(|part| {
+        let end = part.find([']', '】'])?;
+        Some(part[..end].trim())
+    });
+    bracketed
+        .chain(tags.iter().map(|tag| tag.name.as_str()))
+        .find(|text| is_group(text))
+        .map(ToString::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 没有简介的<p>，也没有标签的最简详情页
+    const MINIMAL_COMIC_HTML: &str = r#"<html>
+<head><link rel="alternate" type="application/rss+xml" href="/feed-index-aid-123456.html"></head>
+<body>
+<div id="bodywrap">
+  <h2>[漢化組] 測試漫画</h2>
+  <div class="asTB">
+    <div class="asTBcell uwthumb"><img src="//t4.wnimg.ru/data/2826/33/cover.jpg"></div>
+    <div class="asTBcell uwconn">
+      <label>分類：同人誌／漢化</label>
+      <label>頁數：24P</label>
+    </div>
+  </div>
+</div>
+</body>
+</html>"#;
+
+    #[test]
+    fn parse_html_without_intro_and_tags() {
+        let comic = Comic::parse_html(MINIMAL_COMIC_HTML, ImgList::default()).unwrap();
+        assert_eq!(comic.id, 123_456);
+        assert_eq!(comic.title, "[漢化組] 測試漫画");
+        assert_eq!(comic.cover, "https://t4.wnimg.ru/data/2826/33/cover.jpg");
+        assert_eq!(comic.category, "同人誌／漢化");
+        assert_eq!(comic.image_count, 24);
+        assert!(comic.tags.is_empty());
+        assert_eq!(comic.intro, "");
+        assert_eq!(comic.language.as_deref(), Some("中文"));
+        assert_eq!(comic.group.as_deref(), Some("漢化組"));
+        assert_eq!(comic.is_downloaded, None);
+    }
+
+    #[test]
+    fn selectors_are_valid() {
+        let selectors: [&LazyLock<Selector>; 7] = [
+            &ID_LINK_SELECTOR,
+            &TITLE_H2_SELECTOR,
+            &COVER_IMG_SELECTOR,
+            &LABEL_SELECTOR,
+            &TAG_A_SELECTOR,
+            &INTRO_P_SELECTOR,
+            &COVER_CELL_SELECTOR,
+        ];
+        for selector in selectors {
+            LazyLock::force(selector);
+        }
+    }
+}
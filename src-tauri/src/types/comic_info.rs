@@ -2,7 +2,7 @@ use serde::{Deserialize, Serialize};
 use specta::Type;
 use yaserde::{YaDeserialize, YaSerialize};
 
-use super::Comic;
+use super::{language_iso, Comic};
 
 /// https://wiki.kavitareader.com/guides/metadata/comics/
 #[derive(
@@ -45,10 +45,24 @@ pub struct ComicInfo {
     /// - `其他非零值` => Ended
     #[yaserde(rename = "Count")]
     pub count: i64,
+    /// 语言的ISO代码
+    #[yaserde(rename = "LanguageISO")]
+    pub language_iso: Option<String>,
+    /// 翻译组
+    #[yaserde(rename = "Translator")]
+    pub translator: Option<String>,
+    /// 漫画详情页的链接
+    #[yaserde(rename = "Web")]
+    pub web: Option<String>,
 }
 
 impl From<Comic> for ComicInfo {
     fn from(comic: Comic) -> Self {
+        let language_iso = comic
+            .language
+            .as_deref()
+            .and_then(language_iso)
+            .map(ToString::to_string);
         ComicInfo {
             manga: "Yes".to_string(),
             series: comic.title,
@@ -66,6 +80,9 @@ impl From<Comic> for ComicInfo {
             format: Some("Special".to_string()),
             page_count: comic.image_count,
             count: 1,
+            language_iso,
+            web: Some(comic.url).filter(|url| !url.is_empty()),
+            translator: comic.group,
         }
     }
 }
@@ -1,11 +1,36 @@
+use std::sync::LazyLock;
+
 use anyhow::Context;
-use parking_lot::RwLock;
 use scraper::{ElementRef, Html, Selector};
 use serde::{Deserialize, Serialize};
 use specta::Type;
-use tauri::{AppHandle, Manager};
-
-use crate::{config::Config, extensions::ToAnyhow, utils::filename_filter};
+use tauri::AppHandle;
+
+use crate::{
+    errors::SessionExpiredError,
+    library,
+    utils::{filename_filter, normalize_url},
+};
+
+static NOT_LOGGED_IN_TITLE_SELECTOR: LazyLock<Selector> =
+    LazyLock::new(|| Selector::parse(".title.title_c").unwrap());
+static COMIC_DIV_SELECTOR: LazyLock<Selector> = LazyLock::new(|| Selector::parse(".asTB").unwrap());
+static CURRENT_PAGE_SELECTOR: LazyLock<Selector> =
+    LazyLock::new(|| Selector::parse(".thispage").unwrap());
+static PAGINATOR_A_SELECTOR: LazyLock<Selector> =
+    LazyLock::new(|| Selector::parse(".f_left.paginator > a").unwrap());
+static CURRENT_SHELF_SELECTOR: LazyLock<Selector> =
+    LazyLock::new(|| Selector::parse(".cur").unwrap());
+static SHELF_A_SELECTOR: LazyLock<Selector> =
+    LazyLock::new(|| Selector::parse(".nav_list > a").unwrap());
+static COVER_IMG_SELECTOR: LazyLock<Selector> =
+    LazyLock::new(|| Selector::parse(".asTBcell.thumb img").unwrap());
+static CATEGORY_SPAN_SELECTOR: LazyLock<Selector> =
+    LazyLock::new(|| Selector::parse(".l_catg > span").unwrap());
+static TITLE_A_SELECTOR: LazyLock<Selector> =
+    LazyLock::new(|| Selector::parse(".l_title > a").unwrap());
+static COMIC_SHELF_A_SELECTOR: LazyLock<Selector> =
+    LazyLock::new(|| Selector::parse(".l_catg > a").unwrap());
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
 #[serde(rename_all = "camelCase")]
@@ -20,18 +45,23 @@ pub struct GetFavoriteResult {
 impl GetFavoriteResult {
     pub fn from_html(app: &AppHandle, html: &str) -> anyhow::Result<GetFavoriteResult> {
         let document = Html::parse_document(html);
+        // 检查是否登录，如果有`.title.title_c`则未登录
+        let is_login = document
+            .select(&NOT_LOGGED_IN_TITLE_SELECTOR)
+            .next()
+            .is_none();
+        if !is_login {
+            return Err(SessionExpiredError.into());
+        }
 
         let mut comics = Vec::new();
-        for comic_div in document.select(&Selector::parse(".asTB").to_anyhow()?) {
+        for comic_div in document.select(&COMIC_DIV_SELECTOR) {
             if let Ok(comic) = ComicInFavorite::from_div(app, &comic_div) {
                 comics.push(comic);
             }
         }
 
-        let current_page = match document
-            .select(&Selector::parse(".thispage").to_anyhow()?)
-            .next()
-        {
+        let current_page = match document.select(&CURRENT_PAGE_SELECTOR).next() {
             Some(span) => {
                 let span_html = span.html();
                 span.text()
@@ -43,10 +73,7 @@ impl GetFavoriteResult {
             None => 1,
         };
 
-        let total_page = match document
-            .select(&Selector::parse(".f_left.paginator > a").to_anyhow()?)
-            .next_back()
-        {
+        let total_page = match document.select(&PAGINATOR_A_SELECTOR).next_back() {
             Some(a) => {
                 let a_html = a.html();
                 a.text()
@@ -75,7 +102,7 @@ impl GetFavoriteResult {
     fn get_shelf(document: &Html) -> anyhow::Result<Shelf> {
         let document_html = document.html();
         let a = document
-            .select(&Selector::parse(".cur").to_anyhow()?)
+            .select(&CURRENT_SHELF_SELECTOR)
             .next()
             .context(format!("没有找到当前书架的<a>: {document_html}"))?;
 
@@ -102,7 +129,7 @@ impl GetFavoriteResult {
 
     fn get_shelves(document: &Html) -> anyhow::Result<Vec<Shelf>> {
         let mut shelves = Vec::new();
-        for a in document.select(&Selector::parse(".nav_list > a").to_anyhow()?) {
+        for a in document.select(&SHELF_A_SELECTOR) {
             let a_html = a.html();
             let id = a
                 .attr("href")
@@ -128,6 +155,16 @@ impl GetFavoriteResult {
     }
 }
 
+/// 书架所有页合并后的结果
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct GetAllFavoritesResult {
+    /// 按页码顺序合并、按漫画id去重后的漫画
+    pub comics: Vec<ComicInFavorite>,
+    /// 获取期间书架被修改等导致结果可能与网站不一致的说明
+    pub warnings: Vec<String>,
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
 #[serde(rename_all = "camelCase")]
 pub struct ComicInFavorite {
@@ -152,15 +189,15 @@ impl ComicInFavorite {
 
         let div_html = div.html();
         let cover_src = div
-            .select(&Selector::parse(".asTBcell.thumb img").to_anyhow()?)
+            .select(&COVER_IMG_SELECTOR)
             .next()
             .context(format!("没有在漫画的<div>中找到<img>: {div_html}"))?
             .attr("src")
             .context(format!("没有在封面的<img>中找到src属性: {div_html}"))?;
-        let cover = format!("https:{cover_src}");
+        let cover = normalize_url(cover_src);
 
         let favorite_time = div
-            .select(&Selector::parse(".l_catg > span").to_anyhow()?)
+            .select(&CATEGORY_SPAN_SELECTOR)
             .next()
             .context(format!(
                 "没有在漫画的<div>中找到收藏时间的<span>: {div_html}"
@@ -175,12 +212,7 @@ impl ComicInFavorite {
 
         let shelf = Self::get_shelf(div)?;
 
-        let is_downloaded = app
-            .state::<RwLock<Config>>()
-            .read()
-            .download_dir
-            .join(&title)
-            .exists();
+        let is_downloaded = library::is_downloaded(app, id, &title);
 
         Ok(ComicInFavorite {
             id,
@@ -195,7 +227,7 @@ impl ComicInFavorite {
     fn get_id_and_title(div: &ElementRef) -> anyhow::Result<(i64, String)> {
         let div_html = div.html();
         let a = div
-            .select(&Selector::parse(".l_title > a").to_anyhow()?)
+            .select(&TITLE_A_SELECTOR)
             .next()
             .context(format!("没有在漫画的<div>中找到标题的<a>: {div_html}"))?;
 
@@ -224,7 +256,7 @@ impl ComicInFavorite {
     fn get_shelf(div: &ElementRef) -> anyhow::Result<Shelf> {
         let div_html = div.html();
         let a = div
-            .select(&Selector::parse(".l_catg > a").to_anyhow()?)
+            .select(&COMIC_SHELF_A_SELECTOR)
             .next()
             .context(format!("没有在漫画的<div>中找到书架的<a>: {div_html}"))?;
 
@@ -252,3 +284,27 @@ pub struct Shelf {
     /// 书架名称
     pub name: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selectors_are_valid() {
+        let selectors: [&LazyLock<Selector>; 10] = [
+            &NOT_LOGGED_IN_TITLE_SELECTOR,
+            &COMIC_DIV_SELECTOR,
+            &CURRENT_PAGE_SELECTOR,
+            &PAGINATOR_A_SELECTOR,
+            &CURRENT_SHELF_SELECTOR,
+            &SHELF_A_SELECTOR,
+            &COVER_IMG_SELECTOR,
+            &CATEGORY_SPAN_SELECTOR,
+            &TITLE_A_SELECTOR,
+            &COMIC_SHELF_A_SELECTOR,
+        ];
+        for selector in selectors {
+            LazyLock::force(selector);
+        }
+    }
+}
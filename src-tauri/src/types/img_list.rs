@@ -1,8 +1,16 @@
-use std::ops::{Deref, DerefMut};
+use std::{
+    collections::{HashMap, HashSet},
+    iter::Peekable,
+    ops::{Deref, DerefMut},
+    str::Chars,
+};
 
+use anyhow::{anyhow, Context};
 use serde::{Deserialize, Serialize};
 use specta::Type;
 
+use crate::utils::normalize_url;
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
 pub struct ImgList(pub Vec<ImgInImgList>);
 impl Deref for ImgList {
@@ -17,6 +25,282 @@ impl DerefMut for ImgList {
         &mut self.0
     }
 }
+impl ImgList {
+    /// 清洗图片列表
+    /// - 去掉url完全重复的图片
+    /// - 去掉url以`placeholder_patterns`中任意一项结尾的占位图片(例如最后一张`shoucang.jpg`)
+    /// - 检查剩下的图片url是否都以`//`、`https://`或`http://`开头
+    pub fn sanitize(&mut self, placeholder_patterns: &[String]) -> anyhow::Result<()> {
+        let mut seen_urls = HashSet::new();
+        self.0.retain(|img| {
+            let is_placeholder = placeholder_patterns
+                .iter()
+                .any(|pattern| img.url.ends_with(pattern.as_str()));
+            !is_placeholder && seen_urls.insert(img.url.clone())
+        });
+
+        if let Some(img) = self.0.iter().find(|img| {
+            !["//", "https://", "http://"]
+                .iter()
+                .any(|prefix| img.url.starts_with(prefix))
+        }) {
+            return Err(anyhow!(
+                "图片url不是以`//`、`https://`或`http://`开头: {img:?}"
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// 从网页中`imglist`的js数组解析出图片列表，例如
+    /// `[{ url: fast_img_host+\"//img5.wnimg.ru/data/2826/33/01.jpg\", caption: \"[01]\"}]`
+    ///
+    /// 只有字符串之外的标识符会被当作键或变量，变量(例如`fast_img_host`)的值从`host_vars`中查找，
+    /// 找不到的变量视为空字符串，所以`caption`中包含`url:`、引号等内容也不影响解析
+    pub fn from_js(js: &str, host_vars: &HashMap<String, String>) -> anyhow::Result<ImgList> {
+        let js = if is_escaped(js) {
+            unescape_once(js)
+        } else {
+            js.to_string()
+        };
+        let js = js.as_str();
+        let mut parser = JsParser::new(js, host_vars);
+        let mut imgs = vec![];
+        parser.expect('[')?;
+        loop {
+            parser.skip_whitespace();
+            match parser.peek() {
+                Some(']') => break,
+                Some(',') => {
+                    parser.next();
+                }
+                Some('{') => imgs.push(parser.parse_object()?),
+                Some(c) => return Err(anyhow!("`imglist`中出现了预料之外的字符`{c}`: {js}")),
+                None => return Err(anyhow!("`imglist`没有以`]`结尾: {js}")),
+            }
+        }
+        let imgs = imgs
+            .into_iter()
+            .map(|mut fields| {
+                let url = fields.remove("url").context(format!("图片没有url: {js}"))?;
+                let caption = fields.remove("caption").unwrap_or_default();
+                Ok(ImgInImgList { caption, url })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(ImgList(imgs))
+    }
+
+    /// 从网页中找出图片域名变量的声明，例如`var fast_img_host = "https://img5.wnimg.ru";`
+    ///
+    /// 变量名以`_host`结尾且值为字符串的`var`、`let`、`const`声明都视为图片域名变量
+    pub fn host_vars_from_html(html: &str) -> HashMap<String, String> {
+        let mut host_vars = HashMap::new();
+        let statements = html.lines().flat_map(|line| line.split(';'));
+        for statement in statements {
+            let Some(declaration) = ["var ", "let ", "const "]
+                .iter()
+                .find_map(|keyword| strip_keyword(statement, keyword))
+            else {
+                continue;
+            };
+            let Some((name, value)) = declaration.split_once('=') else {
+                continue;
+            };
+            let name = name.trim();
+            if !name.ends_with("_host") {
+                continue;
+            }
+            let mut parser = JsParser::new(value.trim(), &host_vars);
+            if !matches!(parser.peek(), Some('"' | '\'')) {
+                continue;
+            }
+            if let Ok(value) = parser.parse_string() {
+                host_vars.insert(name.to_string(), value);
+            }
+        }
+        host_vars
+    }
+
+    /// 按照`caption`中的页码排序后的图片列表
+    ///
+    /// 如果有任意一张图片的`caption`中没有页码，则保持原有顺序
+    pub fn sorted_by_caption(&self) -> Vec<&ImgInImgList> {
+        let mut imgs = self.0.iter().collect::<Vec<_>>();
+        if imgs.iter().all(|img| img.caption_number().is_some()) {
+            // 稳定排序，页码相同的图片保持原有顺序
+            imgs.sort_by_key(|img| img.caption_number());
+        }
+        imgs
+    }
+}
+
+/// 只支持`imglist`用到的js语法：数组、对象、字符串以及字符串与变量的`+`拼接
+struct JsParser<'a> {
+    chars: Peekable<Chars<'a>>,
+    /// 变量的值，找不到的变量视为空字符串
+    vars: &'a HashMap<String, String>,
+}
+
+impl<'a> JsParser<'a> {
+    fn new(js: &'a str, vars: &'a HashMap<String, String>) -> Self {
+        Self {
+            chars: js.chars().peekable(),
+            vars,
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        self.chars.next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.next();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> anyhow::Result<()> {
+        self.skip_whitespace();
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(anyhow!("应为`{expected}`，实际为`{c}`")),
+            None => Err(anyhow!("应为`{expected}`，实际已到结尾")),
+        }
+    }
+
+    /// 解析`{ key: value, ... }`，值都是字符串
+    fn parse_object(&mut self) -> anyhow::Result<HashMap<String, String>> {
+        let mut fields = HashMap::new();
+        self.expect('{')?;
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some('}') => {
+                    self.next();
+                    return Ok(fields);
+                }
+                Some(',') => {
+                    self.next();
+                }
+                Some(_) => {
+                    let key = self.parse_key()?;
+                    self.expect(':')?;
+                    let value = self.parse_value()?;
+                    fields.insert(key, value);
+                }
+                None => return Err(anyhow!("对象没有以`}}`结尾")),
+            }
+        }
+    }
+
+    /// 键可以是标识符，也可以是字符串
+    fn parse_key(&mut self) -> anyhow::Result<String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('"' | '\'') => self.parse_string(),
+            _ => self.parse_identifier(),
+        }
+    }
+
+    /// 解析用`+`拼接的字符串和变量，找不到的变量视为空字符串
+    fn parse_value(&mut self) -> anyhow::Result<String> {
+        let mut value = String::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some('"' | '\'') => value.push_str(&self.parse_string()?),
+                _ => {
+                    let identifier = self.parse_identifier()?;
+                    if let Some(var_value) = self.vars.get(&identifier) {
+                        value.push_str(var_value);
+                    }
+                }
+            }
+            self.skip_whitespace();
+            if self.peek() != Some('+') {
+                return Ok(value);
+            }
+            self.next();
+        }
+    }
+
+    fn parse_identifier(&mut self) -> anyhow::Result<String> {
+        let mut identifier = String::new();
+        while let Some(c) = self.peek() {
+            if !(c.is_alphanumeric() || c == '_' || c == '$') {
+                break;
+            }
+            identifier.push(c);
+            self.next();
+        }
+        if identifier.is_empty() {
+            let c = self.peek().map(String::from).unwrap_or_default();
+            return Err(anyhow!("应为标识符，实际为`{c}`"));
+        }
+        Ok(identifier)
+    }
+
+    /// 解析以`"`或`'`包裹的字符串
+    fn parse_string(&mut self) -> anyhow::Result<String> {
+        let quote = self.next().context("字符串缺少开头的引号")?;
+        let mut string = String::new();
+        loop {
+            match self.next() {
+                Some('\\') => {
+                    let escaped = self.next().context("字符串在转义字符后结束")?;
+                    string.push(escaped);
+                }
+                Some(c) if c == quote => return Ok(string),
+                Some(c) => string.push(c),
+                None => return Err(anyhow!("字符串缺少结尾的引号: {string}")),
+            }
+        }
+    }
+}
+
+/// 去掉`statement`中声明关键字及其之前的内容，关键字前可以有`<script>`之类的非标识符内容
+fn strip_keyword<'a>(statement: &'a str, keyword: &str) -> Option<&'a str> {
+    let (before, declaration) = statement.split_once(keyword)?;
+    let is_identifier_char = |c: char| c.is_alphanumeric() || c == '_' || c == '$';
+    if before.ends_with(is_identifier_char) {
+        return None;
+    }
+    Some(declaration)
+}
+
+/// `imglist`是否位于另一个js字符串中，此时第一个引号前会有一个反斜杠
+///
+/// 只看第一个引号，避免把`caption`中被转义的引号误判为整个数组被转义
+fn is_escaped(js: &str) -> bool {
+    js.find(['"', '\''])
+        .is_some_and(|i| js[..i].ends_with('\\'))
+}
+
+/// `imglist`位于另一个js字符串中时，引号和反斜杠会被转义，先去掉这一层转义
+fn unescape_once(js: &str) -> String {
+    let mut unescaped = String::with_capacity(js.len());
+    let mut chars = js.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some(escaped @ ('"' | '\'' | '\\')) => unescaped.push(escaped),
+            Some(escaped) => {
+                unescaped.push(c);
+                unescaped.push(escaped);
+            }
+            None => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
 impl IntoIterator for ImgList {
     type Item = ImgInImgList;
     type IntoIter = std::vec::IntoIter<Self::Item>;
@@ -32,7 +316,263 @@ impl IntoIterator for ImgList {
 pub struct ImgInImgList {
     /// 图片标题([01]、[001]，根据漫画总页数确定)
     pub caption: String,
-    /// 图片url(//img5.wnimg.ru/data/2826/33/01.jpg，缺https:前缀)  
-    /// 最后一张图片为/themes/weitu/images/bg/shoucang.jpg，记得过滤
+    /// 图片url(//img5.wnimg.ru/data/2826/33/01.jpg，通常缺https:前缀，用`ImgInImgList::full_url`补全)  
+    /// 最后一张图片为/themes/weitu/images/bg/shoucang.jpg，已在`ImgList::sanitize`中过滤
     pub url: String,
 }
+
+impl ImgInImgList {
+    /// 带协议的图片url
+    pub fn full_url(&self) -> String {
+        normalize_url(&self.url)
+    }
+
+    /// 从`caption`中提取页码，例如`[001]`提取出`1`
+    ///
+    /// 优先使用`[]`中的数字，没有时使用最后一段连续的数字，例如`p1-10`提取出`10`
+    pub fn caption_number(&self) -> Option<u64> {
+        let caption = &self.caption;
+        let bracketed = caption.split('[').skip(1).find_map(|rest| {
+            let (inside, _) = rest.split_once(']')?;
+            let inside = inside.trim();
+            let is_number = !inside.is_empty() && inside.chars().all(|c| c.is_ascii_digit());
+            is_number.then_some(inside)
+        });
+        let digits = bracketed.or_else(|| {
+            caption
+                .split(|c: char| !c.is_ascii_digit())
+                .rfind(|digits| !digits.is_empty())
+        })?;
+        digits.parse::<u64>().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn img(caption: &str, url: &str) -> ImgInImgList {
+        ImgInImgList {
+            caption: caption.to_string(),
+            url: url.to_string(),
+        }
+    }
+
+    #[test]
+    fn sanitize_removes_duplicates_and_placeholders() {
+        let mut img_list = ImgList(vec![
+            img("[01]", "//img5.wnimg.ru/data/2826/33/01.jpg"),
+            img("[02]", "//img5.wnimg.ru/data/2826/33/02.jpg"),
+            img("[02]", "//img5.wnimg.ru/data/2826/33/02.jpg"),
+            img("[03]", "https://img5.wnimg.ru/data/2826/33/03.jpg"),
+            img("", "/themes/weitu/images/bg/shoucang.jpg"),
+            img("", "//img5.wnimg.ru/static/loading.gif"),
+        ]);
+        let placeholder_patterns = ["shoucang.jpg".to_string(), "loading.gif".to_string()];
+        img_list.sanitize(&placeholder_patterns).unwrap();
+        let urls: Vec<&str> = img_list.iter().map(|img| img.url.as_str()).collect();
+        assert_eq!(
+            urls,
+            [
+                "//img5.wnimg.ru/data/2826/33/01.jpg",
+                "//img5.wnimg.ru/data/2826/33/02.jpg",
+                "https://img5.wnimg.ru/data/2826/33/03.jpg",
+            ]
+        );
+    }
+
+    #[test]
+    fn sanitize_rejects_relative_urls() {
+        let mut img_list = ImgList(vec![
+            img("[01]", "//img5.wnimg.ru/data/2826/33/01.jpg"),
+            img("[02]", "/data/2826/33/02.jpg"),
+        ]);
+        assert!(img_list.sanitize(&["shoucang.jpg".to_string()]).is_err());
+    }
+
+    fn no_vars() -> HashMap<String, String> {
+        HashMap::new()
+    }
+
+    #[test]
+    fn from_js_caption_with_quotes() {
+        let js = r#"[{ url: "//img5.wnimg.ru/data/01.jpg", caption: "[01] \"引号\" 'single'" }, { url: '//img5.wnimg.ru/data/02.jpg', caption: '[02] "double" \'single\'' }]"#;
+        let img_list = ImgList::from_js(js, &no_vars()).unwrap();
+        assert_eq!(
+            img_list.0,
+            [
+                img("[01] \"引号\" 'single'", "//img5.wnimg.ru/data/01.jpg"),
+                img("[02] \"double\" 'single'", "//img5.wnimg.ru/data/02.jpg"),
+            ]
+        );
+    }
+
+    #[test]
+    fn from_js_caption_with_colons_and_keywords() {
+        // `caption`中的`url:`、`caption:`、逗号和括号不能被当作键或分隔符
+        let js = r#"[{ url: "//img5.wnimg.ru/data/01.jpg", caption: "[01] url: //fake.jpg, caption: {x}]" }, { caption: "[02] 12:30", url: "https://img5.wnimg.ru/data/02.jpg" }]"#;
+        let img_list = ImgList::from_js(js, &no_vars()).unwrap();
+        assert_eq!(
+            img_list.0,
+            [
+                img(
+                    "[01] url: //fake.jpg, caption: {x}]",
+                    "//img5.wnimg.ru/data/01.jpg"
+                ),
+                img("[02] 12:30", "https://img5.wnimg.ru/data/02.jpg"),
+            ]
+        );
+    }
+
+    #[test]
+    fn from_js_escaped_caption_with_quotes() {
+        // `imglist`位于另一个js字符串中时，整个数组多了一层转义
+        let js =
+            r#"[{ url: \"//img5.wnimg.ru/data/01.jpg\", caption: \"[01] \\\"引号\\\": 冒号\"}]"#;
+        let img_list = ImgList::from_js(js, &no_vars()).unwrap();
+        assert_eq!(
+            img_list.0,
+            [img("[01] \"引号\": 冒号", "//img5.wnimg.ru/data/01.jpg")]
+        );
+    }
+
+    #[test]
+    fn from_js_rejects_img_without_url() {
+        let js = r#"[{ caption: "[01] url: //img5.wnimg.ru/data/01.jpg" }]"#;
+        assert!(ImgList::from_js(js, &no_vars()).is_err());
+    }
+
+    #[test]
+    fn full_url_handles_both_url_shapes() {
+        let scheme_relative = img("[01]", "//img5.wnimg.ru/data/01.jpg");
+        assert_eq!(
+            scheme_relative.full_url(),
+            "https://img5.wnimg.ru/data/01.jpg"
+        );
+        let absolute = img("[02]", "https://img5.wnimg.ru/data/02.jpg");
+        assert_eq!(absolute.full_url(), "https://img5.wnimg.ru/data/02.jpg");
+    }
+
+    #[test]
+    fn from_js_unknown_host_var_is_empty() {
+        let js = r#"[{ url: fast_img_host+"//img5.wnimg.ru/data/01.jpg", caption: "[01]"}]"#;
+        let img_list = ImgList::from_js(js, &no_vars()).unwrap();
+        assert_eq!(img_list.0, [img("[01]", "//img5.wnimg.ru/data/01.jpg")]);
+    }
+
+    #[test]
+    fn from_js_escaped_host_var() {
+        let js = r#"[{ url: fast_img_host+\"//img5.wnimg.ru/data/01.jpg\", caption: \"[01]\"}]"#;
+        let img_list = ImgList::from_js(js, &no_vars()).unwrap();
+        assert_eq!(img_list.0, [img("[01]", "//img5.wnimg.ru/data/01.jpg")]);
+    }
+
+    #[test]
+    fn from_js_resolves_host_var_declared_in_html() {
+        let html = r#"<script>var other_img_host = "https://img6.wnimg.ru";
+var imglist = [{ url: other_img_host+"/data/01.jpg", caption: "[01]"}];</script>"#;
+        let host_vars = ImgList::host_vars_from_html(html);
+        let js = r#"[{ url: other_img_host+"/data/01.jpg", caption: "[01]"}]"#;
+        let img_list = ImgList::from_js(js, &host_vars).unwrap();
+        assert_eq!(
+            img_list.0,
+            [img("[01]", "https://img6.wnimg.ru/data/01.jpg")]
+        );
+    }
+
+    #[test]
+    fn from_js_plain_url_and_single_quotes() {
+        let js = r#"[{ url: "https://img5.wnimg.ru/data/01.jpg", caption: "[01]" }, { 'url': '//img5.wnimg.ru/data/02.jpg', 'caption': '[02]' }]"#;
+        let img_list = ImgList::from_js(js, &no_vars()).unwrap();
+        assert_eq!(
+            img_list.0,
+            [
+                img("[01]", "https://img5.wnimg.ru/data/01.jpg"),
+                img("[02]", "//img5.wnimg.ru/data/02.jpg"),
+            ]
+        );
+    }
+
+    #[test]
+    fn host_vars_from_html_finds_declarations() {
+        let html = r#"<script>
+var fast_img_host = "https://img5.wnimg.ru"; let slow_img_host = 'https://img6.wnimg.ru';
+const backup_host = "https://img7.wnimg.ru";
+var title = "not a host";
+var number_host = 1;
+var computed_host = fast_img_host + "/data";
+</script>"#;
+        let host_vars = ImgList::host_vars_from_html(html);
+        let expected = HashMap::from([
+            (
+                "fast_img_host".to_string(),
+                "https://img5.wnimg.ru".to_string(),
+            ),
+            (
+                "slow_img_host".to_string(),
+                "https://img6.wnimg.ru".to_string(),
+            ),
+            (
+                "backup_host".to_string(),
+                "https://img7.wnimg.ru".to_string(),
+            ),
+        ]);
+        assert_eq!(host_vars, expected);
+    }
+
+    #[test]
+    fn caption_number_prefers_bracketed_number() {
+        assert_eq!(img("[001]", "").caption_number(), Some(1));
+        assert_eq!(img("p1-[12]", "").caption_number(), Some(12));
+        assert_eq!(img("[ 7 ]", "").caption_number(), Some(7));
+    }
+
+    #[test]
+    fn caption_number_uses_last_run_of_digits() {
+        assert_eq!(img("p1-10", "").caption_number(), Some(10));
+        assert_eq!(img("第2卷 第15頁", "").caption_number(), Some(15));
+        assert_eq!(img("[封面] 3", "").caption_number(), Some(3));
+        assert_eq!(img("封面", "").caption_number(), None);
+    }
+
+    #[test]
+    fn sorted_by_caption_restores_shuffled_order() {
+        let img_list = ImgList(vec![
+            img("[10]", "//img/10.jpg"),
+            img("[02]", "//img/02.jpg"),
+            img("[01]", "//img/01.jpg"),
+            img("[11]", "//img/11.jpg"),
+            img("[03]", "//img/03.jpg"),
+        ]);
+        let urls: Vec<&str> = img_list
+            .sorted_by_caption()
+            .into_iter()
+            .map(|img| img.url.as_str())
+            .collect();
+        assert_eq!(
+            urls,
+            [
+                "//img/01.jpg",
+                "//img/02.jpg",
+                "//img/03.jpg",
+                "//img/10.jpg",
+                "//img/11.jpg"
+            ]
+        );
+    }
+
+    #[test]
+    fn sorted_by_caption_keeps_order_when_any_caption_has_no_number() {
+        let img_list = ImgList(vec![
+            img("[02]", "//img/02.jpg"),
+            img("封面", "//img/cover.jpg"),
+            img("[01]", "//img/01.jpg"),
+        ]);
+        let urls: Vec<&str> = img_list
+            .sorted_by_caption()
+            .into_iter()
+            .map(|img| img.url.as_str())
+            .collect();
+        assert_eq!(urls, ["//img/02.jpg", "//img/cover.jpg", "//img/01.jpg"]);
+    }
+}
@@ -1,19 +1,27 @@
+mod app_version;
+mod check_in_result;
 mod comic;
 mod comic_info;
 mod download_format;
 mod get_favorite_result;
 mod img_list;
+mod log_format;
 mod log_level;
+mod on_existing_folder;
 mod search_result;
 mod tag;
 mod user_profile;
 
+pub use app_version::*;
+pub use check_in_result::*;
 pub use comic::*;
 pub use comic_info::*;
 pub use download_format::*;
 pub use get_favorite_result::*;
 pub use img_list::*;
+pub use log_format::*;
 pub use log_level::*;
+pub use on_existing_folder::*;
 pub use search_result::*;
 pub use tag::*;
 pub use user_profile::*;
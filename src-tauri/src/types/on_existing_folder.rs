@@ -0,0 +1,14 @@
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+/// 下载完成时，下载目录中已经有同名目录的处理方式
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum OnExistingFolder {
+    /// 删除已有的目录
+    #[default]
+    Overwrite,
+    /// 保留已有的目录，删除新下载的临时下载目录，下载任务视为完成，通知中显示为已跳过
+    Skip,
+    /// 保留已有的目录，新下载的目录加上` (2)`、` (3)`等后缀
+    RenameWithSuffix,
+}
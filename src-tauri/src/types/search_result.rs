@@ -1,18 +1,42 @@
+use std::sync::LazyLock;
+
 use anyhow::Context;
-use parking_lot::RwLock;
 use scraper::{ElementRef, Html, Selector};
 use serde::{Deserialize, Serialize};
 use specta::Type;
-use tauri::{AppHandle, Manager};
-
-use crate::{config::Config, extensions::ToAnyhow, utils::filename_filter};
+use tauri::AppHandle;
+
+use crate::{
+    library,
+    utils::{filename_filter, normalize_url},
+};
+
+static COMIC_LI_SELECTOR: LazyLock<Selector> =
+    LazyLock::new(|| Selector::parse(".li.gallary_item").unwrap());
+static CURRENT_PAGE_SELECTOR: LazyLock<Selector> =
+    LazyLock::new(|| Selector::parse(".thispage").unwrap());
+static PAGINATOR_A_SELECTOR: LazyLock<Selector> =
+    LazyLock::new(|| Selector::parse(".f_left.paginator > a").unwrap());
+static NEXT_PAGE_A_SELECTOR: LazyLock<Selector> =
+    LazyLock::new(|| Selector::parse(".f_left.paginator > .next > a").unwrap());
+static TOTAL_COUNT_B_SELECTOR: LazyLock<Selector> =
+    LazyLock::new(|| Selector::parse("#bodywrap .result > b").unwrap());
+static TITLE_A_SELECTOR: LazyLock<Selector> =
+    LazyLock::new(|| Selector::parse(".title > a").unwrap());
+static COVER_IMG_SELECTOR: LazyLock<Selector> = LazyLock::new(|| Selector::parse("img").unwrap());
+static INFO_DIV_SELECTOR: LazyLock<Selector> =
+    LazyLock::new(|| Selector::parse(".info_col").unwrap());
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
 #[serde(rename_all = "camelCase")]
 pub struct SearchResult {
-    comics: Vec<ComicInSearch>,
+    pub comics: Vec<ComicInSearch>,
     current_page: i64,
     total_page: i64,
+    /// 总结果数，按标签搜索时网站只显示页数，为`None`
+    total_count: Option<i64>,
+    /// 分页栏中是否有"后页"链接，比用`total_page`判断更可靠，适合无限滚动
+    has_next_page: bool,
     is_search_by_tag: bool,
 }
 
@@ -23,18 +47,13 @@ impl SearchResult {
         is_search_by_tag: bool,
     ) -> anyhow::Result<SearchResult> {
         let document = Html::parse_document(html);
-        let comic_li_selector = Selector::parse(".li.gallary_item").to_anyhow()?;
-
         let mut comics = Vec::new();
-        for comic_li in document.select(&comic_li_selector) {
+        for comic_li in document.select(&COMIC_LI_SELECTOR) {
             let comic = ComicInSearch::from_li(app, &comic_li)?;
             comics.push(comic);
         }
 
-        let current_page = match document
-            .select(&Selector::parse(".thispage").to_anyhow()?)
-            .next()
-        {
+        let current_page = match document.select(&CURRENT_PAGE_SELECTOR).next() {
             Some(span) => {
                 let span_html = span.html();
                 span.text()
@@ -46,11 +65,11 @@ impl SearchResult {
             None => 1,
         };
 
-        let total_page = if is_search_by_tag {
-            match document
-                .select(&Selector::parse(".f_left.paginator > a").to_anyhow()?)
-                .next_back()
-            {
+        // 最后一页的"后页"只是文本，没有<a>
+        let has_next_page = document.select(&NEXT_PAGE_A_SELECTOR).next().is_some();
+
+        let (total_page, total_count) = if is_search_by_tag {
+            let total_page = match document.select(&PAGINATOR_A_SELECTOR).next_back() {
                 Some(a) => {
                     let a_html = a.html();
                     a.text()
@@ -62,13 +81,14 @@ impl SearchResult {
                 }
 
                 None => 1,
-            }
+            };
+            (total_page, None)
         } else {
             const PAGE_SIZE: i64 = 24;
             let document_html = document.html();
 
             let b = document
-                .select(&Selector::parse("#bodywrap .result > b").to_anyhow()?)
+                .select(&TOTAL_COUNT_B_SELECTOR)
                 .next()
                 .context(format!("没有找到总结果数的<b>: {document_html}"))?;
             let b_html = b.html();
@@ -80,23 +100,30 @@ impl SearchResult {
                 .replace(',', "")
                 .parse::<i64>()
                 .context(format!("总结果数不是整数: {b_html}"))?;
-            (total + PAGE_SIZE - 1) / PAGE_SIZE
+            ((total + PAGE_SIZE - 1) / PAGE_SIZE, Some(total))
         };
 
         Ok(SearchResult {
             comics,
             current_page,
             total_page,
+            total_count,
+            has_next_page,
             is_search_by_tag,
         })
     }
+
+    /// 下一页的页码，已经是最后一页时为`None`
+    pub fn next_page(&self) -> Option<i64> {
+        self.has_next_page.then_some(self.current_page + 1)
+    }
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
 #[serde(rename_all = "camelCase")]
 pub struct ComicInSearch {
     /// 漫画id
-    id: i64,
+    pub id: i64,
     /// 漫画标题(带html标签，用于显示匹配关键词)
     title_html: String,
     /// 漫画标题
@@ -106,7 +133,7 @@ pub struct ComicInSearch {
     /// 额外信息(209張圖片， 創建於2025-01-05 18:33:19)
     additional_info: String,
     /// 是否已下载
-    is_downloaded: bool,
+    pub is_downloaded: bool,
 }
 
 impl ComicInSearch {
@@ -114,7 +141,7 @@ impl ComicInSearch {
         let li_html = li.html();
 
         let title_a = li
-            .select(&Selector::parse(".title > a").to_anyhow()?)
+            .select(&TITLE_A_SELECTOR)
             .next()
             .context(format!("没有在<li>中找到标题的<a>: {li_html}"))?;
         let title_a_html = title_a.html();
@@ -141,7 +168,7 @@ impl ComicInSearch {
         let title = filename_filter(&title);
 
         let img = li
-            .select(&Selector::parse("img").to_anyhow()?)
+            .select(&COVER_IMG_SELECTOR)
             .next()
             .context(format!("没有在<li>中找到<img>: {li_html}"))?;
         let img_html = img.html();
@@ -149,10 +176,10 @@ impl ComicInSearch {
         let cover_src = img
             .attr("src")
             .context(format!("没有在<img>中找到src属性: {img_html}"))?;
-        let cover = format!("https:{cover_src}");
+        let cover = normalize_url(cover_src);
 
         let div = li
-            .select(&Selector::parse(".info_col").to_anyhow()?)
+            .select(&INFO_DIV_SELECTOR)
             .next()
             .context(format!("没有在<li>中找到额外信息的<div>: {li_html}"))?;
         let div_html = div.html();
@@ -164,12 +191,7 @@ impl ComicInSearch {
             .trim()
             .to_string();
 
-        let is_downloaded = app
-            .state::<RwLock<Config>>()
-            .read()
-            .download_dir
-            .join(&title)
-            .exists();
+        let is_downloaded = library::is_downloaded(app, id, &title);
 
         Ok(ComicInSearch {
             id,
@@ -181,3 +203,25 @@ impl ComicInSearch {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selectors_are_valid() {
+        let selectors: [&LazyLock<Selector>; 8] = [
+            &COMIC_LI_SELECTOR,
+            &CURRENT_PAGE_SELECTOR,
+            &PAGINATOR_A_SELECTOR,
+            &NEXT_PAGE_A_SELECTOR,
+            &TOTAL_COUNT_B_SELECTOR,
+            &TITLE_A_SELECTOR,
+            &COVER_IMG_SELECTOR,
+            &INFO_DIV_SELECTOR,
+        ];
+        for selector in selectors {
+            LazyLock::force(selector);
+        }
+    }
+}
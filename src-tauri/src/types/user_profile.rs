@@ -1,9 +1,17 @@
-use anyhow::{anyhow, Context};
+use std::sync::LazyLock;
+
+use anyhow::Context;
 use scraper::{Html, Selector};
 use serde::{Deserialize, Serialize};
 use specta::Type;
 
-use crate::extensions::ToAnyhow;
+use crate::errors::SessionExpiredError;
+
+static NOT_LOGGED_IN_TITLE_SELECTOR: LazyLock<Selector> =
+    LazyLock::new(|| Selector::parse(".title.title_c").unwrap());
+static USERNAME_A_SELECTOR: LazyLock<Selector> =
+    LazyLock::new(|| Selector::parse(".top_utab.ui > a").unwrap());
+static AVATAR_IMG_SELECTOR: LazyLock<Selector> = LazyLock::new(|| Selector::parse("img").unwrap());
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
 #[serde(rename_all = "camelCase")]
@@ -12,6 +20,10 @@ pub struct UserProfile {
     pub username: String,
     /// 头像url
     pub avatar: String,
+    /// 积分，页面中没有时为None
+    pub points: Option<i64>,
+    /// 收藏的漫画数量，页面中没有时为None
+    pub favorite_count: Option<i64>,
 }
 impl UserProfile {
     pub fn from_html(html: &str) -> anyhow::Result<UserProfile> {
@@ -19,24 +31,24 @@ impl UserProfile {
         let document = Html::parse_document(html);
         // 检查是否登录，如果有`.title.title_c`则未登录
         let is_login = document
-            .select(&Selector::parse(".title.title_c").to_anyhow()?)
+            .select(&NOT_LOGGED_IN_TITLE_SELECTOR)
             .next()
             .is_none();
         if !is_login {
-            return Err(anyhow!("未登录，cookie已过期或cookie无效"));
+            return Err(SessionExpiredError.into());
         }
 
         let document_html = document.html();
 
         // 获取头像与用户名的<a>
         let a = document
-            .select(&Selector::parse(".top_utab.ui > a").to_anyhow()?)
+            .select(&USERNAME_A_SELECTOR)
             .next()
             .context(format!("没有找到头像与用户名的<a>: {document_html}"))?;
         let a_html = a.html();
         // 获取头像url
         let img = a
-            .select(&Selector::parse("img").to_anyhow()?)
+            .select(&AVATAR_IMG_SELECTOR)
             .next()
             .context(format!("没有在头像与用户名的<a>中找到<img>: {a_html}"))?;
 
@@ -53,7 +65,49 @@ impl UserProfile {
             .trim()
             .to_string();
 
-        let user_profile = UserProfile { username, avatar };
+        // 获取积分与收藏数量，这些信息不是必须的，找不到时为None
+        let document_text = document.root_element().text().collect::<String>();
+        let points = parse_labeled_number(&document_text, "積分");
+        let favorite_count = parse_labeled_number(&document_text, "收藏");
+
+        let user_profile = UserProfile {
+            username,
+            avatar,
+            points,
+            favorite_count,
+        };
         Ok(user_profile)
     }
 }
+
+/// 在`text`中找到`label`，解析紧跟在后面的数字(允许中间有`：`、`:`和空白)
+///
+/// 例如`積分： 1,234`解析为`1234`
+fn parse_labeled_number(text: &str, label: &str) -> Option<i64> {
+    text.match_indices(label).find_map(|(i, _)| {
+        let digits = text[i + label.len()..]
+            .trim_start_matches(|c: char| c == '：' || c == ':' || c.is_whitespace())
+            .chars()
+            .take_while(|c| c.is_ascii_digit() || *c == ',')
+            .filter(char::is_ascii_digit)
+            .collect::<String>();
+        digits.parse::<i64>().ok()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selectors_are_valid() {
+        let selectors: [&LazyLock<Selector>; 3] = [
+            &NOT_LOGGED_IN_TITLE_SELECTOR,
+            &USERNAME_A_SELECTOR,
+            &AVATAR_IMG_SELECTOR,
+        ];
+        for selector in selectors {
+            LazyLock::force(selector);
+        }
+    }
+}
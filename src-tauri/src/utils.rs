@@ -1,3 +1,8 @@
+use std::{collections::HashSet, path::PathBuf};
+
+use anyhow::Context;
+use tauri::{AppHandle, Manager};
+
 pub fn filename_filter(s: &str) -> String {
     s.chars()
         .map(|c| match c {
@@ -15,3 +20,208 @@ pub fn filename_filter(s: &str) -> String {
         .trim()
         .to_string()
 }
+
+/// 是否为便携模式
+///
+/// exe所在目录中存在`portable.txt`或`portable`文件时为便携模式
+pub fn is_portable() -> bool {
+    let Ok(exe_dir) = exe_dir() else {
+        return false;
+    };
+    exe_dir.join("portable.txt").is_file() || exe_dir.join("portable").is_file()
+}
+
+/// 保存配置、日志等数据的目录
+///
+/// 优先级如下：
+/// 1. 便携模式(见`is_portable`)下为exe所在目录，方便放在U盘中使用
+/// 2. 否则为app_data_dir
+///
+/// 默认的下载目录与导出目录也在这个目录下
+pub fn data_dir(app: &AppHandle) -> anyhow::Result<PathBuf> {
+    if is_portable() {
+        return exe_dir();
+    }
+    app.path()
+        .app_data_dir()
+        .context("获取app_data_dir目录失败")
+}
+
+fn exe_dir() -> anyhow::Result<PathBuf> {
+    let exe_path = std::env::current_exe().context("获取exe路径失败")?;
+    let exe_dir = exe_path
+        .parent()
+        .context(format!("获取`{exe_path:?}`的父目录失败"))?;
+    Ok(exe_dir.to_path_buf())
+}
+
+/// 网站中的url大多省略了协议(例如`//img5.wnimg.ru/data/2826/33/01.jpg`)，这种url补上`https:`，
+/// 已经带有协议的url保持不变
+pub fn normalize_url(url: &str) -> String {
+    if url.starts_with("//") {
+        format!("https:{url}")
+    } else {
+        url.to_string()
+    }
+}
+
+/// 从任意文本中提取漫画id，按第一次出现的顺序返回，已去重
+///
+/// 支持`photos-index-aid-{id}`和`feed-index-aid-{id}`形式的链接，以及单独占一行的纯数字id
+pub fn extract_comic_ids(text: &str) -> Vec<i64> {
+    const PATTERNS: [&str; 2] = ["photos-index-aid-", "feed-index-aid-"];
+
+    let mut comic_ids = vec![];
+    let mut seen_ids = HashSet::new();
+    let mut push_id = |id: i64| {
+        if seen_ids.insert(id) {
+            comic_ids.push(id);
+        }
+    };
+
+    for line in text.lines() {
+        let line = line.trim();
+        if !line.is_empty() && line.bytes().all(|b| b.is_ascii_digit()) {
+            if let Ok(id) = line.parse::<i64>() {
+                push_id(id);
+            }
+            continue;
+        }
+        // 一行中可能有多个链接，每次从剩余文本中找最靠前的那个
+        let mut rest = line;
+        while let Some((pos, pattern)) = PATTERNS
+            .iter()
+            .filter_map(|pattern| rest.find(pattern).map(|pos| (pos, pattern)))
+            .min_by_key(|(pos, _)| *pos)
+        {
+            rest = &rest[pos + pattern.len()..];
+            let digit_count = rest.bytes().take_while(u8::is_ascii_digit).count();
+            if let Ok(id) = rest[..digit_count].parse::<i64>() {
+                push_id(id);
+            }
+        }
+    }
+
+    comic_ids
+}
+
+/// 将标题规范化，用于比较两个标题是否为同一部漫画
+///
+/// 去掉所有括号及其中的内容(例如`[作者] 标题 (C99) [中国翻译]`只剩`标题`)，去掉空白字符并转为小写  
+/// 如果去掉括号后什么都不剩，则只去掉括号本身，保留其中的内容
+pub fn normalize_title(title: &str) -> String {
+    const OPEN_BRACKETS: [char; 6] = ['[', '【', '(', '（', '{', '「'];
+    const CLOSE_BRACKETS: [char; 6] = [']', '】', ')', '）', '}', '」'];
+
+    let mut depth = 0_usize;
+    let mut normalized = String::new();
+    for c in title.chars() {
+        if OPEN_BRACKETS.contains(&c) {
+            depth += 1;
+        } else if CLOSE_BRACKETS.contains(&c) {
+            depth = depth.saturating_sub(1);
+        } else if depth == 0 && !c.is_whitespace() {
+            normalized.extend(c.to_lowercase());
+        }
+    }
+    if !normalized.is_empty() {
+        return normalized;
+    }
+    title
+        .chars()
+        .filter(|c| !c.is_whitespace() && !OPEN_BRACKETS.contains(c) && !CLOSE_BRACKETS.contains(c))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_url_adds_scheme_to_scheme_relative_url() {
+        assert_eq!(
+            normalize_url("//img5.wnimg.ru/data/2826/33/01.jpg"),
+            "https://img5.wnimg.ru/data/2826/33/01.jpg"
+        );
+    }
+
+    #[test]
+    fn normalize_url_keeps_absolute_url() {
+        for url in [
+            "https://img5.wnimg.ru/data/2826/33/01.jpg",
+            "http://img5.wnimg.ru/data/2826/33/01.jpg",
+        ] {
+            assert_eq!(normalize_url(url), url);
+        }
+    }
+
+    #[test]
+    fn extract_comic_ids_from_mixed_input() {
+        let text = "
+            https://www.wnacg.com/photos-index-aid-123456.html
+            234567
+            看看这个 //www.wnacg.com/feed-index-aid-345678.html 还有这个
+            https://www.wnacg.com/photos-index-aid-456789.html#comments
+        ";
+        assert_eq!(extract_comic_ids(text), [123456, 234567, 345678, 456789]);
+    }
+
+    #[test]
+    fn extract_comic_ids_multiple_links_in_one_line_and_dedupe() {
+        let text = "photos-index-aid-3.html,feed-index-aid-1.html photos-index-aid-2.html
+                    1
+                    photos-index-aid-3.html";
+        assert_eq!(extract_comic_ids(text), [3, 1, 2]);
+    }
+
+    #[test]
+    fn extract_comic_ids_ignores_garbage() {
+        let text = "
+            hello world
+            photos-index-aid-.html
+            photos-index-aid-abc.html
+            12a34
+            -1
+            99999999999999999999999
+        ";
+        assert!(extract_comic_ids(text).is_empty());
+    }
+
+    #[test]
+    fn normalize_title_strips_author_prefix() {
+        assert_eq!(normalize_title("[作者] 标题"), "标题");
+        assert_eq!(normalize_title("[作者]标题"), normalize_title("标题"));
+    }
+
+    #[test]
+    fn normalize_title_strips_all_brackets() {
+        assert_eq!(
+            normalize_title("(C99) [社团 (作者)] 标题 [中国翻译] 【汉化组】 {DL版} 「附录」"),
+            "标题"
+        );
+        assert_eq!(
+            normalize_title("（同人）[作者] 标题（完）"),
+            normalize_title("[作者] 标题")
+        );
+    }
+
+    #[test]
+    fn normalize_title_ignores_case_and_whitespace() {
+        assert_eq!(normalize_title("  Some  Title\t"), "sometitle");
+        assert_eq!(normalize_title("[Author] SOME title"), "sometitle");
+    }
+
+    #[test]
+    fn normalize_title_keeps_content_when_title_is_all_brackets() {
+        assert_eq!(normalize_title("[作者] [标题]"), "作者标题");
+        assert_eq!(normalize_title("【Title】"), "title");
+    }
+
+    #[test]
+    fn normalize_title_handles_unbalanced_brackets() {
+        // 多余的右括号被忽略，未闭合的左括号吞掉其后的所有内容
+        assert_eq!(normalize_title("标题] 后记"), "标题后记");
+        assert_eq!(normalize_title("标题 [未闭合"), "标题");
+    }
+}
@@ -1,22 +1,62 @@
-use std::{io::Cursor, time::Duration};
+use std::{
+    collections::HashSet,
+    future::Future,
+    io::{Cursor, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
 use anyhow::{anyhow, Context};
 use bytes::Bytes;
 use image::ImageFormat;
-use parking_lot::RwLock;
-use reqwest::{Client, StatusCode};
+use parking_lot::{Mutex, RwLock};
+use reqwest::{
+    header::{HeaderMap, HeaderValue, ACCEPT_LANGUAGE, ACCEPT_RANGES, RANGE},
+    StatusCode,
+};
 use reqwest_middleware::ClientWithMiddleware;
 use reqwest_retry::{policies::ExponentialBackoff, Jitter, RetryTransientMiddleware};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use tauri::{AppHandle, Manager};
+use tauri_specta::Event;
+use tokio::{sync::Semaphore, task::JoinSet, time::sleep};
 
 use crate::{
+    bandwidth_stats,
     config::Config,
-    types::{Comic, DownloadFormat, GetFavoriteResult, ImgList, SearchResult, UserProfile},
+    cookie_store::CookieStore,
+    cover_cache,
+    errors::{ComicNotFoundError, ParseError, RateLimitedError, SessionExpiredError},
+    events::{AuthRequiredEvent, SessionRefreshedEvent},
+    extensions::AnyhowErrorToStringChain,
+    retry_strategy::{ApiRetryStrategy, RetryAfterMiddleware},
+    search_cache::{SearchCache, SearchQuery},
+    types::{
+        CheckInResp, CheckInResult, Comic, DownloadFormat, GetAllFavoritesResult,
+        GetFavoriteResult, ImgInImgList, ImgList, SearchResult, UserProfile,
+    },
+    utils,
 };
 
 const API_DOMAIN: &str = "www.wnacg03.cc";
+/// `get_all_favorites`同时获取的页数
+const FAVORITE_PAGE_CONCURRENCY: usize = 3;
+/// `get_comic_preview`最多获取的页数
+const MAX_PREVIEW_COUNT: u8 = 10;
+/// 同时下载的预览图片数量，以免滚动搜索结果时同时发出大量请求
+const PREVIEW_CONCURRENCY: usize = 2;
+/// 预览缩略图的最大边长
+const PREVIEW_MAX_SIZE: u32 = 400;
+
+/// 漫画详情页的url
+pub fn comic_url(comic_id: i64) -> String {
+    format!("https://{API_DOMAIN}/photos-index-aid-{comic_id}.html")
+}
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -28,24 +68,203 @@ pub struct LoginResp {
 #[derive(Clone)]
 pub struct WnacgClient {
     app: AppHandle,
-    api_client: ClientWithMiddleware,
-    img_client: ClientWithMiddleware,
-    cover_client: Client,
+    api_client: Arc<RwLock<ClientWithMiddleware>>,
+    img_client: Arc<RwLock<ClientWithMiddleware>>,
+    /// 图片请求的首字节耗时统计，用于粗略判断连接是否被复用
+    img_request_stats: Arc<ImgRequestStats>,
+    /// 已经发送过`AuthRequiredEvent`的cookie，避免同一个cookie重复提示
+    auth_required_cookie: Arc<Mutex<Option<String>>>,
+    /// 多个请求同时发现cookie过期时，只重新登录一次
+    relogin_lock: Arc<tokio::sync::Mutex<()>>,
+    search_cache: Arc<Mutex<SearchCache>>,
+    /// 限制同时下载的预览图片数量
+    preview_sem: Arc<Semaphore>,
 }
 
 impl WnacgClient {
     pub fn new(app: AppHandle) -> Self {
-        let api_client = create_api_client();
-        let img_client = create_img_client();
-        let cover_client = Client::new();
-        Self {
+        let config = app.state::<RwLock<Config>>().read().clone();
+        let api_client = create_api_client(&config);
+        let img_client = create_img_client(&config);
+        let client = Self {
             app,
-            api_client,
-            img_client,
-            cover_client,
+            api_client: Arc::new(RwLock::new(api_client)),
+            img_client: Arc::new(RwLock::new(img_client)),
+            img_request_stats: Arc::new(ImgRequestStats::default()),
+            auth_required_cookie: Arc::new(Mutex::new(None)),
+            relogin_lock: Arc::new(tokio::sync::Mutex::new(())),
+            search_cache: Arc::new(Mutex::new(SearchCache::default())),
+            preview_sem: Arc::new(Semaphore::new(PREVIEW_CONCURRENCY)),
+        };
+
+        tauri::async_runtime::spawn(client.clone().keep_session_alive_loop());
+
+        client
+    }
+
+    /// 开启`keep_session_alive`且已登录时，定期访问用户页面以保持会话
+    ///
+    /// 发现cookie已过期后，直到重新登录前都不再访问
+    async fn keep_session_alive_loop(self) {
+        loop {
+            let interval_min = self
+                .app
+                .state::<RwLock<Config>>()
+                .read()
+                .keep_session_alive_interval_min;
+            sleep(Duration::from_secs(interval_min.saturating_mul(60))).await;
+
+            let keep_session_alive = self.app.state::<RwLock<Config>>().read().keep_session_alive;
+            let cookie = CookieStore::get(&self.app);
+            // 未开启或未登录时不访问
+            let is_expired = self.auth_required_cookie.lock().as_ref() == Some(&cookie);
+            if !keep_session_alive || cookie.is_empty() || is_expired {
+                continue;
+            }
+
+            let Err(err) = self.get_user_profile().await else {
+                tracing::debug!("保持会话成功");
+                continue;
+            };
+            if err.downcast_ref::<SessionExpiredError>().is_some() {
+                tracing::warn!("保持会话时发现cookie已过期");
+            } else {
+                let err_title = "保持会话失败";
+                let string_chain = err.to_string_chain();
+                tracing::warn!(err_title, message = string_chain);
+            }
         }
     }
 
+    /// 配置变化后，用新的配置重建http client
+    pub fn on_config_changed(&self, config: &Config) {
+        *self.api_client.write() = create_api_client(config);
+        *self.img_client.write() = create_img_client(config);
+    }
+
+    fn api_client(&self) -> ClientWithMiddleware {
+        self.api_client.read().clone()
+    }
+
+    fn img_client(&self) -> ClientWithMiddleware {
+        self.img_client.read().clone()
+    }
+
+    /// 解析失败时将错误标记为`ParseError`，
+    /// 如果开启了`save_html_on_parse_error`，还会将html保存到文件，并在错误信息中附上文件路径
+    fn save_html_on_parse_error<T>(
+        &self,
+        type_name: &'static str,
+        html: &str,
+        parse_result: anyhow::Result<T>,
+    ) -> anyhow::Result<T> {
+        let err = match parse_result {
+            Ok(value) => return Ok(value),
+            // 未登录时的页面不是网站改版导致的，保持原有错误，由`with_relogin`处理
+            Err(err) if err.is::<SessionExpiredError>() => return Err(err),
+            Err(err) => err.context(ParseError { type_name }),
+        };
+        let save_html_on_parse_error = self
+            .app
+            .state::<RwLock<Config>>()
+            .read()
+            .save_html_on_parse_error;
+        if !save_html_on_parse_error {
+            return Err(err);
+        }
+        match save_html(&self.app, type_name, html) {
+            Ok(html_path) => Err(err.context(format!("解析失败的html已保存到`{html_path:?}`"))),
+            Err(save_err) => {
+                let err_title = "保存解析失败的html失败";
+                let string_chain = save_err.to_string_chain();
+                tracing::error!(err_title, message = string_chain);
+                Err(err)
+            }
+        }
+    }
+
+    /// 发现未登录时调用，同一个cookie只发送一次`AuthRequiredEvent`
+    fn on_auth_required(&self) {
+        let cookie = CookieStore::get(&self.app);
+        let mut auth_required_cookie = self.auth_required_cookie.lock();
+        if auth_required_cookie.as_ref() == Some(&cookie) {
+            return;
+        }
+        *auth_required_cookie = Some(cookie);
+        let _ = AuthRequiredEvent.emit(&self.app);
+    }
+
+    /// 执行需要cookie的请求，发现cookie已过期时，用保存的账号密码重新登录并重放请求
+    ///
+    /// 每次调用最多重新登录一次，没有保存账号密码或重新登录失败时返回原来的`SessionExpiredError`
+    async fn with_relogin<T, F, Fut>(&self, request: F) -> anyhow::Result<T>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = anyhow::Result<T>>,
+    {
+        let expired_cookie = CookieStore::get(&self.app);
+        let err = match request().await {
+            Err(err) if err.is::<SessionExpiredError>() => err,
+            result => return result,
+        };
+        match self.relogin(&expired_cookie).await {
+            Ok(true) => {}
+            Ok(false) => {
+                self.on_auth_required();
+                return Err(err);
+            }
+            Err(relogin_err) => {
+                let err_title = "cookie已过期，自动重新登录失败";
+                let string_chain = relogin_err.to_string_chain();
+                tracing::warn!(err_title, message = string_chain);
+                self.on_auth_required();
+                return Err(err);
+            }
+        }
+        request().await.inspect_err(|err| {
+            if err.is::<SessionExpiredError>() {
+                self.on_auth_required();
+            }
+        })
+    }
+
+    /// 用保存的账号密码重新登录，并保存新的cookie，没有保存账号密码时返回`false`
+    ///
+    /// 如果等待期间其他请求已经重新登录过(cookie与`expired_cookie`不同)，则直接返回`true`
+    async fn relogin(&self, expired_cookie: &str) -> anyhow::Result<bool> {
+        let _guard = self.relogin_lock.lock().await;
+        if CookieStore::get(&self.app) != expired_cookie {
+            return Ok(true);
+        }
+        let Some(credentials) = CookieStore::load_credentials(&self.app)? else {
+            return Ok(false);
+        };
+        let cookie = self
+            .login(&credentials.username, &credentials.password)
+            .await
+            .context("重新登录失败")?;
+        CookieStore::save(&self.app, cookie).context("保存新的cookie失败")?;
+        tracing::info!("cookie已过期，已用保存的账号密码自动重新登录");
+        let _ = SessionRefreshedEvent.emit(&self.app);
+        Ok(true)
+    }
+
+    /// 用`config`中的代理等设置访问网站，检查网站是否可以访问
+    pub async fn check_reachable(config: &Config) -> anyhow::Result<()> {
+        let http_resp = create_api_client(config)
+            .get(format!("https://{API_DOMAIN}/"))
+            .send()
+            .await
+            .context(format!("无法访问`{API_DOMAIN}`"))?;
+        let status = http_resp.status();
+        if !status.is_success() {
+            return Err(anyhow!(
+                "访问`{API_DOMAIN}`时返回了预料之外的状态码({status})"
+            ));
+        }
+        Ok(())
+    }
+
     pub async fn login(&self, username: &str, password: &str) -> anyhow::Result<String> {
         let form = json!({
             "login_name": username,
@@ -53,7 +272,7 @@ impl WnacgClient {
         });
         // 发送登录请求
         let http_resp = self
-            .api_client
+            .api_client()
             .post(format!("https://{API_DOMAIN}/users-check_login.html"))
             .header("referer", format!("https://{API_DOMAIN}/"))
             .form(&form)
@@ -87,10 +306,14 @@ impl WnacgClient {
     }
 
     pub async fn get_user_profile(&self) -> anyhow::Result<UserProfile> {
-        let cookie = self.app.state::<RwLock<Config>>().read().cookie.clone();
+        self.with_relogin(|| self.fetch_user_profile()).await
+    }
+
+    async fn fetch_user_profile(&self) -> anyhow::Result<UserProfile> {
+        let cookie = CookieStore::get(&self.app);
         // 发送获取用户信息请求
         let http_resp = self
-            .api_client
+            .api_client()
             .get(format!("https://{API_DOMAIN}/users.html"))
             .header("cookie", cookie)
             .header("referer", format!("https://{API_DOMAIN}/"))
@@ -103,15 +326,145 @@ impl WnacgClient {
             return Err(anyhow!("预料之外的状态码({status}): {body}"));
         }
         // 尝试将body解析为UserProfile
-        let user_profile = UserProfile::from_html(&body)
+        let user_profile = UserProfile::from_html(&body);
+        let user_profile = self
+            .save_html_on_parse_error("UserProfile", &body, user_profile)
             .context(format!("将body解析为UserProfile失败: {body}"))?;
         Ok(user_profile)
     }
 
+    pub async fn check_in(&self) -> anyhow::Result<CheckInResult> {
+        self.with_relogin(|| self.fetch_check_in()).await
+    }
+
+    async fn fetch_check_in(&self) -> anyhow::Result<CheckInResult> {
+        let cookie = CookieStore::get(&self.app);
+        // 发送签到请求
+        let http_resp = self
+            .api_client()
+            .post(format!("https://{API_DOMAIN}/users-sign.html"))
+            .header("cookie", cookie)
+            .header("referer", format!("https://{API_DOMAIN}/users.html"))
+            .send()
+            .await?;
+        // 检查http响应状态码
+        let status = http_resp.status();
+        let body = http_resp.text().await?;
+        if status != StatusCode::OK {
+            return Err(anyhow!("预料之外的状态码({status}): {body}"));
+        }
+        // 尝试将body解析为CheckInResp
+        let check_in_resp = serde_json::from_str::<CheckInResp>(&body)
+            .context(format!("将body解析为CheckInResp失败: {body}"))?;
+        let check_in_result = CheckInResult::from_resp(check_in_resp)?;
+        Ok(check_in_result)
+    }
+
     pub async fn search_by_keyword(
         &self,
         keyword: &str,
         page_num: i64,
+    ) -> anyhow::Result<SearchResult> {
+        let query = SearchQuery::Keyword(keyword.to_string());
+        self.search(query, page_num).await
+    }
+
+    pub async fn search_by_tag(
+        &self,
+        tag_name: &str,
+        page_num: i64,
+    ) -> anyhow::Result<SearchResult> {
+        let query = SearchQuery::Tag(tag_name.to_string());
+        self.search(query, page_num).await
+    }
+
+    /// 优先使用缓存(包括预取)的搜索结果，返回后在后台预取下一页
+    ///
+    /// 只用于用户主动发起的搜索，后台任务应该使用`fetch_search_result`
+    async fn search(&self, query: SearchQuery, page_num: i64) -> anyhow::Result<SearchResult> {
+        let cached_search_result = {
+            let mut search_cache = self.search_cache.lock();
+            search_cache.on_search(&query);
+            search_cache.get(&query, page_num)
+        };
+        let search_result = match cached_search_result {
+            Some(search_result) => search_result,
+            None => {
+                let search_result = self
+                    .fetch_search_result(&query, page_num)
+                    .await
+                    .inspect_err(|err| {
+                        if err.is::<RateLimitedError>() {
+                            self.search_cache.lock().on_rate_limited();
+                        }
+                    })?;
+                self.search_cache
+                    .lock()
+                    .put(&query, page_num, search_result.clone());
+                search_result
+            }
+        };
+        self.prefetch_next_page(query, &search_result);
+        Ok(search_result)
+    }
+
+    /// 在后台获取下一页并放入缓存，失败时不影响正常搜索
+    ///
+    /// 已经是最后一页、下一页已缓存、关闭了预取或刚被限流时不预取
+    fn prefetch_next_page(&self, query: SearchQuery, search_result: &SearchResult) {
+        let prefetch_search_results = self
+            .app
+            .state::<RwLock<Config>>()
+            .read()
+            .prefetch_search_results;
+        let Some(next_page) = search_result.next_page() else {
+            return;
+        };
+        let mut search_cache = self.search_cache.lock();
+        let should_skip = !prefetch_search_results
+            || search_cache.is_rate_limited()
+            || search_cache.contains(&query, next_page);
+        if should_skip {
+            return;
+        }
+        let wnacg_client = self.clone();
+        let prefetch_task = tauri::async_runtime::spawn(async move {
+            match wnacg_client.fetch_search_result(&query, next_page).await {
+                Ok(search_result) => {
+                    let mut search_cache = wnacg_client.search_cache.lock();
+                    search_cache.put(&query, next_page, search_result);
+                }
+                Err(err) => {
+                    if err.is::<RateLimitedError>() {
+                        wnacg_client.search_cache.lock().on_rate_limited();
+                    }
+                    let err_title = format!("预取搜索结果第{next_page}页失败");
+                    let string_chain = err.to_string_chain();
+                    tracing::debug!(err_title, message = string_chain);
+                }
+            }
+        });
+        search_cache.set_prefetch_task(prefetch_task);
+    }
+
+    /// 直接请求搜索结果，不读写缓存，也不预取下一页
+    ///
+    /// 订阅检查、批量下载等后台任务用这个，以免清空用户的缓存或打断用户的预取
+    pub async fn fetch_search_result(
+        &self,
+        query: &SearchQuery,
+        page_num: i64,
+    ) -> anyhow::Result<SearchResult> {
+        match query {
+            SearchQuery::Keyword(keyword) => self.fetch_search_by_keyword(keyword, page_num).await,
+            SearchQuery::Tag(tag_name) => self.fetch_search_by_tag(tag_name, page_num).await,
+        }
+    }
+
+    async fn fetch_search_by_keyword(
+        &self,
+        keyword: &str,
+        page_num: i64,
     ) -> anyhow::Result<SearchResult> {
         let params = json!({
             "q": keyword,
@@ -121,42 +474,52 @@ impl WnacgClient {
             "p": page_num,
         });
         let http_resp = self
-            .api_client
+            .api_client()
             .get(format!("https://{API_DOMAIN}/search/index.php"))
             .header("referer", format!("https://{API_DOMAIN}/"))
             .query(&params)
             .send()
             .await?;
         let status = http_resp.status();
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            return Err(RateLimitedError.into());
+        }
         let body = http_resp.text().await?;
         if status != StatusCode::OK {
             return Err(anyhow!("预料之外的状态码({status}): {body}"));
         }
         // 尝试将body解析为SearchResult
-        let search_result = SearchResult::from_html(&self.app, &body, false)
+        let search_result = SearchResult::from_html(&self.app, &body, false);
+        let search_result = self
+            .save_html_on_parse_error("SearchResult", &body, search_result)
             .context(format!("将html解析为SearchResult失败: {body}"))?;
         Ok(search_result)
     }
 
-    pub async fn search_by_tag(
+    async fn fetch_search_by_tag(
         &self,
         tag_name: &str,
         page_num: i64,
     ) -> anyhow::Result<SearchResult> {
         let url = format!("https://{API_DOMAIN}/albums-index-page-{page_num}-tag-{tag_name}.html");
         let http_resp = self
-            .api_client
+            .api_client()
             .get(url)
             .header("referer", format!("https://{API_DOMAIN}/"))
             .send()
             .await?;
         let status = http_resp.status();
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            return Err(RateLimitedError.into());
+        }
         let body = http_resp.text().await?;
         if status != StatusCode::OK {
             return Err(anyhow!("预料之外的状态码({status}): {body}"));
         }
         // 尝试将body解析为SearchResult
-        let search_result = SearchResult::from_html(&self.app, &body, true)
+        let search_result = SearchResult::from_html(&self.app, &body, true);
+        let search_result = self
+            .save_html_on_parse_error("SearchResult", &body, search_result)
             .context(format!("将html解析为SearchResult失败: {body}"))?;
         Ok(search_result)
     }
@@ -164,14 +527,19 @@ impl WnacgClient {
     pub async fn get_img_list(&self, id: i64) -> anyhow::Result<ImgList> {
         let url = format!("https://{API_DOMAIN}/photos-gallery-aid-{id}.html");
         let http_resp = self
-            .api_client
+            .api_client()
             .get(url)
             .header("referer", format!("https://{API_DOMAIN}/"))
             .send()
             .await?;
         let status = http_resp.status();
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            return Err(RateLimitedError.into());
+        }
         let body = http_resp.text().await?;
-        if status != StatusCode::OK {
+        if status == StatusCode::NOT_FOUND {
+            return Err(ComicNotFoundError { id }.into());
+        } else if status != StatusCode::OK {
             return Err(anyhow!("预料之外的状态码({status}): {body}"));
         }
         // 找到包含`imglist`的行
@@ -186,34 +554,55 @@ impl WnacgClient {
         let end = img_list_line
             .rfind(']')
             .context("没有在`imglist`行中找到`]`")?;
-        // 将 JSON 部分提取出来，并转为合法的 JSON 字符串
-        let json_str = &img_list_line[start..=end]
-            .replace("url:", "\"url\":")
-            .replace("caption:", "\"caption\":")
-            .replace("fast_img_host+", "")
-            .replace("\\\"", "\"");
-        // 将 JSON 字符串解析为 ImgList
-        let img_list = serde_json::from_str::<ImgList>(json_str)
-            .context(format!("将JSON字符串解析为ImgList失败: {json_str}"))?;
+        // 将 js 数组部分提取出来，解析为 ImgList
+        let img_list_js = &img_list_line[start..=end];
+        let (placeholder_img_patterns, img_host_overrides) = {
+            let config = self.app.state::<RwLock<Config>>();
+            let config = config.read();
+            (
+                config.placeholder_img_patterns.clone(),
+                config.img_host_overrides.clone(),
+            )
+        };
+        // 网页中声明的图片域名变量，配置中指定的值优先
+        let mut host_vars = ImgList::host_vars_from_html(&body);
+        host_vars.extend(img_host_overrides);
+        let mut img_list = ImgList::from_js(img_list_js, &host_vars)
+            .context(format!("将js数组解析为ImgList失败: {img_list_js}"))?;
+        // 去重并过滤掉占位图片
+        img_list
+            .sanitize(&placeholder_img_patterns)
+            .context(format!("清洗ImgList失败: {img_list_js}"))?;
         Ok(img_list)
     }
 
     pub async fn get_comic(&self, id: i64) -> anyhow::Result<Comic> {
         let http_resp = self
-            .api_client
+            .api_client()
             .get(format!("https://{API_DOMAIN}/photos-index-aid-{id}.html"))
             .header("referer", format!("https://{API_DOMAIN}/"))
             .send()
             .await?;
         let status = http_resp.status();
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            return Err(RateLimitedError.into());
+        }
         let body = http_resp.text().await?;
-        if status != StatusCode::OK {
+        if status == StatusCode::NOT_FOUND {
+            return Err(ComicNotFoundError { id }.into());
+        } else if status != StatusCode::OK {
             return Err(anyhow!("预料之外的状态码({status}): {body}"));
         }
+        // 漫画被删除时，网站也可能返回200
+        if Comic::is_not_found_page(&body) {
+            return Err(ComicNotFoundError { id }.into());
+        }
         // TODO: 可以并发获取body和img_list
         let img_list = self.get_img_list(id).await?;
         // 尝试将body解析为Comic
-        let comic = Comic::from_html(&self.app, &body, img_list)
+        let comic = Comic::from_html(&self.app, &body, img_list);
+        let comic = self
+            .save_html_on_parse_error("Comic", &body, comic)
             .context(format!("将body和解析为Comic失败: {body}"))?;
 
         Ok(comic)
@@ -224,11 +613,20 @@ impl WnacgClient {
         shelf_id: i64,
         page_num: i64,
     ) -> anyhow::Result<GetFavoriteResult> {
-        let cookie = self.app.state::<RwLock<Config>>().read().cookie.clone();
+        self.with_relogin(|| self.fetch_favorite(shelf_id, page_num))
+            .await
+    }
+
+    async fn fetch_favorite(
+        &self,
+        shelf_id: i64,
+        page_num: i64,
+    ) -> anyhow::Result<GetFavoriteResult> {
+        let cookie = CookieStore::get(&self.app);
         // 发送获取收藏夹请求
         let url = format!("https://{API_DOMAIN}/users-users_fav-page-{page_num}-c-{shelf_id}.html");
         let http_resp = self
-            .api_client
+            .api_client()
             .get(url)
             .header("cookie", cookie)
             .header("referer", format!("https://{API_DOMAIN}/"))
@@ -241,37 +639,131 @@ impl WnacgClient {
             return Err(anyhow!("预料之外的状态码({status}): {body}"));
         }
         // 尝试将body解析为GetFavoriteResult
-        let get_favorite_result = GetFavoriteResult::from_html(&self.app, &body)
+        let get_favorite_result = GetFavoriteResult::from_html(&self.app, &body);
+        let get_favorite_result = self
+            .save_html_on_parse_error("GetFavoriteResult", &body, get_favorite_result)
             .context(format!("将body解析为GetFavoriteResult失败: {body}"))?;
         Ok(get_favorite_result)
     }
 
-    pub async fn get_img_data_and_format(&self, url: &str) -> anyhow::Result<(Bytes, ImageFormat)> {
-        // 发送下载图片请求
-        let http_resp = self
-            .img_client
-            .get(url)
-            .header("referer", format!("https://{API_DOMAIN}/"))
-            .send()
-            .await?;
-        // 检查http响应状态码
-        let status = http_resp.status();
-        if status == StatusCode::TOO_MANY_REQUESTS {
-            return Err(anyhow!("IP被封，请在更多设置中减少并发数或设置下载完成后的休息时间，以此降低下载速度，稍后再试"));
-        } else if status != StatusCode::OK {
-            let body = http_resp.text().await?;
-            return Err(anyhow!("预料之外的状态码({status}): {body}"));
+    /// 获取书架的所有页，按页码顺序合并，并按漫画id去重(保留第一次出现的)
+    ///
+    /// 翻页期间书架可能被修改，漫画会在页之间移动，导致重复或遗漏。
+    /// 如果最后一页的总页数与第一页不同，会重新获取一次新的最后一页，并在`warnings`中说明
+    pub async fn get_all_favorites(&self, shelf_id: i64) -> anyhow::Result<GetAllFavoritesResult> {
+        let first_page = self
+            .get_favorite(shelf_id, 1)
+            .await
+            .context(format!("获取书架`{shelf_id}`的第1页失败"))?;
+        let first_total_page = first_page.total_page;
+
+        let sem = Arc::new(Semaphore::new(FAVORITE_PAGE_CONCURRENCY));
+        let mut join_set = JoinSet::new();
+        for page_num in 2..=first_total_page {
+            let wnacg_client = self.clone();
+            let sem = sem.clone();
+            join_set.spawn(async move {
+                let _permit = sem.acquire().await?;
+                wnacg_client
+                    .get_favorite(shelf_id, page_num)
+                    .await
+                    .context(format!("获取书架`{shelf_id}`的第{page_num}页失败"))
+            });
         }
-        // 获取 resp headers 的 content-type 字段
-        let content_type = http_resp
-            .headers()
-            .get("content-type")
-            .ok_or(anyhow!("响应中没有content-type字段"))?
-            .to_str()
-            .context("响应中的content-type字段不是utf-8字符串")?
-            .to_string();
-        // 获取图片数据
-        let image_data = http_resp.bytes().await?;
+        let mut pages = join_set
+            .join_all()
+            .await
+            .into_iter()
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        pages.sort_by_key(|page| page.current_page);
+        pages.insert(0, first_page);
+
+        let mut warnings = Vec::new();
+        let last_total_page = pages
+            .last()
+            .map_or(first_total_page, |page| page.total_page);
+        if last_total_page != first_total_page {
+            warnings.push(format!(
+                "获取期间书架被修改，总页数从{first_total_page}变为{last_total_page}，已重新获取第{last_total_page}页"
+            ));
+            let final_page =
+                self.get_favorite(shelf_id, last_total_page)
+                    .await
+                    .context(format!(
+                        "重新获取书架`{shelf_id}`的第{last_total_page}页失败"
+                    ))?;
+            if final_page.total_page > first_total_page.max(last_total_page) {
+                warnings.push(format!(
+                    "书架仍在变化，总页数变为{}，部分漫画可能没有获取到",
+                    final_page.total_page
+                ));
+            }
+            pages.push(final_page);
+        }
+
+        let mut seen_ids = HashSet::new();
+        let mut duplicate_count = 0;
+        let comics = pages
+            .into_iter()
+            .flat_map(|page| page.comics)
+            .filter(|comic| {
+                let is_first = seen_ids.insert(comic.id);
+                if !is_first {
+                    duplicate_count += 1;
+                }
+                is_first
+            })
+            .collect();
+        if duplicate_count > 0 {
+            warnings.push(format!(
+                "有{duplicate_count}部漫画在多页中重复出现，可能是获取期间书架被修改，已去重"
+            ));
+        }
+        for warning in &warnings {
+            tracing::warn!(shelf_id, "{warning}");
+        }
+
+        Ok(GetAllFavoritesResult { comics, warnings })
+    }
+
+    /// 图片请求的referer，设置了`image_referer`时使用它，否则使用漫画详情页(有漫画id时)或网站首页
+    ///
+    /// 部分图片服务器有防盗链，要求referer为漫画详情页
+    fn img_referer(&self, comic_id: Option<i64>) -> String {
+        let image_referer = self
+            .app
+            .state::<RwLock<Config>>()
+            .read()
+            .image_referer
+            .clone();
+        if !image_referer.is_empty() {
+            return image_referer;
+        }
+        match comic_id {
+            Some(comic_id) => comic_url(comic_id),
+            None => format!("https://{API_DOMAIN}/"),
+        }
+    }
+
+    /// 下载图片并转换为`download_format`
+    /// `partial_path`不为`None`时，服务器支持断点续传的图片会边下载边写入`partial_path`，
+    /// 下载中断后再次调用时从已下载的部分继续下载，下载完成后删除`partial_path`
+    pub async fn get_img_data_and_format(
+        &self,
+        url: &str,
+        comic_id: i64,
+        download_format: DownloadFormat,
+        partial_path: Option<&Path>,
+    ) -> anyhow::Result<(Bytes, ImageFormat)> {
+        let (image_data, content_type) = self.get_img_data(url, comic_id, partial_path).await?;
+        // 保存原图且开启了`raw_passthrough`时，不看content-type，也不解码图片，只按文件头判断格式
+        let raw_passthrough = self.app.state::<RwLock<Config>>().read().raw_passthrough;
+        if download_format == DownloadFormat::Original && raw_passthrough {
+            let original_format =
+                image::guess_format(&image_data).context("无法根据文件头判断图片的格式")?;
+            return Ok((image_data, original_format));
+        }
+        let content_type = content_type.ok_or(anyhow!("响应中没有content-type字段"))?;
         // 确定原始图片格式
         let original_format = match content_type.as_str() {
             "image/jpeg" => ImageFormat::Jpeg,
@@ -280,7 +772,6 @@ impl WnacgClient {
             _ => return Err(anyhow!("原图出现了意料之外的格式: {content_type}")),
         };
         // 确定目标格式
-        let download_format = self.app.state::<RwLock<Config>>().read().download_format;
         let target_format = match download_format {
             DownloadFormat::Jpeg => ImageFormat::Jpeg,
             DownloadFormat::Png => ImageFormat::Png,
@@ -307,15 +798,185 @@ impl WnacgClient {
         .context(format!(
             "将`{original_format:?}`转换为`{target_format:?}`失败"
         ))?;
+        // 编码器在极端输入下可能输出空的或不完整的数据，保存前确认能读出相同的尺寸
+        check_converted_img(&converted_data, target_format, img.width(), img.height()).context(
+            format!("将`{original_format:?}`转换为`{target_format:?}`的结果不正确"),
+        )?;
 
         Ok((Bytes::from(converted_data), target_format))
     }
 
+    /// 下载图片的原始数据，返回数据和响应中的content-type
+    ///
+    /// `partial_path`中已有数据时发送`Range`请求头，服务器返回206时接着已有的数据继续下载，返回200时重新下载  
+    /// 流量统计记录的是实际收到的字节数，继续下载时不会重复计入已下载的部分
+    async fn get_img_data(
+        &self,
+        url: &str,
+        comic_id: i64,
+        partial_path: Option<&Path>,
+    ) -> anyhow::Result<(Bytes, Option<String>)> {
+        let downloaded_len = partial_path
+            .and_then(|path| path.metadata().ok())
+            .map_or(0, |metadata| metadata.len());
+        let mut request = self
+            .img_client()
+            .get(url)
+            .header("referer", self.img_referer(Some(comic_id)));
+        if downloaded_len > 0 {
+            request = request.header(RANGE, format!("bytes={downloaded_len}-"));
+        }
+        // 发送下载图片请求
+        let request_start = Instant::now();
+        let mut http_resp = request.send().await?;
+        self.img_request_stats.record(request_start.elapsed());
+        // 检查http响应状态码
+        let status = http_resp.status();
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            return Err(RateLimitedError.into());
+        } else if status == StatusCode::RANGE_NOT_SATISFIABLE {
+            // 已下载的部分有问题，删掉后下次重新下载
+            if let Some(partial_path) = partial_path {
+                let _ = std::fs::remove_file(partial_path);
+            }
+            return Err(anyhow!("服务器无法从第`{downloaded_len}`个字节继续下载"));
+        } else if status != StatusCode::OK && status != StatusCode::PARTIAL_CONTENT {
+            let body = http_resp.text().await?;
+            return Err(anyhow!("预料之外的状态码({status}): {body}"));
+        }
+        // 获取 resp headers 的 content-type 字段
+        let content_type = match http_resp.headers().get("content-type") {
+            Some(content_type) => Some(
+                content_type
+                    .to_str()
+                    .context("响应中的content-type字段不是utf-8字符串")?
+                    .to_string(),
+            ),
+            None => None,
+        };
+        // 返回206才是接着已有的数据继续下载，返回200说明服务器忽略了`Range`，需要从头下载
+        let is_resumed = status == StatusCode::PARTIAL_CONTENT && downloaded_len > 0;
+        let accept_ranges = http_resp
+            .headers()
+            .get(ACCEPT_RANGES)
+            .is_some_and(|value| value.as_bytes() == b"bytes");
+        let partial_path = match partial_path {
+            Some(partial_path) if is_resumed || accept_ranges => partial_path,
+            // 服务器不支持断点续传，直接在内存中下载
+            _ => {
+                if let Some(partial_path) = partial_path {
+                    let _ = std::fs::remove_file(partial_path);
+                }
+                let mut image_data = Vec::new();
+                while let Some(chunk) = http_resp.chunk().await? {
+                    bandwidth_stats::record(chunk.len() as u64);
+                    image_data.extend_from_slice(&chunk);
+                }
+                return Ok((Bytes::from(image_data), content_type));
+            }
+        };
+        // 边下载边写入`partial_path`，下载中断时已下载的部分会保留下来
+        let mut partial_file = std::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(is_resumed)
+            .truncate(!is_resumed)
+            .open(partial_path)
+            .context(format!("打开文件`{partial_path:?}`失败"))?;
+        while let Some(chunk) = http_resp.chunk().await? {
+            bandwidth_stats::record(chunk.len() as u64);
+            partial_file
+                .write_all(&chunk)
+                .context(format!("写入文件`{partial_path:?}`失败"))?;
+        }
+        drop(partial_file);
+        let image_data =
+            std::fs::read(partial_path).context(format!("读取文件`{partial_path:?}`失败"))?;
+        std::fs::remove_file(partial_path).context(format!("删除文件`{partial_path:?}`失败"))?;
+        if is_resumed {
+            tracing::debug!(url, "从第`{downloaded_len}`个字节继续下载图片成功");
+        }
+        Ok((Bytes::from(image_data), content_type))
+    }
+
+    /// 获取漫画前`count`页的缩略图(jpeg，最大边长为`PREVIEW_MAX_SIZE`)，用于下载前预览
+    ///
+    /// `img_list`为`None`时重新获取图片列表，`count`最大为`MAX_PREVIEW_COUNT`  
+    /// 缩略图缓存在封面缓存目录中，返回的是缓存文件的路径，前端可以通过asset协议访问
+    pub async fn get_comic_preview(
+        &self,
+        comic_id: i64,
+        img_list: Option<ImgList>,
+        count: u8,
+    ) -> anyhow::Result<Vec<PathBuf>> {
+        let img_list = match img_list {
+            Some(img_list) => img_list,
+            None => self.get_img_list(comic_id).await?,
+        };
+        let img_urls = img_list
+            .sorted_by_caption()
+            .into_iter()
+            .take(usize::from(count.min(MAX_PREVIEW_COUNT)))
+            .map(ImgInImgList::full_url)
+            .collect::<Vec<_>>();
+
+        let mut preview_paths = Vec::with_capacity(img_urls.len());
+        for img_url in img_urls {
+            let preview_path = self
+                .get_preview_path(&img_url, comic_id)
+                .await
+                .context(format!("获取图片`{img_url}`的缩略图失败"))?;
+            preview_paths.push(preview_path);
+        }
+        Ok(preview_paths)
+    }
+
+    /// 返回缩略图在缓存中的路径，缓存中没有时先下载图片、生成缩略图并写入缓存
+    async fn get_preview_path(&self, img_url: &str, comic_id: i64) -> anyhow::Result<PathBuf> {
+        // 与封面共用缓存目录，加上前缀以免与封面的缓存冲突
+        let cache_key = format!("preview:{img_url}");
+        if let Some(preview_path) = cover_cache::get_path(&self.app, &cache_key) {
+            return Ok(preview_path);
+        }
+
+        let _permit = self.preview_sem.acquire().await?;
+        let http_resp = self
+            .img_client()
+            .get(img_url)
+            .header("referer", self.img_referer(Some(comic_id)))
+            .send()
+            .await?;
+        let status = http_resp.status();
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            return Err(RateLimitedError.into());
+        } else if status != StatusCode::OK {
+            let body = http_resp.text().await?;
+            return Err(anyhow!("预料之外的状态码({status}): {body}"));
+        }
+        let img_data = http_resp.bytes().await?;
+        bandwidth_stats::record(img_data.len() as u64);
+        // 解码和缩放图片是耗时的同步操作，放到专门的线程中执行
+        let preview_data =
+            tauri::async_runtime::spawn_blocking(move || create_thumbnail(&img_data))
+                .await
+                .map_err(anyhow::Error::from)
+                .and_then(|result| result)?;
+
+        let preview_path = cover_cache::put_and_get_path(&self.app, &cache_key, &preview_data)
+            .context("将缩略图写入缓存失败")?;
+        Ok(preview_path)
+    }
+
+    /// 优先从缓存中读取封面，缓存中没有时再下载并写入缓存
     pub async fn get_cover_data(&self, cover_url: &str) -> anyhow::Result<Bytes> {
+        if let Some(cover_data) = cover_cache::get(&self.app, cover_url) {
+            return Ok(cover_data);
+        }
+
         let http_resp = self
-            .cover_client
+            .img_client()
             .get(cover_url)
-            .header("referer", format!("https://{API_DOMAIN}/"))
+            .header("referer", self.img_referer(None))
             .send()
             .await?;
         let status = http_resp.status();
@@ -324,36 +985,229 @@ impl WnacgClient {
             return Err(anyhow!("预料之外的状态码({status}): {body}"));
         }
         let cover_data = http_resp.bytes().await?;
+        bandwidth_stats::record(cover_data.len() as u64);
+
+        if let Err(err) = cover_cache::put(&self.app, cover_url, &cover_data) {
+            let err_title = format!("缓存封面`{cover_url}`失败");
+            let string_chain = err.to_string_chain();
+            tracing::warn!(err_title, message = string_chain);
+        }
+
         Ok(cover_data)
     }
 }
 
-fn create_api_client() -> ClientWithMiddleware {
+/// 图片请求从发送到收到响应头的耗时统计
+///
+/// 复用连接时不需要TCP和TLS握手，耗时明显更短，可以据此粗略判断连接池是否生效
+#[derive(Default)]
+struct ImgRequestStats {
+    request_count: AtomicU64,
+    total_millis: AtomicU64,
+}
+
+impl ImgRequestStats {
+    /// 每统计这么多个请求输出一次日志
+    const LOG_INTERVAL: u64 = 50;
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn record(&self, elapsed: Duration) {
+        let total_millis = self
+            .total_millis
+            .fetch_add(elapsed.as_millis() as u64, Ordering::Relaxed)
+            + elapsed.as_millis() as u64;
+        let request_count = self.request_count.fetch_add(1, Ordering::Relaxed) + 1;
+        if request_count < Self::LOG_INTERVAL {
+            return;
+        }
+        // 重新开始统计，并发时可能漏掉少量请求，不影响粗略判断
+        self.request_count.store(0, Ordering::Relaxed);
+        self.total_millis.store(0, Ordering::Relaxed);
+        let average_millis = total_millis / request_count;
+        tracing::debug!(
+            "最近`{request_count}`个图片请求收到响应头的平均耗时为`{average_millis}ms`"
+        );
+    }
+}
+
+/// 检查转换后的图片数据不为空，且能读出与原图相同的尺寸
+fn check_converted_img(
+    converted_data: &[u8],
+    format: ImageFormat,
+    width: u32,
+    height: u32,
+) -> anyhow::Result<()> {
+    if converted_data.is_empty() {
+        return Err(anyhow!("转换后的图片数据为空"));
+    }
+    let (converted_width, converted_height) =
+        image::ImageReader::with_format(Cursor::new(converted_data), format)
+            .into_dimensions()
+            .context("无法读取转换后的图片的尺寸")?;
+    if (converted_width, converted_height) != (width, height) {
+        return Err(anyhow!(
+            "转换后的图片尺寸`{converted_width}x{converted_height}`与原图`{width}x{height}`不一致"
+        ));
+    }
+    Ok(())
+}
+
+/// 将图片缩小到最大边长为`PREVIEW_MAX_SIZE`，编码为jpeg
+fn create_thumbnail(img_data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let img = image::load_from_memory(img_data).context("将图片数据转换为DynamicImage失败")?;
+    let thumbnail = img.thumbnail(PREVIEW_MAX_SIZE, PREVIEW_MAX_SIZE).to_rgb8();
+    let mut thumbnail_data = Vec::new();
+    thumbnail
+        .write_to(&mut Cursor::new(&mut thumbnail_data), ImageFormat::Jpeg)
+        .context("将缩略图编码为jpeg失败")?;
+    Ok(thumbnail_data)
+}
+
+fn create_api_client(config: &Config) -> ClientWithMiddleware {
+    let retry_budget = Duration::from_secs(config.api_retry_budget_sec);
     let retry_policy = ExponentialBackoff::builder()
         .base(1) // 指数为1，保证重试间隔为1秒不变
         .jitter(Jitter::Bounded) // 重试间隔在1秒左右波动
-        .build_with_total_retry_duration(Duration::from_secs(5)); // 重试总时长为5秒
+        .build_with_total_retry_duration(retry_budget);
 
-    let client = reqwest::ClientBuilder::new()
+    let mut headers = HeaderMap::new();
+    // 固定响应的语言，保证html中的文本与解析时用的前缀一致
+    let accept_language = HeaderValue::from_str(&config.accept_language)
+        .unwrap_or_else(|_| HeaderValue::from_static("zh-TW,zh;q=0.9"));
+    headers.insert(ACCEPT_LANGUAGE, accept_language);
+
+    let mut builder = reqwest::ClientBuilder::new()
         .use_rustls_tls()
-        .timeout(Duration::from_secs(3)) // 每个请求超过3秒就超时
-        .build()
-        .unwrap();
+        .default_headers(headers)
+        .timeout(Duration::from_secs(config.api_timeout_sec));
+    if let Some(proxy) = create_proxy(&config.proxy_url) {
+        builder = builder.proxy(proxy);
+    }
+    let client = builder.build().unwrap();
 
     reqwest_middleware::ClientBuilder::new(client)
-        .with(RetryTransientMiddleware::new_with_policy(retry_policy))
+        .with(RetryTransientMiddleware::new_with_policy_and_strategy(
+            retry_policy,
+            ApiRetryStrategy,
+        ))
+        .with(RetryAfterMiddleware {
+            budget: retry_budget,
+        })
         .build()
 }
 
-fn create_img_client() -> ClientWithMiddleware {
+fn create_img_client(config: &Config) -> ClientWithMiddleware {
     let retry_policy = ExponentialBackoff::builder().build_with_max_retries(3);
+    // 图片域名经常轮换，保留足够多的空闲连接，避免每张图片都重新进行TLS握手
+    let pool_max_idle_per_host = match config.img_pool_max_idle_per_host {
+        0 => config.img_concurrency,
+        size => size,
+    };
 
-    let client = reqwest::ClientBuilder::new()
+    let mut builder = reqwest::ClientBuilder::new()
         .use_rustls_tls()
-        .build()
-        .unwrap();
+        .pool_max_idle_per_host(pool_max_idle_per_host)
+        .pool_idle_timeout(Duration::from_secs(90))
+        .tcp_keepalive(Duration::from_secs(60))
+        .timeout(Duration::from_secs(config.img_timeout_sec));
+    if let Some(proxy) = create_proxy(&config.proxy_url) {
+        builder = builder.proxy(proxy);
+    }
+    let client = builder.build().unwrap();
 
     reqwest_middleware::ClientBuilder::new(client)
         .with(RetryTransientMiddleware::new_with_policy(retry_policy))
         .build()
 }
+
+/// 将html保存到数据目录中的`解析失败的html`目录，返回文件路径
+fn save_html(app: &AppHandle, type_name: &str, html: &str) -> anyhow::Result<PathBuf> {
+    let html_dir = utils::data_dir(app)?.join("解析失败的html");
+    std::fs::create_dir_all(&html_dir).context(format!("创建目录`{html_dir:?}`失败"))?;
+
+    let timestamp = chrono::Local::now().format("%Y%m%d-%H%M%S%.3f");
+    let html_path = html_dir.join(format!("{type_name}-{timestamp}.html"));
+    std::fs::write(&html_path, html).context(format!("写入文件`{html_path:?}`失败"))?;
+    Ok(html_path)
+}
+
+/// 根据代理地址创建代理，地址为空或无效时不使用代理
+///
+/// reqwest根据协议区分DNS解析方式：`socks5://`在本地解析域名，`socks5h://`交给代理服务器解析域名
+fn create_proxy(proxy_url: &str) -> Option<reqwest::Proxy> {
+    if proxy_url.is_empty() {
+        return None;
+    }
+    match reqwest::Proxy::all(proxy_url).map_err(anyhow::Error::from) {
+        Ok(proxy) => Some(proxy),
+        Err(err) => {
+            let err_title = format!("代理地址`{proxy_url}`无效，将不使用代理");
+            let string_chain = err.to_string_chain();
+            tracing::error!(err_title, message = string_chain);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn create_proxy_accepts_socks5_and_socks5h() {
+        for proxy_url in ["socks5://127.0.0.1:1080", "socks5h://127.0.0.1:1080"] {
+            let proxy = create_proxy(proxy_url).expect(proxy_url);
+            let client = reqwest::Client::builder().proxy(proxy).build();
+            assert!(client.is_ok(), "{proxy_url}");
+        }
+    }
+
+    #[test]
+    fn create_proxy_returns_none_for_empty_url() {
+        assert!(create_proxy("").is_none());
+    }
+
+    /// 按`get_img_data_and_format`中的方式把1x1的图片编码为`format`
+    fn encode_1x1(format: ImageFormat) -> Vec<u8> {
+        let img = image::DynamicImage::new_rgba8(1, 1);
+        let mut data = Vec::new();
+        match format {
+            ImageFormat::Jpeg => img.to_rgb8().write_to(&mut Cursor::new(&mut data), format),
+            _ => img.to_rgba8().write_to(&mut Cursor::new(&mut data), format),
+        }
+        .unwrap();
+        data
+    }
+
+    #[test]
+    fn check_converted_img_accepts_1x1_img() {
+        for format in [ImageFormat::Jpeg, ImageFormat::Png, ImageFormat::WebP] {
+            let data = encode_1x1(format);
+            assert!(
+                check_converted_img(&data, format, 1, 1).is_ok(),
+                "{format:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn check_converted_img_rejects_degenerate_data() {
+        for format in [ImageFormat::Jpeg, ImageFormat::Png, ImageFormat::WebP] {
+            let data = encode_1x1(format);
+            assert!(
+                check_converted_img(&[], format, 1, 1).is_err(),
+                "{format:?}"
+            );
+            // 只剩文件头的一部分，读不出尺寸
+            assert!(
+                check_converted_img(&data[..4], format, 1, 1).is_err(),
+                "{format:?}"
+            );
+            // 尺寸与原图不一致
+            assert!(
+                check_converted_img(&data, format, 2, 1).is_err(),
+                "{format:?}"
+            );
+        }
+    }
+}